@@ -1,4 +1,49 @@
 pub const BLOCK_SIZE: usize = 256;
 pub const CONFIG_KEY: &[u8] = b"config";
+pub const EXIT_ORDERS_PREFIX: &[u8] = b"exit_orders";
+// Reported by the ContractInfo query so frontends can adapt to what this build supports
+pub const FEATURES: &[&str] = &[
+    "callback",
+    "deadline",
+    "dca_subscriptions",
+    "deposit_target",
+    "exit_orders",
+    "hide_amounts",
+    "intermediate_token",
+    "keeper_harvest",
+    "limit_orders",
+    "liquidity_migration",
+    "multi_pool",
+    "price_impact_guard",
+    "recipient",
+    "reward_zap",
+    "sienna_swap",
+    "simulate_zap",
+    "stable_swap",
+    "swap_router_allowlist",
+    "vault",
+    "zap_history",
+];
+// Most orders, exit orders or subscriptions a keeper can process in one message
+pub const MAX_BATCH_SIZE: usize = 10;
+// Allowances below this are reported as insufficient as providing liquidity draws them down
+pub const MINIMUM_PAIR_ALLOWANCE: u128 = u128::MAX / 2;
 pub const MOCK_AMOUNT: u128 = 1_000_000_000_000;
 pub const MOCK_BUTT_SWBTC_LP_ADDRESS: &str = "mock-butt-swbtc-lp-address";
+pub const ORDERS_PREFIX: &[u8] = b"orders";
+pub const POOLS_PREFIX: &[u8] = b"pools";
+pub const REGISTERED_TOKENS_LIST_PREFIX: &[u8] = b"registered_tokens_list";
+pub const REGISTERED_TOKENS_PREFIX: &[u8] = b"registered_tokens";
+pub const SESSION_KEY: &[u8] = b"session";
+pub const STORAGE_VERSION: u32 = 3;
+pub const STORAGE_VERSION_KEY: &[u8] = b"storage_version";
+pub const SUCCESSOR_KEY: &[u8] = b"successor";
+pub const SUBSCRIPTIONS_PREFIX: &[u8] = b"subscriptions";
+pub const SWAP_ROUTERS_KEY: &[u8] = b"swap_routers";
+pub const TOKEN_VIEWING_KEYS_PREFIX: &[u8] = b"token_viewing_keys";
+pub const USER_EXIT_ORDERS_PREFIX: &[u8] = b"user_exit_orders";
+pub const USER_ORDERS_PREFIX: &[u8] = b"user_orders";
+pub const USER_SUBSCRIPTIONS_PREFIX: &[u8] = b"user_subscriptions";
+pub const VAULT_KEY: &[u8] = b"vault";
+pub const VIEWING_KEYS_PREFIX: &[u8] = b"viewing_keys";
+pub const ZAPS_PREFIX: &[u8] = b"zaps";
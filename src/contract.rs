@@ -1,14 +1,41 @@
-use crate::constants::{BLOCK_SIZE, CONFIG_KEY, MOCK_AMOUNT, MOCK_BUTT_SWBTC_LP_ADDRESS};
-use crate::msg::{Asset, AssetInfo, HandleMsg, InitMsg, QueryMsg, ReceiveMsg, SecretSwapHandleMsg};
-use crate::state::{Config, SecretContract};
-use crate::validations::authorize;
+use crate::constants::{
+    BLOCK_SIZE, CONFIG_KEY, EXIT_ORDERS_PREFIX, FEATURES, MAX_BATCH_SIZE, MINIMUM_PAIR_ALLOWANCE,
+    MOCK_AMOUNT, MOCK_BUTT_SWBTC_LP_ADDRESS, ORDERS_PREFIX, POOLS_PREFIX,
+    REGISTERED_TOKENS_LIST_PREFIX, REGISTERED_TOKENS_PREFIX, SESSION_KEY, STORAGE_VERSION,
+    STORAGE_VERSION_KEY, SUBSCRIPTIONS_PREFIX, SUCCESSOR_KEY, SWAP_ROUTERS_KEY,
+    TOKEN_VIEWING_KEYS_PREFIX, USER_EXIT_ORDERS_PREFIX, USER_ORDERS_PREFIX,
+    USER_SUBSCRIPTIONS_PREFIX, VAULT_KEY, VIEWING_KEYS_PREFIX, ZAPS_PREFIX,
+};
+use crate::dex::{dex_adapter, DexAdapter, PairReserves};
+use crate::math::{
+    liquidity_for_lp_amount, lp_amount_for_liquidity, lp_amount_for_shares, price, price_impact,
+    share_amount_for_lp, stable_swap_price_impact, stable_swap_return_amount, stable_swap_split,
+    swap_return_amount,
+};
+use crate::msg::ResponseStatus::Success;
+use crate::msg::{
+    AllowanceStatus, CallbackHandleMsg, ContractInfoResponse, ContractStatus, ExitOrderRequest,
+    ExportedState, FarmHandleMsg, FarmReceiveMsg, HandleAnswer, HandleMsg, InitMsg,
+    MigrationRequest, OrderRequest, PairAllowance, QueryMsg, ReceiveMsg, SimulateZapResponse,
+    SubscriptionRequest, VaultResponse, YieldOptimizerReceiveMsg, ZapRequest,
+};
+use crate::state::{
+    Callback, Config, ConfigV1, DepositTarget, Dex, ExitOrder, Invariant, Migration, Order,
+    OrderStatus, Pool, PoolV2, RegisteredToken, SecretContract, Session, SessionV2, Subscription,
+    SubscriptionStatus, Vault, Zap,
+};
+use crate::validations::{
+    authorize, validate_deadline, validate_harvest_bounty, validate_invariant, validate_recipient,
+};
 use cosmwasm_std::{
-    from_binary, log, to_binary, Api, BankMsg, Binary, Coin, CosmosMsg, Env, Extern,
-    HandleResponse, HumanAddr, InitResponse, Querier, QueryResult, StdError, StdResult, Storage,
-    Uint128, WasmMsg,
+    from_binary, log, to_binary, Api, BankMsg, Binary, Coin, CosmosMsg, Decimal, Env, Extern,
+    HandleResponse, HumanAddr, InitResponse, Querier, QueryResult, ReadonlyStorage, StdError,
+    StdResult, Storage, Uint128, WasmMsg,
 };
+use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
+use secret_toolkit::crypto::{sha_256, Prng};
 use secret_toolkit::snip20;
-use secret_toolkit::storage::{TypedStore, TypedStoreMut};
+use secret_toolkit::storage::{AppendStore, AppendStoreMut, TypedStore, TypedStoreMut};
 use secret_toolkit::utils::HandleCallback;
 
 pub fn init<S: Storage, A: Api, Q: Querier>(
@@ -16,30 +43,21 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: InitMsg,
 ) -> StdResult<InitResponse> {
-    let mut config_store = TypedStoreMut::attach(&mut deps.storage);
     let config: Config = Config {
         admin: env.message.sender,
-        current_user: None,
+        contract_address: env.contract.address,
         butt: msg.butt,
         swbtc: msg.swbtc,
         butt_swbtc_farm_pool: msg.butt_swbtc_farm_pool,
         butt_swbtc_trade_pair: msg.butt_swbtc_trade_pair,
         butt_swbtc_lp: msg.butt_swbtc_lp,
-        swap_to_swbtc_contract_address: None,
-        butt_amount_to_provide: None,
-        swbtc_amount_to_provide: None,
+        max_price_impact: msg.max_price_impact,
+        hide_amounts: msg.hide_amounts,
         viewing_key: msg.viewing_key,
     };
-    config_store.store(CONFIG_KEY, &config)?;
 
     Ok(InitResponse {
-        messages: vec![snip20::set_viewing_key_msg(
-            config.viewing_key.clone(),
-            None,
-            BLOCK_SIZE,
-            config.butt_swbtc_lp.contract_hash,
-            config.butt_swbtc_lp.address,
-        )?],
+        messages: store_initial_state(&mut deps.storage, config, &env.contract_code_hash)?,
         log: vec![],
     })
 }
@@ -49,35 +67,490 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: HandleMsg,
 ) -> StdResult<HandleResponse> {
-    match msg {
-        HandleMsg::IncreaseAllowanceForPairContract {} => {
-            increase_allowance_for_pair_contract(deps)
+    // Everything but the migration expects the current storage layout
+    if msg != (HandleMsg::MigrateState {}) {
+        validate_storage_version(&deps.storage)?;
+    }
+    let response = match msg {
+        HandleMsg::AddPool {
+            trade_pair,
+            tokens,
+            lp_token,
+            deposit_target,
+            dex,
+            invariant,
+        } => add_pool(
+            deps,
+            &env,
+            trade_pair,
+            tokens,
+            lp_token,
+            deposit_target,
+            dex,
+            invariant,
+        ),
+        HandleMsg::AddSwapRouters { routers } => add_swap_routers(deps, &env, routers),
+        HandleMsg::CancelExitOrder { exit_order_id } => {
+            cancel_exit_order(deps, &env, exit_order_id)
+        }
+        HandleMsg::CancelOrder { order_id } => cancel_order(deps, &env, order_id),
+        HandleMsg::CancelSubscription { subscription_id } => {
+            cancel_subscription(deps, &env, subscription_id)
+        }
+        HandleMsg::CompoundHarvestedRewards {
+            harvester,
+            butt_balance,
+        } => compound_harvested_rewards(deps, &env, harvester, butt_balance),
+        HandleMsg::ExecuteExitOrder { exit_order_id } => {
+            execute_exit_order(deps, &env, exit_order_id)
+        }
+        HandleMsg::ExecuteExitOrders { exit_order_ids } => {
+            execute_exit_orders(deps, &env, exit_order_ids)
+        }
+        HandleMsg::ExecuteOrder { order_id } => execute_order(deps, &env, order_id),
+        HandleMsg::ExecuteOrders { order_ids } => execute_orders(deps, &env, order_ids),
+        HandleMsg::FinishMigration {} => finish_migration(deps, &env),
+        HandleMsg::Harvest {} => harvest(deps, &env),
+        HandleMsg::IncreaseAllowanceForPairContract { pool_id } => {
+            increase_allowance_for_pair_contract(deps, &env, pool_id)
+        }
+        HandleMsg::MigrateState {} => migrate_state(deps, &env),
+        HandleMsg::ProcessSubscriptions { subscription_ids } => {
+            process_subscriptions(deps, &env, subscription_ids)
         }
         HandleMsg::Receive {
             from, amount, msg, ..
         } => receive(deps, env, from, amount, msg),
-        HandleMsg::RegisterTokens { tokens } => register_tokens(&env, tokens),
+        HandleMsg::RecordVaultRewards { butt_balance } => {
+            record_vault_rewards(deps, &env, butt_balance)
+        }
+        HandleMsg::RegisterTokens { tokens } => register_tokens(deps, &env, tokens),
+        HandleMsg::RemoveSwapRouters { routers } => remove_swap_routers(deps, &env, routers),
         HandleMsg::RescueTokens {
             amount,
             denom,
             token,
         } => rescue_tokens(deps, &env, amount, denom, token),
+        HandleMsg::RotateViewingKey { entropy } => rotate_viewing_key(deps, &env, entropy),
+        HandleMsg::RouteMigratedTokens {} => route_migrated_tokens(deps, &env),
         HandleMsg::SendLpToUserThenDepositIntoFarmContract {} => {
             send_lp_to_user_then_deposit_into_farm_contract(deps, &env)
         }
-    }
+        HandleMsg::SetDepositTarget {
+            pool_id,
+            deposit_target,
+        } => set_deposit_target(deps, &env, pool_id, deposit_target),
+        HandleMsg::SetSuccessor { contract } => set_successor(deps, &env, contract),
+        HandleMsg::SetVault {
+            share_token,
+            harvest_bounty,
+            harvest_interval,
+        } => set_vault(deps, &env, share_token, harvest_bounty, harvest_interval),
+        HandleMsg::SetViewingKey { key } => set_viewing_key(deps, &env, key),
+        HandleMsg::UpdateConfig {
+            max_price_impact,
+            hide_amounts,
+        } => update_config(deps, &env, max_price_impact, hide_amounts),
+        HandleMsg::UpdateVault {
+            harvest_bounty,
+            harvest_interval,
+        } => update_vault(deps, &env, harvest_bounty, harvest_interval),
+        HandleMsg::ZapInstallment {
+            subscription_id,
+            amount,
+        } => zap_installment(deps, &env, subscription_id, amount),
+    };
+    pad_response(response)
 }
 
 pub fn query<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, msg: QueryMsg) -> QueryResult {
+    // Contract info tells integrators whether outdated storage has to be migrated
+    if msg != (QueryMsg::ContractInfo {}) {
+        validate_storage_version(&deps.storage)?;
+    }
     match msg {
         QueryMsg::Config {} => query_config(deps),
+        QueryMsg::ContractInfo {} => query_contract_info(deps),
+        QueryMsg::ExitOrders {
+            address,
+            key,
+            page,
+            page_size,
+        } => query_exit_orders(deps, address, key, page, page_size),
+        QueryMsg::ExportState {} => query_export_state(deps),
+        QueryMsg::Orders {
+            address,
+            key,
+            page,
+            page_size,
+        } => query_orders(deps, address, key, page, page_size),
+        QueryMsg::PairAllowances {} => query_pair_allowances(deps),
+        QueryMsg::Pools {} => to_binary(&pools(&deps.storage)?),
+        QueryMsg::RegisteredTokens { page, page_size } => {
+            query_registered_tokens(deps, page, page_size)
+        }
+        QueryMsg::SimulateZap {
+            pool_id,
+            intermediate_token,
+            amount,
+        } => query_simulate_zap(deps, pool_id, intermediate_token, amount),
+        QueryMsg::Subscriptions {
+            address,
+            key,
+            page,
+            page_size,
+        } => query_subscriptions(deps, address, key, page, page_size),
+        QueryMsg::SwapRouters {} => query_swap_routers(deps),
+        QueryMsg::Vault {} => query_vault(deps),
+        QueryMsg::ZapHistory {
+            address,
+            key,
+            page,
+            page_size,
+        } => query_zap_history(deps, address, key, page, page_size),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn add_pool<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    trade_pair: SecretContract,
+    tokens: [SecretContract; 2],
+    lp_token: SecretContract,
+    deposit_target: Option<DepositTarget>,
+    dex: Option<Dex>,
+    invariant: Option<Invariant>,
+) -> StdResult<HandleResponse> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    authorize(vec![config.admin], &env.message.sender)?;
+    // Pools other than BUTT-SWBTC send their LP to the recipient unless told otherwise
+    let deposit_target: DepositTarget = deposit_target.unwrap_or(DepositTarget::Wallet);
+    validate_deposit_target(&deps.api, &deposit_target)?;
+    let invariant: Invariant = invariant.unwrap_or(Invariant::ConstantProduct);
+    validate_invariant(&invariant)?;
+
+    let pool: Pool = store_pool(
+        &mut deps.storage,
+        trade_pair,
+        tokens,
+        lp_token,
+        deposit_target,
+        dex.unwrap_or(Dex::SecretSwap),
+        invariant,
+    )?;
+    store_token_viewing_keys(&mut deps.storage, &pool_tokens(&pool), &config.viewing_key)?;
+
+    Ok(HandleResponse {
+        messages: pool_setup_msgs(&pool, &config.viewing_key, &env.contract_code_hash)?,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::AddPool { pool_id: pool.id })?),
+    })
+}
+
+fn add_swap_routers<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    routers: Vec<HumanAddr>,
+) -> StdResult<HandleResponse> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    authorize(vec![config.admin], &env.message.sender)?;
+
+    let mut swap_routers: Vec<HumanAddr> =
+        TypedStore::attach(&deps.storage).load(SWAP_ROUTERS_KEY)?;
+    for router in routers {
+        if !swap_routers.contains(&router) {
+            swap_routers.push(router);
+        }
     }
+    TypedStoreMut::attach(&mut deps.storage).store(SWAP_ROUTERS_KEY, &swap_routers)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::AddSwapRouters {
+            status: Success,
+        })?),
+    })
 }
 
 fn query_config<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
     let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
 
-    to_binary(&config.with_public_attributes()?)
+    to_binary(&config.with_public_attributes(successor(&deps.storage)?)?)
+}
+
+fn query_contract_info<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<Binary> {
+    let storage_version: u32 = storage_version(&deps.storage)?;
+    let status: ContractStatus = if storage_version != STORAGE_VERSION {
+        ContractStatus::MigrationRequired
+    } else if successor(&deps.storage)?.is_some() {
+        ContractStatus::Deprecated
+    } else {
+        let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY)?;
+        if session.current_user.is_some() {
+            ContractStatus::Zapping
+        } else {
+            ContractStatus::Operational
+        }
+    };
+
+    to_binary(&ContractInfoResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        storage_version,
+        features: FEATURES.iter().map(|feature| feature.to_string()).collect(),
+        pools: pools(&deps.storage)?,
+        status,
+    })
+}
+
+// A page of the user's exit orders, newest first
+fn query_exit_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+    key: String,
+    page: u32,
+    page_size: u32,
+) -> StdResult<Binary> {
+    authenticate(&deps.storage, &address, &key)?;
+
+    let user_exit_orders_storage = ReadonlyPrefixedStorage::multilevel(
+        &[USER_EXIT_ORDERS_PREFIX, address.0.as_bytes()],
+        &deps.storage,
+    );
+    let exit_order_ids: Vec<u64> = match AppendStore::<u64, _>::attach(&user_exit_orders_storage) {
+        None => vec![],
+        Some(exit_order_ids_store) => exit_order_ids_store?
+            .iter()
+            .rev()
+            .skip(page.saturating_mul(page_size) as usize)
+            .take(page_size as usize)
+            .collect::<StdResult<Vec<u64>>>()?,
+    };
+    let mut exit_orders: Vec<ExitOrder> = vec![];
+    for exit_order_id in exit_order_ids {
+        exit_orders.push(exit_order(&deps.storage, exit_order_id)?);
+    }
+
+    to_binary(&exit_orders)
+}
+
+// Settings, stats and registries for a successor to import
+fn query_export_state<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY)?;
+
+    to_binary(&ExportedState {
+        config: config.with_public_attributes(successor(&deps.storage)?)?,
+        zaps_count: session.id,
+        pools: pools(&deps.storage)?,
+        registered_tokens: registered_tokens(&deps.storage, 0, usize::MAX)?,
+        swap_routers: TypedStore::attach(&deps.storage).load(SWAP_ROUTERS_KEY)?,
+    })
+}
+
+// A page of the user's orders, newest first
+fn query_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+    key: String,
+    page: u32,
+    page_size: u32,
+) -> StdResult<Binary> {
+    authenticate(&deps.storage, &address, &key)?;
+
+    let user_orders_storage = ReadonlyPrefixedStorage::multilevel(
+        &[USER_ORDERS_PREFIX, address.0.as_bytes()],
+        &deps.storage,
+    );
+    let order_ids: Vec<u64> = match AppendStore::<u64, _>::attach(&user_orders_storage) {
+        None => vec![],
+        Some(order_ids_store) => order_ids_store?
+            .iter()
+            .rev()
+            .skip(page.saturating_mul(page_size) as usize)
+            .take(page_size as usize)
+            .collect::<StdResult<Vec<u64>>>()?,
+    };
+    let mut orders: Vec<Order> = vec![];
+    for order_id in order_ids {
+        orders.push(order(&deps.storage, order_id)?);
+    }
+
+    to_binary(&orders)
+}
+
+// The allowance given to the trade pair of every pool for each of its tokens
+fn query_pair_allowances<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<Binary> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    let mut pair_allowances: Vec<PairAllowance> = vec![];
+    for pool in pools(&deps.storage)? {
+        for token in pool.tokens.iter() {
+            let allowance: Uint128 = snip20::allowance_query(
+                &deps.querier,
+                config.contract_address.clone(),
+                pool.trade_pair.address.clone(),
+                token_viewing_key(&deps.storage, &token.address)?,
+                BLOCK_SIZE,
+                token.contract_hash.clone(),
+                token.address.clone(),
+            )?
+            .allowance;
+            let status: AllowanceStatus = if allowance.is_zero() {
+                AllowanceStatus::Missing
+            } else if allowance.u128() < MINIMUM_PAIR_ALLOWANCE {
+                AllowanceStatus::Insufficient
+            } else {
+                AllowanceStatus::Sufficient
+            };
+            pair_allowances.push(PairAllowance {
+                pool_id: pool.id,
+                token: token.address.clone(),
+                spender: pool.trade_pair.address.clone(),
+                allowance,
+                status,
+            });
+        }
+    }
+
+    to_binary(&pair_allowances)
+}
+
+// Registered tokens in the order they were first registered
+fn query_registered_tokens<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    page: u32,
+    page_size: u32,
+) -> StdResult<Binary> {
+    to_binary(&registered_tokens(
+        &deps.storage,
+        page.saturating_mul(page_size) as usize,
+        page_size as usize,
+    )?)
+}
+
+// Simulates the swap and provide steps of a zap without the first swap,
+// which is done by a swap router outside of this contract
+fn query_simulate_zap<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    pool_id: u32,
+    intermediate_token: Option<HumanAddr>,
+    amount: Uint128,
+) -> StdResult<Binary> {
+    let pool: Pool = pool(&deps.storage, pool_id)?;
+    let session: Session = Session {
+        intermediate_token,
+        ..Session::default()
+    };
+    let intermediate_token: SecretContract = session.intermediate_token(&pool);
+    let paired_token: SecretContract = session.paired_token(&pool);
+    let dex: Box<dyn DexAdapter<Q>> = dex_adapter(&pool.dex);
+    let pair_reserves: PairReserves = dex.query_reserves(&deps.querier, &pool.trade_pair)?;
+    let intermediate_token_reserve: Uint128 = pair_reserves.reserve(&intermediate_token.address)?;
+    let paired_token_reserve: Uint128 = pair_reserves.reserve(&paired_token.address)?;
+
+    let (amount_to_swap, return_amount, price_impact): (Uint128, Uint128, Decimal) = plan_swap(
+        &pool.invariant,
+        amount,
+        intermediate_token_reserve,
+        paired_token_reserve,
+        dex.commission_rate(),
+    )?;
+    let amount_to_provide: Uint128 = (amount - amount_to_swap)?;
+    let lp_amount: Uint128 = lp_amount_for_liquidity(
+        [amount_to_provide, return_amount],
+        [
+            intermediate_token_reserve + amount_to_swap,
+            (paired_token_reserve - return_amount)?,
+        ],
+        pair_reserves.total_share,
+    )?;
+
+    to_binary(&SimulateZapResponse {
+        amount_to_swap,
+        amount_to_provide,
+        return_amount,
+        price_impact,
+        lp_amount,
+    })
+}
+
+// A page of the user's subscriptions, newest first
+fn query_subscriptions<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+    key: String,
+    page: u32,
+    page_size: u32,
+) -> StdResult<Binary> {
+    authenticate(&deps.storage, &address, &key)?;
+
+    let user_subscriptions_storage = ReadonlyPrefixedStorage::multilevel(
+        &[USER_SUBSCRIPTIONS_PREFIX, address.0.as_bytes()],
+        &deps.storage,
+    );
+    let subscription_ids: Vec<u64> =
+        match AppendStore::<u64, _>::attach(&user_subscriptions_storage) {
+            None => vec![],
+            Some(subscription_ids_store) => subscription_ids_store?
+                .iter()
+                .rev()
+                .skip(page.saturating_mul(page_size) as usize)
+                .take(page_size as usize)
+                .collect::<StdResult<Vec<u64>>>()?,
+        };
+    let mut subscriptions: Vec<Subscription> = vec![];
+    for subscription_id in subscription_ids {
+        subscriptions.push(subscription(&deps.storage, subscription_id)?);
+    }
+
+    to_binary(&subscriptions)
+}
+
+fn query_swap_routers<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let swap_routers: Vec<HumanAddr> = TypedStore::attach(&deps.storage).load(SWAP_ROUTERS_KEY)?;
+
+    to_binary(&swap_routers)
+}
+
+fn query_vault<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let vault: Vault = vault(&deps.storage)?;
+    let share_price: Decimal = if vault.total_shares.is_zero() {
+        Decimal::one()
+    } else {
+        Decimal::from_ratio(vault.total_lp.u128(), vault.total_shares.u128())
+    };
+
+    to_binary(&VaultResponse { vault, share_price })
+}
+
+// Zaps of the address, newest first
+fn query_zap_history<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+    key: String,
+    page: u32,
+    page_size: u32,
+) -> StdResult<Binary> {
+    authenticate(&deps.storage, &address, &key)?;
+
+    let zaps_storage =
+        ReadonlyPrefixedStorage::multilevel(&[ZAPS_PREFIX, address.0.as_bytes()], &deps.storage);
+    let zaps: Vec<Zap> = match AppendStore::<Zap, _>::attach(&zaps_storage) {
+        None => vec![],
+        Some(zaps_store) => zaps_store?
+            .iter()
+            .rev()
+            .skip(page.saturating_mul(page_size) as usize)
+            .take(page_size as usize)
+            .collect::<StdResult<Vec<Zap>>>()?,
+    };
+
+    to_binary(&zaps)
 }
 
 fn receive<S: Storage, A: Api, Q: Querier>(
@@ -87,97 +560,115 @@ fn receive<S: Storage, A: Api, Q: Querier>(
     amount: Uint128,
     msg: Option<Binary>,
 ) -> StdResult<HandleResponse> {
-    let config: Config = TypedStoreMut::attach(&mut deps.storage)
-        .load(CONFIG_KEY)
-        .unwrap();
-    let response = if let Some(msg_unwrapped) = msg {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY)?;
+    let session_pool: Pool = pool(&deps.storage, session.pool_id)?;
+    // The swap router returns SWBTC while a migration routes its tokens,
+    // which is only zapped once all of them are routed
+    if session.migration.is_some() && env.message.sender == config.swbtc.address {
+        authorize(
+            session.swap_to_swbtc_contract_address.into_iter().collect(),
+            &from,
+        )?;
+        return Ok(HandleResponse {
+            messages: vec![],
+            log: vec![],
+            data: Some(to_binary(&HandleAnswer::Receive {
+                swbtc_amount: amount,
+            })?),
+        });
+    }
+    if let Some(msg_unwrapped) = msg {
         let msg: ReceiveMsg = from_binary(&msg_unwrapped)?;
         match msg {
-            ReceiveMsg::InitSwapAndProvide {
-                first_token_contract_hash,
-                swap_to_swbtc_contract,
-                swap_to_swbtc_msg,
-            } => init_swap_and_provide(
-                deps,
-                &env,
-                from,
-                amount,
-                config,
-                first_token_contract_hash,
-                swap_to_swbtc_contract,
-                swap_to_swbtc_msg,
-            ),
-        }
-    } else if env.message.sender == config.swbtc.address {
-        swap_half_of_swbtc_to_butt(deps, &env, from, amount, config)
-    } else if env.message.sender == config.butt.address {
-        provide_liquidity_to_trade_pair(deps, &env, from, amount, config)
+            ReceiveMsg::InitSwapAndProvide(zap_request) => {
+                validate_not_deprecated(&deps.storage)?;
+                let token: HumanAddr = env.message.sender.clone();
+                init_swap_and_provide(deps, &env, from, token, amount, session, zap_request)
+            }
+            ReceiveMsg::MigrateLiquidity(migration_request) => {
+                migrate_liquidity(deps, &env, from, amount, config, session, migration_request)
+            }
+            ReceiveMsg::PlaceExitOrder(exit_order_request) => {
+                place_exit_order(deps, &env, from, amount, exit_order_request)
+            }
+            ReceiveMsg::PlaceOrder(order_request) => {
+                place_order(deps, &env, from, amount, order_request)
+            }
+            ReceiveMsg::Subscribe(subscription_request) => {
+                subscribe(deps, &env, from, amount, subscription_request)
+            }
+            ReceiveMsg::TopUpSubscription { subscription_id } => {
+                top_up_subscription(deps, &env, from, amount, subscription_id)
+            }
+            ReceiveMsg::WithdrawFromVault {} => withdraw_from_vault(deps, &env, from, amount),
+        }
+    } else if env.message.sender == session.intermediate_token(&session_pool).address {
+        swap_half_of_intermediate_token(deps, &env, from, amount, config, session_pool, session)
+    } else if env.message.sender == session.paired_token(&session_pool).address {
+        provide_liquidity_to_trade_pair(deps, &env, from, amount, session_pool, session)
     } else {
-        return Err(StdError::generic_err(
+        Err(StdError::generic_err(
             "Receive message combination is wrong.",
-        ));
-    };
-    pad_response(response)
+        ))
+    }
 }
 
-// No matter what first swap has to return in a swap to swbtc
+// No matter what first swap has to return in a swap to the intermediate token (SWBTC by default)
 fn init_swap_and_provide<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: &Env,
     from: HumanAddr,
+    token: HumanAddr,
     amount: Uint128,
-    mut config: Config,
-    first_token_contract_hash: String,
-    swap_to_swbtc_contract: Option<SecretContract>,
-    swap_to_swbtc_msg: Option<Binary>,
+    mut session: Session,
+    zap_request: ZapRequest,
 ) -> StdResult<HandleResponse> {
-    // 1. Make sure token isn't BUTT
-    if config.butt.address == env.message.sender {
-        return Err(StdError::generic_err(
-            "Token can't be BUTT when ReceiveMsg present.",
-        ));
-    };
+    let pool: Pool = validate_zap_request(deps, &from, &token, &zap_request)?;
+    // 1. Set the intermediate token, which defaults to the pool's second token
+    session.intermediate_token = Some(
+        zap_request
+            .intermediate_token
+            .clone()
+            .unwrap_or_else(|| pool.tokens[1].address.clone()),
+    );
     // 2. Make sure contract isn't being used already
-    if config.current_user.is_some() {
+    if session.current_user.is_some() {
         return Err(StdError::generic_err("Contract is already being used."));
     }
+    validate_deadline(zap_request.deadline, &env.block)?;
+    let deposit_into_vault: bool = zap_request.vault.unwrap_or(false);
 
     let mut messages: Vec<CosmosMsg> = vec![];
-    // 3. Swap token to SWBTC if first token is not SWBTC
-    // Or send the SWBTC to the contract again which would simulate the result of a swap to swbtc
-    if config.swbtc.address == env.message.sender {
-        config.swap_to_swbtc_contract_address = Some(env.contract.address.clone());
+    // 3. Swap token to the intermediate token if first token is not the intermediate token
+    // Or send the intermediate token to the contract again which would simulate the result of a swap
+    let intermediate_token: SecretContract = session.intermediate_token(&pool);
+    if intermediate_token.address == token {
+        session.swap_to_swbtc_contract_address = Some(env.contract.address.clone());
         messages.push(snip20::send_msg(
             env.contract.address.clone(),
             amount,
             None,
             None,
             BLOCK_SIZE,
-            config.swbtc.contract_hash.clone(),
-            config.swbtc.address.clone(),
+            intermediate_token.contract_hash,
+            intermediate_token.address,
         )?);
     } else {
-        if swap_to_swbtc_msg.is_none() {
-            return Err(StdError::generic_err("Swap to SWBTC msg missing."));
-        }
-        if swap_to_swbtc_contract.is_none() {
-            return Err(StdError::generic_err("Swap to SWBTC contract missing."));
-        }
-
-        config.swap_to_swbtc_contract_address =
-            Some(swap_to_swbtc_contract.clone().unwrap().address);
+        let swap_to_swbtc_contract: SecretContract = zap_request.swap_to_swbtc_contract.unwrap();
+        session.swap_to_swbtc_contract_address = Some(swap_to_swbtc_contract.address.clone());
         messages.push(snip20::send_msg(
-            swap_to_swbtc_contract.unwrap().address,
+            swap_to_swbtc_contract.address,
             amount,
-            swap_to_swbtc_msg,
+            zap_request.swap_to_swbtc_msg,
             None,
             BLOCK_SIZE,
-            first_token_contract_hash,
-            env.message.sender.clone(),
+            zap_request.first_token_contract_hash,
+            token,
         )?);
     }
 
-    // 5. Call function to send lp to user then deposit into farm contract
+    // 4. Call function to send lp to user then deposit into farm contract
     messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
         contract_addr: env.contract.address.clone(),
         callback_code_hash: env.contract_code_hash.clone(),
@@ -185,930 +676,7059 @@ fn init_swap_and_provide<S: Storage, A: Api, Q: Querier>(
         send: vec![],
     }));
 
-    // 6. Store Config
-    config.current_user = Some(from);
-    TypedStoreMut::attach(&mut deps.storage).store(CONFIG_KEY, &config)?;
+    // 5. Store Session
+    session.recipient = Some(zap_request.recipient.unwrap_or_else(|| from.clone()));
+    session.current_user = Some(from);
+    session.id += 1;
+    session.pool_id = pool.id;
+    session.belief_price = zap_request.belief_price;
+    session.max_spread = zap_request.max_spread;
+    session.max_price_impact = zap_request.max_price_impact;
+    session.deadline = zap_request.deadline;
+    session.callback = zap_request.callback;
+    session.hide_amounts = zap_request.hide_amounts.unwrap_or(false);
+    session.vault = deposit_into_vault;
+    session.deposit_target = zap_request.deposit_target;
+    TypedStoreMut::attach(&mut deps.storage).store(SESSION_KEY, &session)?;
 
     Ok(HandleResponse {
         messages,
         log: vec![],
-        data: None,
+        data: Some(to_binary(&HandleAnswer::InitSwapAndProvide {
+            session_id: session.id,
+        })?),
     })
 }
 
-fn increase_allowance_for_pair_contract<S: Storage, A: Api, Q: Querier>(
-    deps: &mut cosmwasm_std::Extern<S, A, Q>,
-) -> StdResult<HandleResponse> {
-    let mut messages: Vec<CosmosMsg> = vec![];
-    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
-    messages.push(secret_toolkit::snip20::increase_allowance_msg(
-        config.butt_swbtc_trade_pair.address.clone(),
-        Uint128(u128::MAX),
-        None,
-        None,
-        BLOCK_SIZE,
-        config.butt.contract_hash,
-        config.butt.address,
-    )?);
-    messages.push(secret_toolkit::snip20::increase_allowance_msg(
-        config.butt_swbtc_trade_pair.address,
-        Uint128(u128::MAX),
-        None,
-        None,
-        BLOCK_SIZE,
-        config.swbtc.contract_hash,
-        config.swbtc.address,
-    )?);
+fn authenticate<S: ReadonlyStorage>(storage: &S, address: &HumanAddr, key: &str) -> StdResult<()> {
+    let viewing_keys_storage = ReadonlyPrefixedStorage::new(VIEWING_KEYS_PREFIX, storage);
+    let key_hash: Option<Vec<u8>> = viewing_keys_storage.get(address.0.as_bytes());
+    if key_hash != Some(sha_256(key.as_bytes()).to_vec()) {
+        return Err(StdError::Unauthorized { backtrace: None });
+    }
 
-    Ok(HandleResponse {
-        messages,
-        log: vec![],
-        data: None,
-    })
+    Ok(())
 }
 
-fn rescue_tokens<S: Storage, A: Api, Q: Querier>(
+// Price of BUTT in SWBTC at the BUTT-SWBTC trade pair's reserves
+fn butt_swbtc_price<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Decimal> {
+    let pool: Pool = pool(&deps.storage, 0)?;
+    let pair_reserves: PairReserves =
+        dex_adapter::<Q>(&pool.dex).query_reserves(&deps.querier, &pool.trade_pair)?;
+    price(
+        pair_reserves.reserve(&pool.tokens[0].address)?,
+        pair_reserves.reserve(&pool.tokens[1].address)?,
+    )
+}
+
+fn cancel_exit_order<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: &Env,
-    amount: Uint128,
-    denom: Option<String>,
-    token: Option<SecretContract>,
+    exit_order_id: u64,
 ) -> StdResult<HandleResponse> {
-    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
-    authorize(vec![config.admin.clone()], &env.message.sender)?;
-
-    let mut messages: Vec<CosmosMsg> = vec![];
-    if let Some(denom_unwrapped) = denom {
-        let withdrawal_coin: Vec<Coin> = vec![Coin {
-            amount,
-            denom: denom_unwrapped,
-        }];
-        messages.push(CosmosMsg::Bank(BankMsg::Send {
-            from_address: env.contract.address.clone(),
-            to_address: config.admin.clone(),
-            amount: withdrawal_coin,
-        }));
+    let mut exit_order: ExitOrder = exit_order(&deps.storage, exit_order_id)?;
+    authorize(vec![exit_order.user.clone()], &env.message.sender)?;
+    if exit_order.status != OrderStatus::Pending {
+        return Err(StdError::generic_err(format!(
+            "Exit order {} is not pending.",
+            exit_order_id
+        )));
     }
 
-    if let Some(token_unwrapped) = token {
-        messages.push(snip20::transfer_msg(
-            config.admin,
-            amount,
-            None,
-            BLOCK_SIZE,
-            token_unwrapped.contract_hash,
-            token_unwrapped.address,
-        )?)
-    }
+    exit_order.status = OrderStatus::Cancelled;
+    store_exit_order(&mut deps.storage, &exit_order)?;
 
     Ok(HandleResponse {
-        messages,
+        messages: vec![refund_exit_order_msg(exit_order)?],
         log: vec![],
-        data: None,
+        data: Some(to_binary(&HandleAnswer::CancelExitOrder {
+            status: Success,
+        })?),
     })
 }
 
-fn swap_half_of_swbtc_to_butt<S: Storage, A: Api, Q: Querier>(
+fn cancel_order<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    _env: &Env,
-    from: HumanAddr,
-    amount: Uint128,
-    mut config: Config,
+    env: &Env,
+    order_id: u64,
 ) -> StdResult<HandleResponse> {
-    // Test that it's sent from swap_to_swbtc_contract_address
-    if config.swap_to_swbtc_contract_address.is_none() {
-        return Err(StdError::generic_err("Swap to SWBTC contract missing."));
+    let mut order: Order = order(&deps.storage, order_id)?;
+    authorize(vec![order.user.clone()], &env.message.sender)?;
+    if order.status != OrderStatus::Pending {
+        return Err(StdError::generic_err(format!(
+            "Order {} is not pending.",
+            order_id
+        )));
     }
-    authorize(
-        [from].to_vec(),
-        &config.swap_to_swbtc_contract_address.clone().unwrap(),
-    )?;
 
-    let swbtc_amount_to_swap: Uint128 = Uint128(amount.u128() / 2);
-    config.swbtc_amount_to_provide = Some((amount - swbtc_amount_to_swap)?);
-    TypedStoreMut::attach(&mut deps.storage).store(CONFIG_KEY, &config)?;
+    order.status = OrderStatus::Cancelled;
+    store_order(&mut deps.storage, &order)?;
 
     Ok(HandleResponse {
-        messages: vec![secret_toolkit::snip20::send_msg(
-            config.butt_swbtc_trade_pair.address,
-            swbtc_amount_to_swap,
-            Some(Binary::from(r#"{ "swap": {} }"#.as_bytes())),
-            None,
-            BLOCK_SIZE,
-            config.swbtc.contract_hash,
-            config.swbtc.address,
-        )?],
+        messages: vec![refund_order_msg(order)?],
         log: vec![],
-        data: None,
-    })
-}
-
-fn pad_response(response: StdResult<HandleResponse>) -> StdResult<HandleResponse> {
-    response.map(|mut response| {
-        response.data = response.data.map(|mut data| {
-            space_pad(BLOCK_SIZE, &mut data.0);
-            data
-        });
-        response
+        data: Some(to_binary(&HandleAnswer::CancelOrder { status: Success })?),
     })
 }
 
-fn provide_liquidity_to_trade_pair<S: Storage, A: Api, Q: Querier>(
+// Ends the subscription and refunds what's left of its balance
+fn cancel_subscription<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    _env: &Env,
-    from: HumanAddr,
-    amount: Uint128,
-    mut config: Config,
+    env: &Env,
+    subscription_id: u64,
 ) -> StdResult<HandleResponse> {
-    // Test that the sender is from the trade pair
-    authorize([from].to_vec(), &config.butt_swbtc_trade_pair.address)?;
-
-    let butt_amount_to_provide: Uint128 = amount;
-    if butt_amount_to_provide.is_zero() {
-        return Err(StdError::generic_err(
-            "Contract BUTT balance must be greater than zero.",
-        ));
-    }
-
-    if config.swbtc_amount_to_provide.is_none() {
-        return Err(StdError::generic_err("swbtc_amount_to_provide is missing."));
-    }
-
-    let swbtc_amount_to_provide: Uint128 = config.swbtc_amount_to_provide.unwrap();
-    if swbtc_amount_to_provide.is_zero() {
-        return Err(StdError::generic_err(
-            "SWBTC amount to provide must be greater than zero.",
-        ));
+    let mut subscription: Subscription = subscription(&deps.storage, subscription_id)?;
+    authorize(vec![subscription.user.clone()], &env.message.sender)?;
+    if subscription.status != SubscriptionStatus::Active {
+        return Err(StdError::generic_err(format!(
+            "Subscription {} is not active.",
+            subscription_id
+        )));
     }
 
-    config.butt_amount_to_provide = Some(amount);
-    TypedStoreMut::attach(&mut deps.storage).store(CONFIG_KEY, &config)?;
-    // Provide liquidity to farm contract
-    let provide_liquidity_msg = SecretSwapHandleMsg::ProvideLiquidity {
-        assets: [
-            Asset {
-                amount: swbtc_amount_to_provide,
-                info: AssetInfo::Token {
-                    contract_addr: config.swbtc.address,
-                    token_code_hash: config.swbtc.contract_hash,
-                    viewing_key: "SecretSwap".to_string(),
-                },
-            },
-            Asset {
-                amount: butt_amount_to_provide,
-                info: AssetInfo::Token {
-                    contract_addr: config.butt.address,
-                    token_code_hash: config.butt.contract_hash,
-                    viewing_key: "SecretSwap".to_string(),
-                },
-            },
-        ],
-        slippage_tolerance: None,
-    };
-    let cosmos_msg = provide_liquidity_msg.to_cosmos_msg(
-        config.butt_swbtc_trade_pair.contract_hash,
-        config.butt_swbtc_trade_pair.address,
-        None,
-    )?;
+    let refund_msg: CosmosMsg = refund_subscription_msg(&subscription)?;
+    subscription.balance = Uint128(0);
+    subscription.status = SubscriptionStatus::Cancelled;
+    store_subscription(&mut deps.storage, &subscription)?;
 
     Ok(HandleResponse {
-        messages: vec![cosmos_msg],
+        messages: vec![refund_msg],
         log: vec![],
-        data: None,
+        data: Some(to_binary(&HandleAnswer::CancelSubscription {
+            status: Success,
+        })?),
     })
 }
 
-fn query_balance_of_token<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-    address: HumanAddr,
-    token: SecretContract,
-    viewing_key: String,
-) -> StdResult<Uint128> {
-    if token.address == HumanAddr::from(MOCK_BUTT_SWBTC_LP_ADDRESS) {
-        Ok(Uint128(MOCK_AMOUNT))
-    } else {
-        let balance = snip20::balance_query(
-            &deps.querier,
-            address,
-            viewing_key,
-            BLOCK_SIZE,
-            token.contract_hash,
-            token.address,
-        )?;
-        Ok(balance.amount)
-    }
-}
-
-fn register_tokens(env: &Env, tokens: Vec<SecretContract>) -> StdResult<HandleResponse> {
-    let mut messages = vec![];
-    for token in tokens {
-        let address = token.address;
-        let contract_hash = token.contract_hash;
-        messages.push(snip20::register_receive_msg(
-            env.contract_code_hash.clone(),
+// Pays the harvester's bounty out of the BUTT rewards claimed by harvest,
+// then zaps the rest back into the vault's farm position
+fn compound_harvested_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    harvester: HumanAddr,
+    butt_balance: Uint128,
+) -> StdResult<HandleResponse> {
+    authorize([env.message.sender.clone()].to_vec(), &env.contract.address)?;
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY)?;
+    let mut vault: Vault = vault(&deps.storage)?;
+    // The contract also holds BUTT escrowed for orders and subscriptions
+    let mut reward_amount: Uint128 =
+        (query_balance_of_token(deps, env.contract.address.clone(), config.butt.clone())?
+            - butt_balance)?;
+    reward_amount += vault.pending_rewards;
+    vault.pending_rewards = Uint128(0);
+    TypedStoreMut::attach(&mut deps.storage).store(VAULT_KEY, &vault)?;
+    let bounty_amount: Uint128 = reward_amount * vault.harvest_bounty;
+    let amount_to_compound: Uint128 = (reward_amount - bounty_amount)?;
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if !bounty_amount.is_zero() {
+        messages.push(snip20::transfer_msg(
+            harvester,
+            bounty_amount,
             None,
             BLOCK_SIZE,
-            contract_hash.clone(),
-            address.clone(),
+            config.butt.contract_hash.clone(),
+            config.butt.address.clone(),
         )?);
     }
+    if amount_to_compound.is_zero() {
+        return Ok(HandleResponse {
+            messages,
+            log: vec![],
+            data: Some(to_binary(&HandleAnswer::CompoundHarvestedRewards {
+                session_id: None,
+                reward_amount,
+                bounty_amount,
+            })?),
+        });
+    }
+
+    // The rewards are BUTT, so they go straight to swapping half of them
+    session.id += 1;
+    session.current_user = Some(env.contract.address.clone());
+    session.recipient = Some(env.contract.address.clone());
+    session.intermediate_token = Some(config.butt.address.clone());
+    session.swap_to_swbtc_contract_address = Some(env.contract.address.clone());
+    session.vault = true;
+    session.compounding = true;
+    TypedStoreMut::attach(&mut deps.storage).store(SESSION_KEY, &session)?;
+
+    messages.push(snip20::send_msg(
+        env.contract.address.clone(),
+        amount_to_compound,
+        None,
+        None,
+        BLOCK_SIZE,
+        config.butt.contract_hash,
+        config.butt.address,
+    )?);
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.clone(),
+        callback_code_hash: env.contract_code_hash.clone(),
+        msg: to_binary(&HandleMsg::SendLpToUserThenDepositIntoFarmContract {})?,
+        send: vec![],
+    }));
 
     Ok(HandleResponse {
         messages,
         log: vec![],
-        data: None,
+        data: Some(to_binary(&HandleAnswer::CompoundHarvestedRewards {
+            session_id: Some(session.id),
+            reward_amount,
+            bounty_amount,
+        })?),
     })
 }
 
-fn send_lp_to_user_then_deposit_into_farm_contract<S: Storage, A: Api, Q: Querier>(
+// Sends the LP to the recipient, then deposits it into the target with the target's own hook.
+// Targets credit whoever sends them the LP,
+// so the recipient must have given this contract an allowance for the LP token.
+fn deposit_msgs(
+    deposit_target: DepositTarget,
+    recipient: &HumanAddr,
+    lp_amount: Uint128,
+    lp_token: &SecretContract,
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut messages: Vec<CosmosMsg> = vec![snip20::transfer_msg(
+        recipient.clone(),
+        lp_amount,
+        None,
+        BLOCK_SIZE,
+        lp_token.contract_hash.clone(),
+        lp_token.address.clone(),
+    )?];
+    let (target, hook): (SecretContract, Binary) = match deposit_target {
+        DepositTarget::Farm(farm) => (
+            farm,
+            to_binary(&FarmReceiveMsg::DepositIncentivizedToken {})?,
+        ),
+        DepositTarget::YieldOptimizer(yield_optimizer) => (
+            yield_optimizer,
+            to_binary(&YieldOptimizerReceiveMsg::DepositIncentivizedToken {})?,
+        ),
+        DepositTarget::Wallet => return Ok(messages),
+    };
+    messages.push(snip20::send_from_msg(
+        recipient.clone(),
+        target.address,
+        lp_amount,
+        Some(hook),
+        None,
+        BLOCK_SIZE,
+        lp_token.contract_hash.clone(),
+        lp_token.address.clone(),
+    )?);
+
+    Ok(messages)
+}
+
+// Exits the escrow of an exit order marked as executed by execute_exit_orders to SWBTC for its user.
+// Vault shares are redeemed for their LP, whose tokens the trade pair returns to the contract.
+// The BUTT is swapped to SWBTC sent straight to the user, along with the withdrawn SWBTC.
+fn execute_exit_order<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: &Env,
+    exit_order_id: u64,
 ) -> StdResult<HandleResponse> {
     authorize([env.message.sender.clone()].to_vec(), &env.contract.address)?;
-    let mut config: Config = TypedStoreMut::attach(&mut deps.storage)
-        .load(CONFIG_KEY)
-        .unwrap();
-    if let Some(current_user_unwrapped) = config.current_user {
-        // Query the contract's SWBTC balance
-        let lp_balance_of_contract: Uint128 = query_balance_of_token(
-            deps,
-            env.contract.address.clone(),
-            config.butt_swbtc_lp.clone(),
-            config.viewing_key.clone(),
-        )
-        .unwrap();
-        if lp_balance_of_contract.is_zero() {
-            return Err(StdError::generic_err(
-                "Contract BUTT-SWBTC LP balance must be greater than zero.",
-            ));
-        }
-
-        let butt_amount_to_provide: Uint128 = config.butt_amount_to_provide.unwrap();
-        let swbtc_amount_to_provide: Uint128 = config.swbtc_amount_to_provide.unwrap();
-        config.current_user = None;
-        config.swap_to_swbtc_contract_address = None;
-        config.butt_amount_to_provide = None;
-        config.swbtc_amount_to_provide = None;
-        TypedStoreMut::attach(&mut deps.storage).store(CONFIG_KEY, &config)?;
+    let exit_order: ExitOrder = exit_order(&deps.storage, exit_order_id)?;
+    if exit_order.status != OrderStatus::Executed {
+        return Err(StdError::generic_err(format!(
+            "Exit order {} is not executed.",
+            exit_order_id
+        )));
+    }
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    let pool: Pool = pool(&deps.storage, 0)?;
 
-        pad_response(Ok(HandleResponse {
-            messages: vec![
-                snip20::transfer_msg(
-                    current_user_unwrapped.clone(),
-                    lp_balance_of_contract,
-                    None,
-                    BLOCK_SIZE,
-                    config.butt_swbtc_lp.contract_hash.clone(),
-                    config.butt_swbtc_lp.address.clone(),
-                )?,
-                snip20::send_from_msg(
-                    current_user_unwrapped,
-                    config.butt_swbtc_farm_pool.address,
-                    lp_balance_of_contract,
-                    Some(Binary::from(
-                        r#"{ "deposit_incentivized_token": {} }"#.as_bytes(),
-                    )),
-                    None,
-                    BLOCK_SIZE,
-                    config.butt_swbtc_lp.contract_hash,
-                    config.butt_swbtc_lp.address,
-                )?,
-            ],
-            log: vec![
-                log("swbtc_amount", swbtc_amount_to_provide.to_string()),
-                log("butt_amount", butt_amount_to_provide.to_string()),
-                log("lp_amount", lp_balance_of_contract.to_string()),
-            ],
-            data: None,
-        }))
-    } else {
-        Err(StdError::generic_err("Contract wasn't called properly."))
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut lp_amount: Uint128 = exit_order.amount;
+    if exit_order.token.address != pool.lp_token.address {
+        let mut vault: Vault = vault(&deps.storage)?;
+        lp_amount = lp_amount_for_shares(exit_order.amount, vault.total_shares, vault.total_lp)?;
+        vault.total_shares = (vault.total_shares - exit_order.amount)?;
+        vault.total_lp = (vault.total_lp - lp_amount)?;
+        TypedStoreMut::attach(&mut deps.storage).store(VAULT_KEY, &vault)?;
+        messages.push(snip20::burn_msg(
+            exit_order.amount,
+            None,
+            BLOCK_SIZE,
+            vault.share_token.contract_hash,
+            vault.share_token.address,
+        )?);
+        messages.push(
+            FarmHandleMsg::Withdraw {
+                incentivized_token_amount: lp_amount,
+            }
+            .to_cosmos_msg(
+                config.butt_swbtc_farm_pool.contract_hash,
+                config.butt_swbtc_farm_pool.address,
+                None,
+            )?,
+        );
+        messages.push(record_vault_rewards_msg(
+            env,
+            query_balance_of_token(deps, env.contract.address.clone(), config.butt)?,
+        )?);
     }
-}
 
-// Take a Vec<u8> and pad it up to a multiple of `block_size`, using spaces at the end.
-fn space_pad(block_size: usize, message: &mut Vec<u8>) -> &mut Vec<u8> {
-    let len = message.len();
-    let surplus = len % block_size;
-    if surplus == 0 {
-        return message;
+    let dex = dex_adapter::<Q>(&pool.dex);
+    let pair_reserves: PairReserves = dex.query_reserves(&deps.querier, &pool.trade_pair)?;
+    let [butt_amount, swbtc_amount] = liquidity_for_lp_amount(
+        lp_amount,
+        [
+            pair_reserves.reserve(&pool.tokens[0].address)?,
+            pair_reserves.reserve(&pool.tokens[1].address)?,
+        ],
+        pair_reserves.total_share,
+    )?;
+    messages.push(dex.withdraw_liquidity_msg(
+        &pool.trade_pair,
+        &pool.lp_token,
+        lp_amount,
+        &env.contract.address,
+    )?);
+    if !butt_amount.is_zero() {
+        messages.push(dex.swap_msg(
+            &pool.trade_pair,
+            &pool.tokens[0],
+            butt_amount,
+            exit_order.belief_price,
+            exit_order.max_spread,
+            Some(&exit_order.user),
+        )?);
+    }
+    if !swbtc_amount.is_zero() {
+        messages.push(snip20::transfer_msg(
+            exit_order.user,
+            swbtc_amount,
+            None,
+            BLOCK_SIZE,
+            pool.tokens[1].contract_hash.clone(),
+            pool.tokens[1].address.clone(),
+        )?);
     }
 
-    let missing = block_size - surplus;
-    message.reserve(missing);
-    message.extend(std::iter::repeat(b' ').take(missing));
-    message
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ExecuteExitOrder {
+            butt_amount,
+            swbtc_amount,
+        })?),
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::state::{ConfigPublic, SecretContract};
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage};
-    pub const MOCK_ADMIN: &str = "admin";
-    pub const MOCK_BUTT_SWBTC_TRADE_PAIR_CONTRACT_ADDRESS: &str = "mock-swbtc-address";
-    pub const MOCK_SWAP_TO_SWBTC_ADDRESS: &str = "mock-swap-to-swbtc-address";
-    pub const MOCK_VIEWING_KEY: &str = "DELIGHTFUL";
-    pub const MOCK_BUTT_ADDRESS: &str = "mock-butt-address";
-    pub const MOCK_SWBTC_ADDRESS: &str = "mock-swbtc-address";
-
-    // === HELPERS ===
-    fn init_helper() -> (
-        StdResult<InitResponse>,
-        Extern<MockStorage, MockApi, MockQuerier>,
-    ) {
-        let env = mock_env(MOCK_ADMIN, &[]);
-        let mut deps = mock_dependencies(20, &[]);
-        let msg = InitMsg {
-            butt: mock_butt(),
-            swbtc: mock_swbtc(),
-            butt_swbtc_farm_pool: mock_butt_swbtc_farm_pool(),
-            butt_swbtc_trade_pair: mock_butt_swbtc_trade_pair(),
-            butt_swbtc_lp: mock_butt_swbtc_lp(),
-            viewing_key: MOCK_VIEWING_KEY.to_string(),
-        };
-        let init_result = init(&mut deps, env.clone(), msg);
-        (init_result, deps)
+// Exits the pending exit orders the current BUTT-SWBTC price triggers, one after the other,
+// and refunds the expired ones. The caller picks at most MAX_BATCH_SIZE exit order ids,
+// which keeps the gas bounded and lets an exit order that fails be left out of a batch.
+fn execute_exit_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    exit_order_ids: Vec<u64>,
+) -> StdResult<HandleResponse> {
+    if exit_order_ids.len() > MAX_BATCH_SIZE {
+        return Err(StdError::generic_err(format!(
+            "At most {} exit orders can be executed at once.",
+            MAX_BATCH_SIZE
+        )));
     }
 
-    fn mock_butt() -> SecretContract {
-        SecretContract {
-            address: HumanAddr::from(MOCK_BUTT_ADDRESS),
-            contract_hash: "mock-butt-contract-hash".to_string(),
+    let price: Decimal = butt_swbtc_price(deps)?;
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut executed_exit_order_ids: Vec<u64> = vec![];
+    let mut expired_exit_order_ids: Vec<u64> = vec![];
+    for exit_order_id in exit_order_ids {
+        let mut exit_order: ExitOrder = exit_order(&deps.storage, exit_order_id)?;
+        if exit_order.status != OrderStatus::Pending {
+            continue;
         }
-    }
-
-    fn mock_butt_swbtc_farm_pool() -> SecretContract {
-        SecretContract {
-            address: HumanAddr::from("mock-butt-swbtc-farm-pool-address"),
-            contract_hash: "mock-butt-swbtc-farm-pool-contract-hash".to_string(),
+        if exit_order.expiry.has_passed(&env.block) {
+            exit_order.status = OrderStatus::Expired;
+            store_exit_order(&mut deps.storage, &exit_order)?;
+            messages.push(refund_exit_order_msg(exit_order)?);
+            expired_exit_order_ids.push(exit_order_id);
+        } else if exit_order.is_triggered(price) {
+            exit_order.status = OrderStatus::Executed;
+            store_exit_order(&mut deps.storage, &exit_order)?;
+            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: env.contract.address.clone(),
+                callback_code_hash: env.contract_code_hash.clone(),
+                msg: to_binary(&HandleMsg::ExecuteExitOrder { exit_order_id })?,
+                send: vec![],
+            }));
+            executed_exit_order_ids.push(exit_order_id);
         }
     }
 
-    fn mock_butt_swbtc_lp() -> SecretContract {
-        SecretContract {
-            address: HumanAddr::from(MOCK_BUTT_SWBTC_LP_ADDRESS),
-            contract_hash: "mock-butt-swbtc-lp-contract-hash".to_string(),
-        }
-    }
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ExecuteExitOrders {
+            executed_exit_order_ids,
+            expired_exit_order_ids,
+        })?),
+    })
+}
 
-    fn mock_butt_swbtc_trade_pair() -> SecretContract {
-        SecretContract {
-            address: HumanAddr::from(MOCK_BUTT_SWBTC_TRADE_PAIR_CONTRACT_ADDRESS),
-            contract_hash: "mock-butt-swbtc-trade-pair-contract-hash".to_string(),
-        }
+// Zaps the escrow of an order marked as executed by execute_orders, on behalf of its user
+fn execute_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    order_id: u64,
+) -> StdResult<HandleResponse> {
+    authorize([env.message.sender.clone()].to_vec(), &env.contract.address)?;
+    let order: Order = order(&deps.storage, order_id)?;
+    if order.status != OrderStatus::Executed {
+        return Err(StdError::generic_err(format!(
+            "Order {} is not executed.",
+            order_id
+        )));
     }
+    let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY)?;
 
-    fn mock_swbtc() -> SecretContract {
-        SecretContract {
-            address: HumanAddr::from(MOCK_SWBTC_ADDRESS),
-            contract_hash: "mock-swbtc-contract-hash".to_string(),
-        }
+    init_swap_and_provide(
+        deps,
+        env,
+        order.user,
+        order.token.address,
+        order.amount,
+        session,
+        order.zap_request,
+    )
+}
+
+// Zaps the pending orders the current BUTT-SWBTC price triggers, one after the other,
+// and refunds the expired ones. The caller picks at most MAX_BATCH_SIZE order ids,
+// which keeps the gas bounded and lets an order whose zap fails be left out of a batch.
+fn execute_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    order_ids: Vec<u64>,
+) -> StdResult<HandleResponse> {
+    if order_ids.len() > MAX_BATCH_SIZE {
+        return Err(StdError::generic_err(format!(
+            "At most {} orders can be executed at once.",
+            MAX_BATCH_SIZE
+        )));
+    }
+    let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY)?;
+    if session.current_user.is_some() {
+        return Err(StdError::generic_err("Contract is already being used."));
     }
 
-    fn mock_swap_to_swbtc_contract() -> SecretContract {
-        SecretContract {
-            address: HumanAddr::from(MOCK_SWAP_TO_SWBTC_ADDRESS),
-            contract_hash: "mock-swbtc-contract-hash".to_string(),
+    let price: Decimal = butt_swbtc_price(deps)?;
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut executed_order_ids: Vec<u64> = vec![];
+    let mut expired_order_ids: Vec<u64> = vec![];
+    for order_id in order_ids {
+        let mut order: Order = order(&deps.storage, order_id)?;
+        if order.status != OrderStatus::Pending {
+            continue;
+        }
+        if order.expiry.has_passed(&env.block) {
+            order.status = OrderStatus::Expired;
+            store_order(&mut deps.storage, &order)?;
+            messages.push(refund_order_msg(order)?);
+            expired_order_ids.push(order_id);
+        } else if order.is_triggered(price) {
+            order.status = OrderStatus::Executed;
+            store_order(&mut deps.storage, &order)?;
+            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: env.contract.address.clone(),
+                callback_code_hash: env.contract_code_hash.clone(),
+                msg: to_binary(&HandleMsg::ExecuteOrder { order_id })?,
+                send: vec![],
+            }));
+            executed_order_ids.push(order_id);
         }
     }
 
-    fn mock_user_address() -> HumanAddr {
-        HumanAddr::from("gary")
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ExecuteOrders {
+            executed_order_ids,
+            expired_order_ids,
+        })?),
+    })
+}
+
+fn exit_order<S: ReadonlyStorage>(storage: &S, exit_order_id: u64) -> StdResult<ExitOrder> {
+    let exit_orders_storage = ReadonlyPrefixedStorage::new(EXIT_ORDERS_PREFIX, storage);
+    let exit_order: Option<ExitOrder> =
+        match AppendStore::<ExitOrder, _>::attach(&exit_orders_storage) {
+            None => None,
+            Some(exit_orders_store) => {
+                let exit_orders_store = exit_orders_store?;
+                if exit_order_id < exit_orders_store.len() as u64 {
+                    Some(exit_orders_store.get_at(exit_order_id as u32)?)
+                } else {
+                    None
+                }
+            }
+        };
+
+    exit_order
+        .ok_or_else(|| StdError::generic_err(format!("Exit order {} not found.", exit_order_id)))
+}
+
+// Zaps the SWBTC the migration's tokens were routed to, which continues like any zap through SWBTC
+fn finish_migration<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+) -> StdResult<HandleResponse> {
+    authorize([env.message.sender.clone()].to_vec(), &env.contract.address)?;
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY)?;
+    let migration: Migration = match session.migration.take() {
+        Some(migration) => migration,
+        None => return Err(StdError::generic_err("Contract wasn't called properly.")),
+    };
+    validate_deadline(session.deadline, &env.block)?;
+
+    let swbtc_amount: Uint128 =
+        (query_balance_of_token(deps, env.contract.address.clone(), config.swbtc.clone())?
+            - migration.swbtc_balance)?;
+    if swbtc_amount.is_zero() {
+        return Err(StdError::generic_err(
+            "Migrated SWBTC must be greater than zero.",
+        ));
     }
+    session.swap_to_swbtc_contract_address = Some(env.contract.address.clone());
+    TypedStoreMut::attach(&mut deps.storage).store(SESSION_KEY, &session)?;
 
-    // === TESTS ===
-    #[test]
-    fn test_init() {
-        let (init_result, deps) = init_helper();
+    Ok(HandleResponse {
+        messages: vec![snip20::send_msg(
+            env.contract.address.clone(),
+            swbtc_amount,
+            None,
+            None,
+            BLOCK_SIZE,
+            config.swbtc.contract_hash,
+            config.swbtc.address,
+        )?],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::FinishMigration { swbtc_amount })?),
+    })
+}
 
-        // * it stores the correct config
-        let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
-        assert_eq!(
-            config,
-            Config {
-                admin: HumanAddr::from(MOCK_ADMIN),
-                current_user: None,
-                butt: mock_butt(),
-                swbtc: mock_swbtc(),
-                butt_swbtc_farm_pool: mock_butt_swbtc_farm_pool(),
-                butt_swbtc_trade_pair: mock_butt_swbtc_trade_pair(),
-                butt_swbtc_lp: mock_butt_swbtc_lp(),
-                swap_to_swbtc_contract_address: None,
-                butt_amount_to_provide: None,
-                swbtc_amount_to_provide: None,
-                viewing_key: MOCK_VIEWING_KEY.to_string(),
+// Claims the vault's farm rewards and compounds them, paying the caller a bounty.
+// The interval between harvests stops callers from griefing the vault with tiny swaps.
+fn harvest<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+) -> StdResult<HandleResponse> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    let mut vault: Vault = vault(&deps.storage)?;
+    let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY)?;
+    if session.current_user.is_some() {
+        return Err(StdError::generic_err("Contract is already being used."));
+    }
+    let next_harvest_time: u64 = vault
+        .last_harvest_time
+        .saturating_add(vault.harvest_interval);
+    if env.block.time < next_harvest_time {
+        return Err(StdError::generic_err(format!(
+            "Harvest is available from {}.",
+            next_harvest_time
+        )));
+    }
+    vault.last_harvest_time = env.block.time;
+    TypedStoreMut::attach(&mut deps.storage).store(VAULT_KEY, &vault)?;
+    // Only what the farm pays out on top of this is compounded
+    let butt_balance: Uint128 =
+        query_balance_of_token(deps, env.contract.address.clone(), config.butt.clone())?;
+
+    Ok(HandleResponse {
+        messages: vec![
+            FarmHandleMsg::Withdraw {
+                incentivized_token_amount: Uint128(0),
             }
+            .to_cosmos_msg(
+                config.butt_swbtc_farm_pool.contract_hash,
+                config.butt_swbtc_farm_pool.address,
+                None,
+            )?,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: env.contract.address.clone(),
+                callback_code_hash: env.contract_code_hash.clone(),
+                msg: to_binary(&HandleMsg::CompoundHarvestedRewards {
+                    harvester: env.message.sender.clone(),
+                    butt_balance,
+                })?,
+                send: vec![],
+            }),
+        ],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Harvest { status: Success })?),
+    })
+}
+
+fn increase_allowance_for_pair_contract<S: Storage, A: Api, Q: Querier>(
+    deps: &mut cosmwasm_std::Extern<S, A, Q>,
+    env: &Env,
+    pool_id: u32,
+) -> StdResult<HandleResponse> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    authorize(vec![config.admin], &env.message.sender)?;
+
+    Ok(HandleResponse {
+        messages: increase_allowance_msgs(&pool(&deps.storage, pool_id)?)?,
+        log: vec![],
+        data: Some(to_binary(
+            &HandleAnswer::IncreaseAllowanceForPairContract { status: Success },
+        )?),
+    })
+}
+
+fn increase_allowance_msgs(pool: &Pool) -> StdResult<Vec<CosmosMsg>> {
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for token in pool.tokens.iter() {
+        messages.push(secret_toolkit::snip20::increase_allowance_msg(
+            pool.trade_pair.address.clone(),
+            Uint128(u128::MAX),
+            None,
+            None,
+            BLOCK_SIZE,
+            token.contract_hash.clone(),
+            token.address.clone(),
+        )?);
+    }
+
+    Ok(messages)
+}
+
+// Withdraws the liquidity of a registered pool's LP token from its trade pair, routes both
+// of its tokens to SWBTC, then zaps the SWBTC into the BUTT-SWBTC farm, all in one session
+fn migrate_liquidity<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    from: HumanAddr,
+    amount: Uint128,
+    config: Config,
+    mut session: Session,
+    migration_request: MigrationRequest,
+) -> StdResult<HandleResponse> {
+    validate_not_deprecated(&deps.storage)?;
+    let butt_swbtc_pool: Pool = pool(&deps.storage, 0)?;
+    let pool: Pool = match pools(&deps.storage)?
+        .into_iter()
+        .find(|pool| pool.id != 0 && pool.lp_token.address == env.message.sender)
+    {
+        Some(pool) => pool,
+        None => {
+            return Err(StdError::generic_err(format!(
+                "Token {} is not the LP token of a pool to migrate from.",
+                env.message.sender
+            )))
+        }
+    };
+    if session.current_user.is_some() {
+        return Err(StdError::generic_err("Contract is already being used."));
+    }
+    validate_deadline(migration_request.deadline, &env.block)?;
+    if let Some(recipient) = &migration_request.recipient {
+        deps.api.canonical_address(recipient)?;
+    }
+    let swap_to_swbtc_contract: SecretContract = migration_request.swap_to_swbtc_contract;
+    validate_swap_router(&deps.storage, &swap_to_swbtc_contract.address)?;
+    validate_recipient(
+        migration_request.recipient.as_ref(),
+        &from,
+        &butt_swbtc_pool.deposit_target,
+    )?;
+
+    // Balances before the withdrawal, so that only the migrated liquidity is zapped
+    let mut token_balances: [Uint128; 2] = [Uint128(0), Uint128(0)];
+    for (token_balance, token) in token_balances.iter_mut().zip(pool.tokens.iter()) {
+        *token_balance = query_balance_of_token(deps, env.contract.address.clone(), token.clone())?;
+    }
+    let swbtc_balance: Uint128 =
+        query_balance_of_token(deps, env.contract.address.clone(), config.swbtc.clone())?;
+
+    session.recipient = Some(migration_request.recipient.unwrap_or_else(|| from.clone()));
+    session.current_user = Some(from);
+    session.id += 1;
+    session.pool_id = 0;
+    session.intermediate_token = Some(config.swbtc.address);
+    session.swap_to_swbtc_contract_address = Some(swap_to_swbtc_contract.address);
+    session.belief_price = migration_request.belief_price;
+    session.max_spread = migration_request.max_spread;
+    session.max_price_impact = migration_request.max_price_impact;
+    session.deadline = migration_request.deadline;
+    session.hide_amounts = migration_request.hide_amounts.unwrap_or(false);
+    session.migration = Some(Migration {
+        pool_id: pool.id,
+        swap_to_swbtc_msgs: migration_request.swap_to_swbtc_msgs,
+        token_balances,
+        swbtc_balance,
+    });
+    TypedStoreMut::attach(&mut deps.storage).store(SESSION_KEY, &session)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![dex_adapter::<Q>(&pool.dex).withdraw_liquidity_msg(
+        &pool.trade_pair,
+        &pool.lp_token,
+        amount,
+        &env.contract.address,
+    )?];
+    for msg in [
+        HandleMsg::RouteMigratedTokens {},
+        HandleMsg::FinishMigration {},
+        HandleMsg::SendLpToUserThenDepositIntoFarmContract {},
+    ]
+    .iter()
+    {
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: env.contract.address.clone(),
+            callback_code_hash: env.contract_code_hash.clone(),
+            msg: to_binary(msg)?,
+            send: vec![],
+        }));
+    }
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::MigrateLiquidity {
+            session_id: session.id,
+        })?),
+    })
+}
+
+// Upgrades the storage written by an earlier version of the contract to the current layout
+fn migrate_state<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+) -> StdResult<HandleResponse> {
+    let storage_version: u32 = storage_version(&deps.storage)?;
+    if storage_version >= STORAGE_VERSION {
+        return Err(StdError::generic_err("Storage is already up to date."));
+    }
+    if storage_version == 2 {
+        return migrate_state_from_v2(deps, env);
+    }
+    // Version 1 kept the settings and the zap in progress in a single config
+    let config_v1: ConfigV1 = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    authorize(vec![config_v1.admin.clone()], &env.message.sender)?;
+    if config_v1.current_user.is_some() {
+        return Err(StdError::generic_err("Contract is already being used."));
+    }
+
+    // Settings missing from version 1 keep its behaviour until the admin updates them
+    let config: Config = Config {
+        admin: config_v1.admin,
+        contract_address: env.contract.address.clone(),
+        butt: config_v1.butt,
+        swbtc: config_v1.swbtc,
+        butt_swbtc_farm_pool: config_v1.butt_swbtc_farm_pool,
+        butt_swbtc_trade_pair: config_v1.butt_swbtc_trade_pair,
+        butt_swbtc_lp: config_v1.butt_swbtc_lp,
+        max_price_impact: Decimal::one(),
+        hide_amounts: false,
+        viewing_key: config_v1.viewing_key,
+    };
+
+    Ok(HandleResponse {
+        messages: store_initial_state(&mut deps.storage, config, &env.contract_code_hash)?,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::MigrateState {
+            storage_version: STORAGE_VERSION,
+        })?),
+    })
+}
+
+// Version 2 stored pools without a deposit target, DEX or invariant, and a session
+// without the pool, vault, migration or amounts to provide by pool token
+fn migrate_state_from_v2<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+) -> StdResult<HandleResponse> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    authorize(vec![config.admin.clone()], &env.message.sender)?;
+    let session_v2: SessionV2 = TypedStore::attach(&deps.storage).load(SESSION_KEY)?;
+    if session_v2.current_user.is_some() {
+        return Err(StdError::generic_err("Contract is already being used."));
+    }
+
+    let pools_storage = ReadonlyPrefixedStorage::new(POOLS_PREFIX, &deps.storage);
+    let pools_v2: Vec<PoolV2> = match AppendStore::<PoolV2, _>::attach(&pools_storage) {
+        Some(pools_store) => pools_store?.iter().collect::<StdResult<Vec<PoolV2>>>()?,
+        None => vec![],
+    };
+    // Version 2 only zapped into SecretSwap constant product pools, depositing the LP of
+    // pool 0 into the BUTT-SWBTC farm and sending the LP of the others to the wallet
+    let mut pools_storage = PrefixedStorage::new(POOLS_PREFIX, &mut deps.storage);
+    let mut pools_store = AppendStoreMut::<Pool, _>::attach_or_create(&mut pools_storage)?;
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for pool_v2 in pools_v2 {
+        messages.push(snip20::register_receive_msg(
+            env.contract_code_hash.clone(),
+            None,
+            BLOCK_SIZE,
+            pool_v2.lp_token.contract_hash.clone(),
+            pool_v2.lp_token.address.clone(),
+        )?);
+        let deposit_target: DepositTarget = if pool_v2.id == 0 {
+            DepositTarget::Farm(config.butt_swbtc_farm_pool.clone())
+        } else {
+            DepositTarget::Wallet
+        };
+        pools_store.set_at(
+            pool_v2.id,
+            &Pool {
+                id: pool_v2.id,
+                trade_pair: pool_v2.trade_pair,
+                tokens: pool_v2.tokens,
+                lp_token: pool_v2.lp_token,
+                deposit_target,
+                dex: Dex::SecretSwap,
+                invariant: Invariant::ConstantProduct,
+            },
+        )?;
+    }
+    let session: Session = Session {
+        id: session_v2.id,
+        ..Session::default()
+    };
+    TypedStoreMut::attach(&mut deps.storage).store(SESSION_KEY, &session)?;
+    TypedStoreMut::attach(&mut deps.storage).store(STORAGE_VERSION_KEY, &STORAGE_VERSION)?;
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::MigrateState {
+            storage_version: STORAGE_VERSION,
+        })?),
+    })
+}
+
+fn order<S: ReadonlyStorage>(storage: &S, order_id: u64) -> StdResult<Order> {
+    let orders_storage = ReadonlyPrefixedStorage::new(ORDERS_PREFIX, storage);
+    let order: Option<Order> = match AppendStore::<Order, _>::attach(&orders_storage) {
+        None => None,
+        Some(orders_store) => {
+            let orders_store = orders_store?;
+            if order_id < orders_store.len() as u64 {
+                Some(orders_store.get_at(order_id as u32)?)
+            } else {
+                None
+            }
+        }
+    };
+
+    order.ok_or_else(|| StdError::generic_err(format!("Order {} not found.", order_id)))
+}
+
+// Escrows BUTT-SWBTC LP, or vault shares whose LP the vault holds in the farm
+fn place_exit_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    from: HumanAddr,
+    amount: Uint128,
+    exit_order_request: ExitOrderRequest,
+) -> StdResult<HandleResponse> {
+    validate_not_deprecated(&deps.storage)?;
+    let pool: Pool = pool(&deps.storage, 0)?;
+    let vault: Option<Vault> = TypedStore::attach(&deps.storage).may_load(VAULT_KEY)?;
+    let (token, lp_amount): (SecretContract, Uint128) = match vault {
+        _ if env.message.sender == pool.lp_token.address => (pool.lp_token, amount),
+        Some(vault) if env.message.sender == vault.share_token.address => (
+            vault.share_token,
+            lp_amount_for_shares(amount, vault.total_shares, vault.total_lp)?,
+        ),
+        _ => {
+            return Err(StdError::generic_err(format!(
+                "Token {} is neither the BUTT-SWBTC LP nor the vault's share token.",
+                env.message.sender
+            )))
+        }
+    };
+    if lp_amount.is_zero() {
+        return Err(StdError::generic_err(
+            "LP amount to exit must be greater than zero.",
+        ));
+    }
+    if exit_order_request.expiry.has_passed(&env.block) {
+        return Err(StdError::generic_err("Exit order expiry has passed."));
+    }
+
+    let mut exit_orders_storage = PrefixedStorage::new(EXIT_ORDERS_PREFIX, &mut deps.storage);
+    let mut exit_orders_store =
+        AppendStoreMut::<ExitOrder, _>::attach_or_create(&mut exit_orders_storage)?;
+    let exit_order: ExitOrder = ExitOrder {
+        id: exit_orders_store.len() as u64,
+        user: from,
+        token,
+        amount,
+        target_price: exit_order_request.target_price,
+        trigger: exit_order_request.trigger,
+        belief_price: exit_order_request.belief_price,
+        max_spread: exit_order_request.max_spread,
+        expiry: exit_order_request.expiry,
+        status: OrderStatus::Pending,
+    };
+    exit_orders_store.push(&exit_order)?;
+    let mut user_exit_orders_storage = PrefixedStorage::multilevel(
+        &[USER_EXIT_ORDERS_PREFIX, exit_order.user.0.as_bytes()],
+        &mut deps.storage,
+    );
+    AppendStoreMut::attach_or_create(&mut user_exit_orders_storage)?.push(&exit_order.id)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::PlaceExitOrder {
+            exit_order_id: exit_order.id,
+        })?),
+    })
+}
+
+fn place_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    from: HumanAddr,
+    amount: Uint128,
+    order_request: OrderRequest,
+) -> StdResult<HandleResponse> {
+    validate_not_deprecated(&deps.storage)?;
+    let token: RegisteredToken = match registered_token(&deps.storage, &env.message.sender)? {
+        Some(token) => token,
+        None => {
+            return Err(StdError::generic_err(format!(
+                "Token {} is not registered.",
+                env.message.sender
+            )))
+        }
+    };
+    validate_zap_request(deps, &from, &token.address, &order_request.zap_request)?;
+    if order_request.expiry.has_passed(&env.block) {
+        return Err(StdError::generic_err("Order expiry has passed."));
+    }
+
+    let mut orders_storage = PrefixedStorage::new(ORDERS_PREFIX, &mut deps.storage);
+    let mut orders_store = AppendStoreMut::<Order, _>::attach_or_create(&mut orders_storage)?;
+    let order: Order = Order {
+        id: orders_store.len() as u64,
+        user: from,
+        token: SecretContract {
+            address: token.address,
+            contract_hash: token.contract_hash,
+        },
+        amount,
+        target_price: order_request.target_price,
+        trigger: order_request.trigger,
+        expiry: order_request.expiry,
+        zap_request: order_request.zap_request,
+        status: OrderStatus::Pending,
+    };
+    orders_store.push(&order)?;
+    let mut user_orders_storage = PrefixedStorage::multilevel(
+        &[USER_ORDERS_PREFIX, order.user.0.as_bytes()],
+        &mut deps.storage,
+    );
+    AppendStoreMut::attach_or_create(&mut user_orders_storage)?.push(&order.id)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::PlaceOrder { order_id: order.id })?),
+    })
+}
+
+// How much of amount a zap swaps, what the swap returns and its price impact, by the pool's invariant.
+// Constant product pools swap half, stable swap pools swap to the ratio of their reserves after the swap.
+fn plan_swap(
+    invariant: &Invariant,
+    amount: Uint128,
+    intermediate_token_reserve: Uint128,
+    paired_token_reserve: Uint128,
+    commission_rate: Decimal,
+) -> StdResult<(Uint128, Uint128, Decimal)> {
+    match invariant {
+        Invariant::ConstantProduct => {
+            let amount_to_swap: Uint128 = Uint128(amount.u128() / 2);
+            Ok((
+                amount_to_swap,
+                swap_return_amount(
+                    amount_to_swap,
+                    intermediate_token_reserve,
+                    paired_token_reserve,
+                    commission_rate,
+                )?,
+                price_impact(amount_to_swap, intermediate_token_reserve),
+            ))
+        }
+        Invariant::StableSwap { amplification } => {
+            let amount_to_swap: Uint128 = stable_swap_split(
+                amount,
+                intermediate_token_reserve,
+                paired_token_reserve,
+                *amplification,
+                commission_rate,
+            )?;
+            Ok((
+                amount_to_swap,
+                stable_swap_return_amount(
+                    amount_to_swap,
+                    intermediate_token_reserve,
+                    paired_token_reserve,
+                    *amplification,
+                    commission_rate,
+                )?,
+                stable_swap_price_impact(
+                    amount_to_swap,
+                    intermediate_token_reserve,
+                    paired_token_reserve,
+                    *amplification,
+                )?,
+            ))
+        }
+    }
+}
+
+fn pool<S: ReadonlyStorage>(storage: &S, pool_id: u32) -> StdResult<Pool> {
+    let pools_storage = ReadonlyPrefixedStorage::new(POOLS_PREFIX, storage);
+    if let Some(pools_store) = AppendStore::<Pool, _>::attach(&pools_storage) {
+        let pools_store = pools_store?;
+        if pool_id < pools_store.len() {
+            return pools_store.get_at(pool_id);
+        }
+    }
+
+    Err(StdError::generic_err(format!(
+        "Pool {} not found.",
+        pool_id
+    )))
+}
+
+// Sets the contract's viewing key on the pool's tokens and lets the trade pair spend them
+// Exit orders and migrations send the LP token to the contract, which receives it without
+// registering it as a token to zap
+fn pool_setup_msgs(
+    pool: &Pool,
+    viewing_key: &str,
+    contract_code_hash: &str,
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut messages: Vec<CosmosMsg> = set_viewing_key_msgs(&pool_tokens(pool), viewing_key)?;
+    messages.extend(increase_allowance_msgs(pool)?);
+    messages.push(snip20::register_receive_msg(
+        contract_code_hash.to_string(),
+        None,
+        BLOCK_SIZE,
+        pool.lp_token.contract_hash.clone(),
+        pool.lp_token.address.clone(),
+    )?);
+
+    Ok(messages)
+}
+
+// The pool's tokens followed by its LP token
+fn pool_tokens(pool: &Pool) -> Vec<SecretContract> {
+    let mut tokens: Vec<SecretContract> = pool.tokens.to_vec();
+    tokens.push(pool.lp_token.clone());
+    tokens
+}
+
+fn pools<S: ReadonlyStorage>(storage: &S) -> StdResult<Vec<Pool>> {
+    let pools_storage = ReadonlyPrefixedStorage::new(POOLS_PREFIX, storage);
+    match AppendStore::<Pool, _>::attach(&pools_storage) {
+        Some(pools_store) => pools_store?.iter().collect(),
+        None => Ok(vec![]),
+    }
+}
+
+// Zaps an installment of every subscription that's due, one after the other. The caller picks
+// at most MAX_BATCH_SIZE subscription ids, so an installment whose zap fails can be left out.
+fn process_subscriptions<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    subscription_ids: Vec<u64>,
+) -> StdResult<HandleResponse> {
+    if subscription_ids.len() > MAX_BATCH_SIZE {
+        return Err(StdError::generic_err(format!(
+            "At most {} subscriptions can be processed at once.",
+            MAX_BATCH_SIZE
+        )));
+    }
+    let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY)?;
+    if session.current_user.is_some() {
+        return Err(StdError::generic_err("Contract is already being used."));
+    }
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut processed_subscription_ids: Vec<u64> = vec![];
+    for subscription_id in subscription_ids {
+        let mut subscription: Subscription = subscription(&deps.storage, subscription_id)?;
+        if !subscription.is_due(&env.block) {
+            continue;
+        }
+
+        let amount: Uint128 = subscription.installment_amount();
+        subscription.balance = (subscription.balance - amount)?;
+        subscription.next_installment_time = env.block.time.saturating_add(subscription.period);
+        if subscription.balance.is_zero() {
+            subscription.status = SubscriptionStatus::Completed;
+        }
+        store_subscription(&mut deps.storage, &subscription)?;
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: env.contract.address.clone(),
+            callback_code_hash: env.contract_code_hash.clone(),
+            msg: to_binary(&HandleMsg::ZapInstallment {
+                subscription_id,
+                amount,
+            })?,
+            send: vec![],
+        }));
+        processed_subscription_ids.push(subscription_id);
+    }
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ProcessSubscriptions {
+            processed_subscription_ids,
+        })?),
+    })
+}
+
+fn remove_swap_routers<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    routers: Vec<HumanAddr>,
+) -> StdResult<HandleResponse> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    authorize(vec![config.admin], &env.message.sender)?;
+
+    let mut swap_routers: Vec<HumanAddr> =
+        TypedStore::attach(&deps.storage).load(SWAP_ROUTERS_KEY)?;
+    swap_routers.retain(|router| !routers.contains(router));
+    TypedStoreMut::attach(&mut deps.storage).store(SWAP_ROUTERS_KEY, &swap_routers)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::RemoveSwapRouters {
+            status: Success,
+        })?),
+    })
+}
+
+fn rescue_tokens<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    amount: Uint128,
+    denom: Option<String>,
+    token: Option<SecretContract>,
+) -> StdResult<HandleResponse> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+    authorize(vec![config.admin.clone()], &env.message.sender)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if let Some(denom_unwrapped) = denom {
+        let withdrawal_coin: Vec<Coin> = vec![Coin {
+            amount,
+            denom: denom_unwrapped,
+        }];
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            from_address: env.contract.address.clone(),
+            to_address: config.admin.clone(),
+            amount: withdrawal_coin,
+        }));
+    }
+
+    if let Some(token_unwrapped) = token {
+        messages.push(snip20::transfer_msg(
+            config.admin,
+            amount,
+            None,
+            BLOCK_SIZE,
+            token_unwrapped.contract_hash,
+            token_unwrapped.address,
+        )?)
+    }
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::RescueTokens { status: Success })?),
+    })
+}
+
+fn store_exit_order<S: Storage>(storage: &mut S, exit_order: &ExitOrder) -> StdResult<()> {
+    let mut exit_orders_storage = PrefixedStorage::new(EXIT_ORDERS_PREFIX, storage);
+    AppendStoreMut::<ExitOrder, _>::attach_or_create(&mut exit_orders_storage)?
+        .set_at(exit_order.id as u32, exit_order)
+}
+
+// Stores the config with an empty session, swap router allowlist and the BUTT-SWBTC pool,
+// returning the messages that set up the pool's tokens
+fn store_initial_state<S: Storage>(
+    storage: &mut S,
+    config: Config,
+    contract_code_hash: &str,
+) -> StdResult<Vec<CosmosMsg>> {
+    TypedStoreMut::attach(storage).store(CONFIG_KEY, &config)?;
+    TypedStoreMut::attach(storage).store(SESSION_KEY, &Session::default())?;
+    let swap_routers: Vec<HumanAddr> = vec![];
+    TypedStoreMut::attach(storage).store(SWAP_ROUTERS_KEY, &swap_routers)?;
+    // The BUTT-SWBTC trade pair is always pool 0
+    let pool: Pool = store_pool(
+        storage,
+        config.butt_swbtc_trade_pair,
+        [config.butt, config.swbtc],
+        config.butt_swbtc_lp,
+        DepositTarget::Farm(config.butt_swbtc_farm_pool),
+        Dex::SecretSwap,
+        Invariant::ConstantProduct,
+    )?;
+    store_token_viewing_keys(storage, &pool_tokens(&pool), &config.viewing_key)?;
+    TypedStoreMut::attach(storage).store(STORAGE_VERSION_KEY, &STORAGE_VERSION)?;
+
+    pool_setup_msgs(&pool, &config.viewing_key, contract_code_hash)
+}
+
+fn store_order<S: Storage>(storage: &mut S, order: &Order) -> StdResult<()> {
+    let mut orders_storage = PrefixedStorage::new(ORDERS_PREFIX, storage);
+    AppendStoreMut::<Order, _>::attach_or_create(&mut orders_storage)?
+        .set_at(order.id as u32, order)
+}
+
+fn store_pool<S: Storage>(
+    storage: &mut S,
+    trade_pair: SecretContract,
+    tokens: [SecretContract; 2],
+    lp_token: SecretContract,
+    deposit_target: DepositTarget,
+    dex: Dex,
+    invariant: Invariant,
+) -> StdResult<Pool> {
+    let mut pools_storage = PrefixedStorage::new(POOLS_PREFIX, storage);
+    let mut pools_store = AppendStoreMut::attach_or_create(&mut pools_storage)?;
+    let pool: Pool = Pool {
+        id: pools_store.len(),
+        trade_pair,
+        tokens,
+        lp_token,
+        deposit_target,
+        dex,
+        invariant,
+    };
+    pools_store.push(&pool)?;
+
+    Ok(pool)
+}
+
+fn store_subscription<S: Storage>(storage: &mut S, subscription: &Subscription) -> StdResult<()> {
+    let mut subscriptions_storage = PrefixedStorage::new(SUBSCRIPTIONS_PREFIX, storage);
+    AppendStoreMut::<Subscription, _>::attach_or_create(&mut subscriptions_storage)?
+        .set_at(subscription.id as u32, subscription)
+}
+
+fn store_token_viewing_keys<S: Storage>(
+    storage: &mut S,
+    tokens: &[SecretContract],
+    viewing_key: &str,
+) -> StdResult<()> {
+    let mut token_viewing_keys_storage = PrefixedStorage::new(TOKEN_VIEWING_KEYS_PREFIX, storage);
+    let mut token_viewing_keys_store = TypedStoreMut::attach(&mut token_viewing_keys_storage);
+    for token in tokens {
+        token_viewing_keys_store.store(token.address.0.as_bytes(), &viewing_key.to_string())?;
+    }
+
+    Ok(())
+}
+
+fn subscribe<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    from: HumanAddr,
+    amount: Uint128,
+    subscription_request: SubscriptionRequest,
+) -> StdResult<HandleResponse> {
+    validate_not_deprecated(&deps.storage)?;
+    let token: RegisteredToken = match registered_token(&deps.storage, &env.message.sender)? {
+        Some(token) => token,
+        None => {
+            return Err(StdError::generic_err(format!(
+                "Token {} is not registered.",
+                env.message.sender
+            )))
+        }
+    };
+    validate_zap_request(
+        deps,
+        &from,
+        &token.address,
+        &subscription_request.zap_request,
+    )?;
+    if subscription_request.amount_per_period.is_zero()
+        || subscription_request.periods == 0
+        || subscription_request.period == 0
+    {
+        return Err(StdError::generic_err(
+            "Amount per period, periods and period must be greater than zero.",
+        ));
+    }
+    if Some(amount.u128())
+        != subscription_request
+            .amount_per_period
+            .u128()
+            .checked_mul(subscription_request.periods as u128)
+    {
+        return Err(StdError::generic_err(
+            "Amount must be the amount per period times the periods.",
+        ));
+    }
+    // A deadline would revert every installment processed after it
+    if subscription_request.zap_request.deadline.is_some() {
+        return Err(StdError::generic_err(
+            "Subscriptions can't have a zap deadline.",
+        ));
+    }
+
+    let mut subscriptions_storage = PrefixedStorage::new(SUBSCRIPTIONS_PREFIX, &mut deps.storage);
+    let mut subscriptions_store =
+        AppendStoreMut::<Subscription, _>::attach_or_create(&mut subscriptions_storage)?;
+    let subscription: Subscription = Subscription {
+        id: subscriptions_store.len() as u64,
+        user: from,
+        token: SecretContract {
+            address: token.address,
+            contract_hash: token.contract_hash,
+        },
+        amount_per_period: subscription_request.amount_per_period,
+        period: subscription_request.period,
+        balance: amount,
+        next_installment_time: env.block.time,
+        zap_request: subscription_request.zap_request,
+        status: SubscriptionStatus::Active,
+    };
+    subscriptions_store.push(&subscription)?;
+    let mut user_subscriptions_storage = PrefixedStorage::multilevel(
+        &[USER_SUBSCRIPTIONS_PREFIX, subscription.user.0.as_bytes()],
+        &mut deps.storage,
+    );
+    AppendStoreMut::attach_or_create(&mut user_subscriptions_storage)?.push(&subscription.id)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Subscribe {
+            subscription_id: subscription.id,
+        })?),
+    })
+}
+
+fn subscription<S: ReadonlyStorage>(storage: &S, subscription_id: u64) -> StdResult<Subscription> {
+    let subscriptions_storage = ReadonlyPrefixedStorage::new(SUBSCRIPTIONS_PREFIX, storage);
+    let subscription: Option<Subscription> =
+        match AppendStore::<Subscription, _>::attach(&subscriptions_storage) {
+            None => None,
+            Some(subscriptions_store) => {
+                let subscriptions_store = subscriptions_store?;
+                if subscription_id < subscriptions_store.len() as u64 {
+                    Some(subscriptions_store.get_at(subscription_id as u32)?)
+                } else {
+                    None
+                }
+            }
+        };
+
+    subscription.ok_or_else(|| {
+        StdError::generic_err(format!("Subscription {} not found.", subscription_id))
+    })
+}
+
+fn swap_half_of_intermediate_token<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    from: HumanAddr,
+    amount: Uint128,
+    config: Config,
+    pool: Pool,
+    mut session: Session,
+) -> StdResult<HandleResponse> {
+    // Test that it's sent from swap_to_swbtc_contract_address
+    if session.swap_to_swbtc_contract_address.is_none() {
+        return Err(StdError::generic_err("Swap to SWBTC contract missing."));
+    }
+    authorize(
+        [from].to_vec(),
+        &session.swap_to_swbtc_contract_address.clone().unwrap(),
+    )?;
+    validate_deadline(session.deadline, &env.block)?;
+
+    let intermediate_token: SecretContract = session.intermediate_token(&pool);
+    let dex: Box<dyn DexAdapter<Q>> = dex_adapter(&pool.dex);
+    let pair_reserves: PairReserves = dex.query_reserves(&deps.querier, &pool.trade_pair)?;
+    let (amount_to_swap, _return_amount, swap_price_impact): (Uint128, Uint128, Decimal) =
+        plan_swap(
+            &pool.invariant,
+            amount,
+            pair_reserves.reserve(&intermediate_token.address)?,
+            pair_reserves.reserve(&session.paired_token(&pool).address)?,
+            dex.commission_rate(),
+        )?;
+    let amount_to_provide: Uint128 = (amount - amount_to_swap)?;
+    // Make sure swapping doesn't move the trade pair's price too much
+    let max_price_impact: Decimal = session.effective_max_price_impact(&config);
+    if swap_price_impact > max_price_impact {
+        return Err(StdError::generic_err(format!(
+            "Price impact of {} exceeds maximum of {}.",
+            swap_price_impact, max_price_impact
+        )));
+    }
+    session.amounts_to_provide[session.intermediate_token_index(&pool)] = Some(amount_to_provide);
+    TypedStoreMut::attach(&mut deps.storage).store(SESSION_KEY, &session)?;
+
+    Ok(HandleResponse {
+        messages: vec![dex.swap_msg(
+            &pool.trade_pair,
+            &intermediate_token,
+            amount_to_swap,
+            session.belief_price,
+            session.max_spread,
+            None,
+        )?],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::SwapHalfOfIntermediateToken {
+            amount_to_swap,
+            amount_to_provide,
+        })?),
+    })
+}
+
+fn pad_response(response: StdResult<HandleResponse>) -> StdResult<HandleResponse> {
+    response.map(|mut response| {
+        response.data = response.data.map(|mut data| {
+            space_pad(BLOCK_SIZE, &mut data.0);
+            data
+        });
+        response
+    })
+}
+
+fn provide_liquidity_to_trade_pair<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    from: HumanAddr,
+    amount: Uint128,
+    pool: Pool,
+    mut session: Session,
+) -> StdResult<HandleResponse> {
+    // Test that the sender is from the trade pair
+    authorize([from].to_vec(), &pool.trade_pair.address)?;
+    validate_deadline(session.deadline, &env.block)?;
+
+    // The amount received is the result of swapping half of the intermediate token
+    let intermediate_token_index: usize = session.intermediate_token_index(&pool);
+    let mut amounts_to_provide: [Uint128; 2] = [amount, amount];
+    amounts_to_provide[intermediate_token_index] =
+        match session.amounts_to_provide[intermediate_token_index] {
+            Some(amount_to_provide) => amount_to_provide,
+            None => {
+                return Err(StdError::generic_err(format!(
+                    "Amount of {} to provide is missing.",
+                    pool.tokens[intermediate_token_index].address
+                )))
+            }
+        };
+    for (token, amount_to_provide) in pool.tokens.iter().zip(amounts_to_provide.iter()) {
+        if amount_to_provide.is_zero() {
+            return Err(StdError::generic_err(format!(
+                "Amount of {} to provide must be greater than zero.",
+                token.address
+            )));
+        }
+    }
+
+    session.amounts_to_provide = [Some(amounts_to_provide[0]), Some(amounts_to_provide[1])];
+    session.lp_balance = Some(query_balance_of_token(
+        deps,
+        env.contract.address.clone(),
+        pool.lp_token.clone(),
+    )?);
+    TypedStoreMut::attach(&mut deps.storage).store(SESSION_KEY, &session)?;
+    // Provide liquidity to farm contract
+    let cosmos_msg = dex_adapter::<Q>(&pool.dex).provide_liquidity_msg(
+        &pool.trade_pair,
+        [
+            (&pool.tokens[1], amounts_to_provide[1]),
+            (&pool.tokens[0], amounts_to_provide[0]),
+        ],
+    )?;
+
+    Ok(HandleResponse {
+        messages: vec![cosmos_msg],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ProvideLiquidityToTradePair {
+            butt_amount: amounts_to_provide[0],
+            swbtc_amount: amounts_to_provide[1],
+        })?),
+    })
+}
+
+fn query_balance_of_token<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+    token: SecretContract,
+) -> StdResult<Uint128> {
+    if token.address == HumanAddr::from(MOCK_BUTT_SWBTC_LP_ADDRESS) {
+        Ok(Uint128(MOCK_AMOUNT))
+    } else {
+        let balance = snip20::balance_query(
+            &deps.querier,
+            address,
+            token_viewing_key(&deps.storage, &token.address)?,
+            BLOCK_SIZE,
+            token.contract_hash,
+            token.address,
+        )?;
+        Ok(balance.amount)
+    }
+}
+
+// Adds what the farm paid out on top of butt_balance to the vault's pending rewards.
+// Called right after each deposit into or withdrawal from the farm by the vault.
+fn record_vault_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    butt_balance: Uint128,
+) -> StdResult<HandleResponse> {
+    authorize([env.message.sender.clone()].to_vec(), &env.contract.address)?;
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    let mut vault: Vault = vault(&deps.storage)?;
+    let reward_amount: Uint128 =
+        (query_balance_of_token(deps, env.contract.address.clone(), config.butt)? - butt_balance)?;
+    vault.pending_rewards += reward_amount;
+    TypedStoreMut::attach(&mut deps.storage).store(VAULT_KEY, &vault)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::RecordVaultRewards {
+            reward_amount,
+        })?),
+    })
+}
+
+fn record_vault_rewards_msg(env: &Env, butt_balance: Uint128) -> StdResult<CosmosMsg> {
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.clone(),
+        callback_code_hash: env.contract_code_hash.clone(),
+        msg: to_binary(&HandleMsg::RecordVaultRewards { butt_balance })?,
+        send: vec![],
+    }))
+}
+
+fn record_zap<S: Storage>(storage: &mut S, address: &HumanAddr, zap: &Zap) -> StdResult<()> {
+    let mut zaps_storage =
+        PrefixedStorage::multilevel(&[ZAPS_PREFIX, address.0.as_bytes()], storage);
+    let mut zaps_store = AppendStoreMut::attach_or_create(&mut zaps_storage)?;
+    zaps_store.push(zap)
+}
+
+fn refund_exit_order_msg(exit_order: ExitOrder) -> StdResult<CosmosMsg> {
+    snip20::transfer_msg(
+        exit_order.user,
+        exit_order.amount,
+        None,
+        BLOCK_SIZE,
+        exit_order.token.contract_hash,
+        exit_order.token.address,
+    )
+}
+
+fn refund_order_msg(order: Order) -> StdResult<CosmosMsg> {
+    snip20::transfer_msg(
+        order.user,
+        order.amount,
+        None,
+        BLOCK_SIZE,
+        order.token.contract_hash,
+        order.token.address,
+    )
+}
+
+fn refund_subscription_msg(subscription: &Subscription) -> StdResult<CosmosMsg> {
+    snip20::transfer_msg(
+        subscription.user.clone(),
+        subscription.balance,
+        None,
+        BLOCK_SIZE,
+        subscription.token.contract_hash.clone(),
+        subscription.token.address.clone(),
+    )
+}
+
+fn register_tokens<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    tokens: Vec<SecretContract>,
+) -> StdResult<HandleResponse> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    authorize(vec![config.admin], &env.message.sender)?;
+
+    let mut messages = vec![];
+    for token in tokens {
+        let address = token.address;
+        let contract_hash = token.contract_hash;
+        let token_info = snip20::token_info_query(
+            &deps.querier,
+            BLOCK_SIZE,
+            contract_hash.clone(),
+            address.clone(),
+        )?;
+        if registered_token(&deps.storage, &address)?.is_none() {
+            let mut registered_tokens_list_storage =
+                PrefixedStorage::new(REGISTERED_TOKENS_LIST_PREFIX, &mut deps.storage);
+            AppendStoreMut::attach_or_create(&mut registered_tokens_list_storage)?
+                .push(&address)?;
+        }
+        let mut registered_tokens_storage =
+            PrefixedStorage::new(REGISTERED_TOKENS_PREFIX, &mut deps.storage);
+        TypedStoreMut::attach(&mut registered_tokens_storage).store(
+            address.0.as_bytes(),
+            &RegisteredToken {
+                address: address.clone(),
+                contract_hash: contract_hash.clone(),
+                symbol: token_info.symbol,
+                decimals: token_info.decimals,
+            },
+        )?;
+        messages.push(snip20::register_receive_msg(
+            env.contract_code_hash.clone(),
+            None,
+            BLOCK_SIZE,
+            contract_hash.clone(),
+            address.clone(),
+        )?);
+    }
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::RegisterTokens {
+            status: Success,
+        })?),
+    })
+}
+
+fn registered_tokens<S: ReadonlyStorage>(
+    storage: &S,
+    skip: usize,
+    take: usize,
+) -> StdResult<Vec<RegisteredToken>> {
+    let registered_tokens_list_storage =
+        ReadonlyPrefixedStorage::new(REGISTERED_TOKENS_LIST_PREFIX, storage);
+    let addresses: Vec<HumanAddr> =
+        match AppendStore::<HumanAddr, _>::attach(&registered_tokens_list_storage) {
+            None => vec![],
+            Some(addresses_store) => addresses_store?
+                .iter()
+                .skip(skip)
+                .take(take)
+                .collect::<StdResult<Vec<HumanAddr>>>()?,
+        };
+    let mut registered_tokens: Vec<RegisteredToken> = vec![];
+    for address in addresses {
+        if let Some(registered_token) = registered_token(storage, &address)? {
+            registered_tokens.push(registered_token);
+        }
+    }
+
+    Ok(registered_tokens)
+}
+
+fn registered_token<S: ReadonlyStorage>(
+    storage: &S,
+    address: &HumanAddr,
+) -> StdResult<Option<RegisteredToken>> {
+    let registered_tokens_storage = ReadonlyPrefixedStorage::new(REGISTERED_TOKENS_PREFIX, storage);
+    TypedStore::attach(&registered_tokens_storage).may_load(address.0.as_bytes())
+}
+
+// Sets a fresh viewing key, derived from the entropy, the block and the previous key, on every pool token
+fn rotate_viewing_key<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    entropy: String,
+) -> StdResult<HandleResponse> {
+    let mut config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    authorize(vec![config.admin.clone()], &env.message.sender)?;
+
+    let mut seed: Vec<u8> = config.viewing_key.as_bytes().to_vec();
+    seed.extend_from_slice(&env.block.height.to_be_bytes());
+    seed.extend_from_slice(&env.block.time.to_be_bytes());
+    seed.extend_from_slice(env.message.sender.0.as_bytes());
+    config.viewing_key =
+        Binary::from(&Prng::new(&seed, entropy.as_bytes()).rand_bytes()[..]).to_base64();
+    TypedStoreMut::attach(&mut deps.storage).store(CONFIG_KEY, &config)?;
+
+    let mut tokens: Vec<SecretContract> = vec![];
+    for pool in pools(&deps.storage)? {
+        for token in pool_tokens(&pool) {
+            if !tokens.contains(&token) {
+                tokens.push(token);
+            }
+        }
+    }
+    store_token_viewing_keys(&mut deps.storage, &tokens, &config.viewing_key)?;
+
+    Ok(HandleResponse {
+        messages: set_viewing_key_msgs(&tokens, &config.viewing_key)?,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::RotateViewingKey {
+            status: Success,
+        })?),
+    })
+}
+
+// Sends the tokens withdrawn by a migration to the swap router, except SWBTC
+fn route_migrated_tokens<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+) -> StdResult<HandleResponse> {
+    authorize([env.message.sender.clone()].to_vec(), &env.contract.address)?;
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY)?;
+    let migration: Migration = match session.migration {
+        Some(migration) => migration,
+        None => return Err(StdError::generic_err("Contract wasn't called properly.")),
+    };
+    validate_deadline(session.deadline, &env.block)?;
+    let swap_to_swbtc_contract_address: HumanAddr = session.swap_to_swbtc_contract_address.unwrap();
+
+    let pool: Pool = pool(&deps.storage, migration.pool_id)?;
+    let mut withdrawn_amounts: [Uint128; 2] = [Uint128(0), Uint128(0)];
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for (i, token) in pool.tokens.iter().enumerate() {
+        withdrawn_amounts[i] =
+            (query_balance_of_token(deps, env.contract.address.clone(), token.clone())?
+                - migration.token_balances[i])?;
+        if token.address == config.swbtc.address || withdrawn_amounts[i].is_zero() {
+            continue;
+        }
+        let swap_to_swbtc_msg: Binary = match &migration.swap_to_swbtc_msgs[i] {
+            Some(swap_to_swbtc_msg) => swap_to_swbtc_msg.clone(),
+            None => return Err(StdError::generic_err("Swap to SWBTC msg missing.")),
+        };
+        messages.push(snip20::send_msg(
+            swap_to_swbtc_contract_address.clone(),
+            withdrawn_amounts[i],
+            Some(swap_to_swbtc_msg),
+            None,
+            BLOCK_SIZE,
+            token.contract_hash.clone(),
+            token.address.clone(),
+        )?);
+    }
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::RouteMigratedTokens {
+            withdrawn_amounts,
+        })?),
+    })
+}
+
+fn send_lp_to_user_then_deposit_into_farm_contract<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+) -> StdResult<HandleResponse> {
+    authorize([env.message.sender.clone()].to_vec(), &env.contract.address)?;
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY)?;
+    let pool: Pool = pool(&deps.storage, session.pool_id)?;
+    if let Some(current_user_unwrapped) = session.current_user.clone() {
+        validate_deadline(session.deadline, &env.block)?;
+        // Only the LP the zap minted, not the LP escrowed for exit orders
+        let lp_amount: Uint128 =
+            (query_balance_of_token(deps, env.contract.address.clone(), pool.lp_token.clone())?
+                - session.lp_balance.unwrap_or(Uint128(0)))?;
+        if lp_amount.is_zero() {
+            return Err(StdError::generic_err(
+                "Contract LP balance must be greater than zero.",
+            ));
+        }
+
+        let amounts_to_provide: [Uint128; 2] = [
+            session.amounts_to_provide[0].unwrap(),
+            session.amounts_to_provide[1].unwrap(),
+        ];
+        let recipient: HumanAddr = session
+            .recipient
+            .clone()
+            .unwrap_or_else(|| current_user_unwrapped.clone());
+        let zap: Zap = Zap {
+            id: session.id,
+            user: current_user_unwrapped.clone(),
+            recipient: recipient.clone(),
+            butt_amount: amounts_to_provide[0],
+            swbtc_amount: amounts_to_provide[1],
+            lp_amount,
+            block_time: env.block.time,
+        };
+        // Compounding is the vault's own zap, so it isn't part of anyone's history
+        if !session.compounding {
+            record_zap(&mut deps.storage, &recipient, &zap)?;
+            if current_user_unwrapped != recipient {
+                record_zap(&mut deps.storage, &current_user_unwrapped, &zap)?;
+            }
+        }
+        let hide_amounts: bool = session.hides_amounts(&config);
+        let callback: Option<Callback> = session.callback.take();
+        let deposit_into_vault: bool = session.vault;
+        let compounding: bool = session.compounding;
+        let deposit_target: DepositTarget = session
+            .deposit_target
+            .take()
+            .unwrap_or_else(|| pool.deposit_target.clone());
+        session.clear();
+        TypedStoreMut::attach(&mut deps.storage).store(SESSION_KEY, &session)?;
+
+        let mut share_amount: Option<Uint128> = None;
+        let mut messages: Vec<CosmosMsg> = if deposit_into_vault {
+            // The contract deposits the LP itself and holds the vault's farm position
+            let mut vault: Vault = vault(&deps.storage)?;
+            let butt_balance: Uint128 =
+                query_balance_of_token(deps, env.contract.address.clone(), config.butt)?;
+            let mut messages: Vec<CosmosMsg> = vec![
+                snip20::send_msg(
+                    config.butt_swbtc_farm_pool.address,
+                    lp_amount,
+                    Some(to_binary(&FarmReceiveMsg::DepositIncentivizedToken {})?),
+                    None,
+                    BLOCK_SIZE,
+                    config.butt_swbtc_lp.contract_hash,
+                    config.butt_swbtc_lp.address,
+                )?,
+                record_vault_rewards_msg(env, butt_balance)?,
+            ];
+            if !compounding {
+                let shares: Uint128 =
+                    share_amount_for_lp(lp_amount, vault.total_shares, vault.total_lp)?;
+                messages.push(snip20::mint_msg(
+                    recipient.clone(),
+                    shares,
+                    None,
+                    BLOCK_SIZE,
+                    vault.share_token.contract_hash.clone(),
+                    vault.share_token.address.clone(),
+                )?);
+                vault.total_shares += shares;
+                share_amount = Some(shares);
+            }
+            vault.total_lp += lp_amount;
+            TypedStoreMut::attach(&mut deps.storage).store(VAULT_KEY, &vault)?;
+            messages
+        } else {
+            deposit_msgs(deposit_target, &recipient, lp_amount, &pool.lp_token)?
+        };
+        // Let the calling contract act on the result of the zap
+        if let Some(callback_unwrapped) = callback {
+            messages.push(
+                CallbackHandleMsg::ZapCompleted {
+                    user: current_user_unwrapped,
+                    lp_amount,
+                    butt_provided: amounts_to_provide[0],
+                    swbtc_provided: amounts_to_provide[1],
+                    session_id: zap.id,
+                    msg: callback_unwrapped.msg,
+                }
+                .to_cosmos_msg(
+                    callback_unwrapped.code_hash,
+                    callback_unwrapped.contract,
+                    None,
+                )?,
+            );
+        }
+
+        // Logs are public, so hidden amounts are only available from the zap history and data
+        let mut logs = vec![log("recipient", recipient.clone())];
+        if !hide_amounts {
+            logs.push(log("swbtc_amount", amounts_to_provide[1].to_string()));
+            logs.push(log("butt_amount", amounts_to_provide[0].to_string()));
+            logs.push(log("lp_amount", lp_amount.to_string()));
+        }
+
+        Ok(HandleResponse {
+            messages,
+            log: logs,
+            data: Some(to_binary(
+                &HandleAnswer::SendLpToUserThenDepositIntoFarmContract {
+                    session_id: zap.id,
+                    recipient,
+                    lp_amount,
+                    share_amount,
+                },
+            )?),
+        })
+    } else {
+        Err(StdError::generic_err("Contract wasn't called properly."))
+    }
+}
+
+fn set_deposit_target<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    pool_id: u32,
+    deposit_target: DepositTarget,
+) -> StdResult<HandleResponse> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    authorize(vec![config.admin], &env.message.sender)?;
+    validate_deposit_target(&deps.api, &deposit_target)?;
+
+    let mut pool: Pool = pool(&deps.storage, pool_id)?;
+    pool.deposit_target = deposit_target;
+    let mut pools_storage = PrefixedStorage::new(POOLS_PREFIX, &mut deps.storage);
+    AppendStoreMut::attach_or_create(&mut pools_storage)?.set_at(pool_id, &pool)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::SetDepositTarget {
+            status: Success,
+        })?),
+    })
+}
+
+// Deprecates the contract in favour of its successor, which stops new zaps
+fn set_successor<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    contract: SecretContract,
+) -> StdResult<HandleResponse> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    authorize(vec![config.admin], &env.message.sender)?;
+    deps.api.canonical_address(&contract.address)?;
+
+    TypedStoreMut::attach(&mut deps.storage).store(SUCCESSOR_KEY, &contract)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("successor", contract.address)],
+        data: Some(to_binary(&HandleAnswer::SetSuccessor { status: Success })?),
+    })
+}
+
+// Turns on the vault, whose shares are minted by this contract with the share token
+fn set_vault<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    share_token: SecretContract,
+    harvest_bounty: Decimal,
+    harvest_interval: u64,
+) -> StdResult<HandleResponse> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    authorize(vec![config.admin], &env.message.sender)?;
+    validate_harvest_bounty(harvest_bounty)?;
+    if TypedStore::<Vault, _>::attach(&deps.storage)
+        .may_load(VAULT_KEY)?
+        .is_some()
+    {
+        return Err(StdError::generic_err("Vault is already set."));
+    }
+    deps.api.canonical_address(&share_token.address)?;
+
+    TypedStoreMut::attach(&mut deps.storage).store(
+        VAULT_KEY,
+        &Vault {
+            share_token: share_token.clone(),
+            total_shares: Uint128(0),
+            total_lp: Uint128(0),
+            harvest_bounty,
+            harvest_interval,
+            last_harvest_time: 0,
+            pending_rewards: Uint128(0),
+        },
+    )?;
+
+    // Shares are withdrawn by sending them to this contract
+    Ok(HandleResponse {
+        messages: vec![snip20::register_receive_msg(
+            env.contract_code_hash.clone(),
+            None,
+            BLOCK_SIZE,
+            share_token.contract_hash,
+            share_token.address,
+        )?],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::SetVault { status: Success })?),
+    })
+}
+
+fn set_viewing_key_msgs(tokens: &[SecretContract], viewing_key: &str) -> StdResult<Vec<CosmosMsg>> {
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for token in tokens {
+        messages.push(snip20::set_viewing_key_msg(
+            viewing_key.to_string(),
+            None,
+            BLOCK_SIZE,
+            token.contract_hash.clone(),
+            token.address.clone(),
+        )?);
+    }
+
+    Ok(messages)
+}
+
+fn set_viewing_key<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    key: String,
+) -> StdResult<HandleResponse> {
+    let mut viewing_keys_storage = PrefixedStorage::new(VIEWING_KEYS_PREFIX, &mut deps.storage);
+    viewing_keys_storage.set(env.message.sender.0.as_bytes(), &sha_256(key.as_bytes()));
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::SetViewingKey { status: Success })?),
+    })
+}
+
+fn update_config<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    max_price_impact: Option<Decimal>,
+    hide_amounts: Option<bool>,
+) -> StdResult<HandleResponse> {
+    let mut config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    authorize(vec![config.admin.clone()], &env.message.sender)?;
+
+    if let Some(max_price_impact_unwrapped) = max_price_impact {
+        config.max_price_impact = max_price_impact_unwrapped;
+    }
+    if let Some(hide_amounts_unwrapped) = hide_amounts {
+        config.hide_amounts = hide_amounts_unwrapped;
+    }
+    TypedStoreMut::attach(&mut deps.storage).store(CONFIG_KEY, &config)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::UpdateConfig { status: Success })?),
+    })
+}
+
+fn successor<S: ReadonlyStorage>(storage: &S) -> StdResult<Option<SecretContract>> {
+    TypedStore::attach(storage).may_load(SUCCESSOR_KEY)
+}
+
+// Storage written before the version was recorded is version 1
+fn storage_version<S: ReadonlyStorage>(storage: &S) -> StdResult<u32> {
+    Ok(TypedStore::attach(storage)
+        .may_load(STORAGE_VERSION_KEY)?
+        .unwrap_or(1))
+}
+
+fn validate_deposit_target<A: Api>(api: &A, deposit_target: &DepositTarget) -> StdResult<()> {
+    match deposit_target {
+        DepositTarget::Farm(contract) | DepositTarget::YieldOptimizer(contract) => {
+            api.canonical_address(&contract.address)?;
+        }
+        DepositTarget::Wallet => {}
+    }
+
+    Ok(())
+}
+
+fn validate_not_deprecated<S: ReadonlyStorage>(storage: &S) -> StdResult<()> {
+    if let Some(successor) = successor(storage)? {
+        return Err(StdError::generic_err(format!(
+            "Contract is deprecated, zap with its successor {} instead.",
+            successor.address
+        )));
+    }
+
+    Ok(())
+}
+
+fn validate_swap_router<S: ReadonlyStorage>(storage: &S, router: &HumanAddr) -> StdResult<()> {
+    let swap_routers: Vec<HumanAddr> = TypedStore::attach(storage).load(SWAP_ROUTERS_KEY)?;
+    if !swap_routers.contains(router) {
+        return Err(StdError::generic_err(
+            "Swap to SWBTC contract is not allowed.",
+        ));
+    }
+
+    Ok(())
+}
+
+// Checks a zap request against the pools, tokens and routers it names. Orders and
+// subscriptions are checked when they're placed so a keeper's batch doesn't revert on them.
+fn validate_zap_request<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    from: &HumanAddr,
+    token: &HumanAddr,
+    zap_request: &ZapRequest,
+) -> StdResult<Pool> {
+    if registered_token(&deps.storage, token)?.is_none() {
+        return Err(StdError::generic_err(format!(
+            "Token {} is not registered.",
+            token
+        )));
+    }
+    let pool: Pool = pool(&deps.storage, zap_request.pool_id.unwrap_or(0))?;
+    // The intermediate token must be one of the pool's tokens
+    let intermediate_token: &HumanAddr = match &zap_request.intermediate_token {
+        None => &pool.tokens[1].address,
+        Some(intermediate_token) => {
+            if pool
+                .tokens
+                .iter()
+                .all(|token| token.address != *intermediate_token)
+            {
+                return Err(StdError::generic_err(format!(
+                    "Intermediate token must be a token of pool {}.",
+                    pool.id
+                )));
+            }
+            intermediate_token
+        }
+    };
+    // Token can't be the other token of the pool
+    let paired_token: &HumanAddr = if pool.tokens[0].address == *intermediate_token {
+        &pool.tokens[1].address
+    } else {
+        &pool.tokens[0].address
+    };
+    if paired_token == token {
+        return Err(StdError::generic_err(format!(
+            "Token can't be the paired token {} when ReceiveMsg present.",
+            paired_token
+        )));
+    };
+    if let Some(recipient) = &zap_request.recipient {
+        deps.api.canonical_address(recipient)?;
+    }
+    if let Some(callback) = &zap_request.callback {
+        deps.api.canonical_address(&callback.contract)?;
+    }
+    let deposit_into_vault: bool = zap_request.vault.unwrap_or(false);
+    if deposit_into_vault {
+        vault(&deps.storage)?;
+        if pool.id != 0 {
+            return Err(StdError::generic_err("Vault only holds pool 0."));
+        }
+        if zap_request.deposit_target.is_some() {
+            return Err(StdError::generic_err(
+                "Deposit target can't be set when depositing into the vault.",
+            ));
+        }
+    }
+    if let Some(deposit_target) = &zap_request.deposit_target {
+        validate_deposit_target(&deps.api, deposit_target)?;
+    }
+    if !deposit_into_vault {
+        validate_recipient(
+            zap_request.recipient.as_ref(),
+            from,
+            zap_request
+                .deposit_target
+                .as_ref()
+                .unwrap_or(&pool.deposit_target),
+        )?;
+    }
+    if intermediate_token != token {
+        if zap_request.swap_to_swbtc_msg.is_none() {
+            return Err(StdError::generic_err("Swap to SWBTC msg missing."));
+        }
+        match &zap_request.swap_to_swbtc_contract {
+            None => return Err(StdError::generic_err("Swap to SWBTC contract missing.")),
+            Some(swap_to_swbtc_contract) => {
+                validate_swap_router(&deps.storage, &swap_to_swbtc_contract.address)?
+            }
+        }
+    }
+
+    Ok(pool)
+}
+
+fn validate_storage_version<S: ReadonlyStorage>(storage: &S) -> StdResult<()> {
+    if storage_version(storage)? != STORAGE_VERSION {
+        return Err(StdError::generic_err(
+            "Storage is outdated, it must be migrated with MigrateState.",
+        ));
+    }
+
+    Ok(())
+}
+
+fn update_vault<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    harvest_bounty: Option<Decimal>,
+    harvest_interval: Option<u64>,
+) -> StdResult<HandleResponse> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    authorize(vec![config.admin], &env.message.sender)?;
+    let mut vault: Vault = vault(&deps.storage)?;
+
+    if let Some(harvest_bounty_unwrapped) = harvest_bounty {
+        validate_harvest_bounty(harvest_bounty_unwrapped)?;
+        vault.harvest_bounty = harvest_bounty_unwrapped;
+    }
+    if let Some(harvest_interval_unwrapped) = harvest_interval {
+        vault.harvest_interval = harvest_interval_unwrapped;
+    }
+    TypedStoreMut::attach(&mut deps.storage).store(VAULT_KEY, &vault)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::UpdateVault { status: Success })?),
+    })
+}
+
+// The viewing key the contract has set on the token
+fn token_viewing_key<S: ReadonlyStorage>(storage: &S, token: &HumanAddr) -> StdResult<String> {
+    let token_viewing_keys_storage =
+        ReadonlyPrefixedStorage::new(TOKEN_VIEWING_KEYS_PREFIX, storage);
+    TypedStore::attach(&token_viewing_keys_storage)
+        .may_load(token.0.as_bytes())?
+        .ok_or_else(|| StdError::generic_err(format!("Viewing key for {} is missing.", token)))
+}
+
+// Adds the sent tokens to the balance of the user's active subscription
+fn top_up_subscription<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    from: HumanAddr,
+    amount: Uint128,
+    subscription_id: u64,
+) -> StdResult<HandleResponse> {
+    validate_not_deprecated(&deps.storage)?;
+    let mut subscription: Subscription = subscription(&deps.storage, subscription_id)?;
+    authorize(vec![subscription.user.clone()], &from)?;
+    if subscription.status != SubscriptionStatus::Active {
+        return Err(StdError::generic_err(format!(
+            "Subscription {} is not active.",
+            subscription_id
+        )));
+    }
+    if env.message.sender != subscription.token.address {
+        return Err(StdError::generic_err(format!(
+            "Subscription {} is funded with {}.",
+            subscription_id, subscription.token.address
+        )));
+    }
+
+    subscription.balance += amount;
+    store_subscription(&mut deps.storage, &subscription)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::TopUpSubscription {
+            balance: subscription.balance,
+        })?),
+    })
+}
+
+fn vault<S: ReadonlyStorage>(storage: &S) -> StdResult<Vault> {
+    TypedStore::attach(storage)
+        .may_load(VAULT_KEY)?
+        .ok_or_else(|| StdError::generic_err("Vault is not set."))
+}
+
+// Burns the shares sent by the user and returns their part of the vault's LP
+fn withdraw_from_vault<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    from: HumanAddr,
+    share_amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY)?;
+    let mut vault: Vault = vault(&deps.storage)?;
+    authorize(vec![vault.share_token.address.clone()], &env.message.sender)?;
+
+    let lp_amount: Uint128 =
+        lp_amount_for_shares(share_amount, vault.total_shares, vault.total_lp)?;
+    if lp_amount.is_zero() {
+        return Err(StdError::generic_err(
+            "LP amount to withdraw must be greater than zero.",
+        ));
+    }
+    vault.total_shares = (vault.total_shares - share_amount)?;
+    vault.total_lp = (vault.total_lp - lp_amount)?;
+    TypedStoreMut::attach(&mut deps.storage).store(VAULT_KEY, &vault)?;
+    let butt_balance: Uint128 =
+        query_balance_of_token(deps, env.contract.address.clone(), config.butt)?;
+
+    Ok(HandleResponse {
+        messages: vec![
+            snip20::burn_msg(
+                share_amount,
+                None,
+                BLOCK_SIZE,
+                vault.share_token.contract_hash,
+                vault.share_token.address,
+            )?,
+            FarmHandleMsg::Withdraw {
+                incentivized_token_amount: lp_amount,
+            }
+            .to_cosmos_msg(
+                config.butt_swbtc_farm_pool.contract_hash,
+                config.butt_swbtc_farm_pool.address,
+                None,
+            )?,
+            record_vault_rewards_msg(env, butt_balance)?,
+            snip20::transfer_msg(
+                from,
+                lp_amount,
+                None,
+                BLOCK_SIZE,
+                config.butt_swbtc_lp.contract_hash,
+                config.butt_swbtc_lp.address,
+            )?,
+        ],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::WithdrawFromVault { lp_amount })?),
+    })
+}
+
+// Zaps an installment taken from a subscription's balance by process_subscriptions, on behalf of its user
+fn zap_installment<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    subscription_id: u64,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    authorize([env.message.sender.clone()].to_vec(), &env.contract.address)?;
+    let subscription: Subscription = subscription(&deps.storage, subscription_id)?;
+    let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY)?;
+
+    init_swap_and_provide(
+        deps,
+        env,
+        subscription.user,
+        subscription.token.address,
+        amount,
+        session,
+        subscription.zap_request,
+    )
+}
+
+// Take a Vec<u8> and pad it up to a multiple of `block_size`, using spaces at the end.
+fn space_pad(block_size: usize, message: &mut Vec<u8>) -> &mut Vec<u8> {
+    let len = message.len();
+    let surplus = len % block_size;
+    if surplus == 0 {
+        return message;
+    }
+
+    let missing = block_size - surplus;
+    message.reserve(missing);
+    message.extend(std::iter::repeat(b' ').take(missing));
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::{
+        Asset, AssetInfo, PoolResponse, SecretSwapHandleMsg, SecretSwapQueryMsg,
+        SecretSwapReceiveMsg,
+    };
+    use crate::state::{ConfigPublic, Deadline, PriceTrigger, SecretContract};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, MockApi, MockStorage};
+    use cosmwasm_std::{from_slice, Empty, QuerierResult, QueryRequest, SystemError, WasmQuery};
+    pub const MOCK_ADMIN: &str = "admin";
+    pub const MOCK_BUTT_SWBTC_TRADE_PAIR_CONTRACT_ADDRESS: &str =
+        "mock-butt-swbtc-trade-pair-address";
+    pub const MOCK_SWAP_TO_SWBTC_ADDRESS: &str = "mock-swap-to-swbtc-address";
+    pub const MOCK_VIEWING_KEY: &str = "DELIGHTFUL";
+    pub const MOCK_BUTT_ADDRESS: &str = "mock-butt-address";
+    pub const MOCK_SWBTC_ADDRESS: &str = "mock-swbtc-address";
+
+    // === MOCK QUERIER ===
+    // Answers the queries made to the trade pair and the tokens
+    pub struct MockContractQuerier {
+        pub pool_reserve: Uint128,
+        pub pair_allowance: Uint128,
+        pub balance: Uint128,
+    }
+    impl Querier for MockContractQuerier {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let request: QueryRequest<Empty> = from_slice(bin_request).unwrap();
+            match request {
+                QueryRequest::Wasm(WasmQuery::Smart {
+                    contract_addr, msg, ..
+                }) => {
+                    if contract_addr == mock_butt_swbtc_trade_pair().address {
+                        let SecretSwapQueryMsg::Pool {} = from_binary(&msg).unwrap();
+                        Ok(to_binary(&mock_pool_response(self.pool_reserve)))
+                    } else if let Some(token_info) = mock_token_info(&contract_addr) {
+                        match from_binary(&msg).unwrap() {
+                            snip20::QueryMsg::TokenInfo {} => {
+                                Ok(to_binary(&snip20::TokenInfoResponse { token_info }))
+                            }
+                            snip20::QueryMsg::Allowance { owner, spender, .. } => {
+                                Ok(to_binary(&snip20::AllowanceResponse {
+                                    allowance: snip20::Allowance {
+                                        spender,
+                                        owner,
+                                        allowance: self.pair_allowance,
+                                        expiration: None,
+                                    },
+                                }))
+                            }
+                            snip20::QueryMsg::Balance { .. } => {
+                                Ok(to_binary(&snip20::BalanceResponse {
+                                    balance: snip20::Balance {
+                                        amount: self.balance,
+                                    },
+                                }))
+                            }
+                            _ => Err(SystemError::UnsupportedRequest {
+                                kind: "snip20 query".to_string(),
+                            }),
+                        }
+                    } else {
+                        Err(SystemError::NoSuchContract {
+                            addr: contract_addr,
+                        })
+                    }
+                }
+                _ => Err(SystemError::UnsupportedRequest {
+                    kind: "non-wasm query".to_string(),
+                }),
+            }
+        }
+    }
+
+    // === HELPERS ===
+    fn init_helper() -> (
+        StdResult<InitResponse>,
+        Extern<MockStorage, MockApi, MockContractQuerier>,
+    ) {
+        let env = mock_env(MOCK_ADMIN, &[]);
+        let mock_deps = mock_dependencies(20, &[]);
+        let mut deps = Extern {
+            storage: mock_deps.storage,
+            api: mock_deps.api,
+            querier: MockContractQuerier {
+                pool_reserve: Uint128(MOCK_AMOUNT),
+                pair_allowance: Uint128(u128::MAX),
+                balance: Uint128(0),
+            },
+        };
+        let msg = InitMsg {
+            butt: mock_butt(),
+            swbtc: mock_swbtc(),
+            butt_swbtc_farm_pool: mock_butt_swbtc_farm_pool(),
+            butt_swbtc_trade_pair: mock_butt_swbtc_trade_pair(),
+            butt_swbtc_lp: mock_butt_swbtc_lp(),
+            viewing_key: MOCK_VIEWING_KEY.to_string(),
+            max_price_impact: Decimal::percent(5),
+            hide_amounts: false,
+        };
+        let init_result = init(&mut deps, env.clone(), msg);
+        (init_result, deps)
+    }
+
+    fn mock_butt() -> SecretContract {
+        SecretContract {
+            address: HumanAddr::from(MOCK_BUTT_ADDRESS),
+            contract_hash: "mock-butt-contract-hash".to_string(),
+        }
+    }
+
+    fn mock_butt_swbtc_farm_pool() -> SecretContract {
+        SecretContract {
+            address: HumanAddr::from("mock-butt-swbtc-farm-pool-address"),
+            contract_hash: "mock-butt-swbtc-farm-pool-contract-hash".to_string(),
+        }
+    }
+
+    fn mock_butt_swbtc_lp() -> SecretContract {
+        SecretContract {
+            address: HumanAddr::from(MOCK_BUTT_SWBTC_LP_ADDRESS),
+            contract_hash: "mock-butt-swbtc-lp-contract-hash".to_string(),
+        }
+    }
+
+    fn mock_butt_swbtc_trade_pair() -> SecretContract {
+        SecretContract {
+            address: HumanAddr::from(MOCK_BUTT_SWBTC_TRADE_PAIR_CONTRACT_ADDRESS),
+            contract_hash: "mock-butt-swbtc-trade-pair-contract-hash".to_string(),
+        }
+    }
+
+    fn mock_pool() -> Pool {
+        Pool {
+            id: 0,
+            trade_pair: mock_butt_swbtc_trade_pair(),
+            tokens: [mock_butt(), mock_swbtc()],
+            lp_token: mock_butt_swbtc_lp(),
+            deposit_target: DepositTarget::Farm(mock_butt_swbtc_farm_pool()),
+            dex: Dex::SecretSwap,
+            invariant: Invariant::ConstantProduct,
+        }
+    }
+
+    fn mock_pool_response(reserve: Uint128) -> PoolResponse {
+        PoolResponse {
+            assets: [
+                Asset {
+                    amount: reserve,
+                    info: AssetInfo::Token {
+                        contract_addr: mock_butt().address,
+                        token_code_hash: mock_butt().contract_hash,
+                        viewing_key: "SecretSwap".to_string(),
+                    },
+                },
+                Asset {
+                    amount: reserve,
+                    info: AssetInfo::Token {
+                        contract_addr: mock_swbtc().address,
+                        token_code_hash: mock_swbtc().contract_hash,
+                        viewing_key: "SecretSwap".to_string(),
+                    },
+                },
+            ],
+            total_share: reserve,
+        }
+    }
+
+    fn mock_sefi() -> SecretContract {
+        SecretContract {
+            address: HumanAddr::from("mock-sefi-address"),
+            contract_hash: "mock-sefi-contract-hash".to_string(),
+        }
+    }
+
+    fn mock_sefi_swbtc_lp() -> SecretContract {
+        SecretContract {
+            address: HumanAddr::from("mock-sefi-swbtc-lp"),
+            contract_hash: "mock-sefi-swbtc-lp-contract-hash".to_string(),
+        }
+    }
+
+    fn mock_sefi_swbtc_pool() -> Pool {
+        Pool {
+            id: 1,
+            trade_pair: SecretContract {
+                address: HumanAddr::from("mock-sefi-swbtc-pair"),
+                contract_hash: "mock-sefi-swbtc-pair-contract-hash".to_string(),
+            },
+            tokens: [mock_sefi(), mock_swbtc()],
+            lp_token: mock_sefi_swbtc_lp(),
+            deposit_target: DepositTarget::Wallet,
+            dex: Dex::SecretSwap,
+            invariant: Invariant::ConstantProduct,
+        }
+    }
+
+    fn mock_swbtc() -> SecretContract {
+        SecretContract {
+            address: HumanAddr::from(MOCK_SWBTC_ADDRESS),
+            contract_hash: "mock-swbtc-contract-hash".to_string(),
+        }
+    }
+
+    fn mock_swap_to_swbtc_contract() -> SecretContract {
+        SecretContract {
+            address: HumanAddr::from(MOCK_SWAP_TO_SWBTC_ADDRESS),
+            contract_hash: "mock-swbtc-contract-hash".to_string(),
+        }
+    }
+
+    fn mock_token_info(address: &HumanAddr) -> Option<snip20::TokenInfo> {
+        let (symbol, decimals) = if *address == mock_butt().address {
+            ("BUTT", 6)
+        } else if *address == mock_swbtc().address {
+            ("SWBTC", 8)
+        } else if *address == mock_butt_swbtc_lp().address {
+            ("SWBTC-BUTT", 6)
+        } else if *address == mock_sefi().address {
+            ("SEFI", 6)
+        } else if *address == mock_sefi_swbtc_lp().address {
+            ("SEFI-SWBTC", 6)
+        } else {
+            return None;
+        };
+
+        Some(snip20::TokenInfo {
+            name: symbol.to_string(),
+            symbol: symbol.to_string(),
+            decimals,
+            total_supply: None,
+        })
+    }
+
+    fn mock_registered_token(token: SecretContract) -> RegisteredToken {
+        let token_info = mock_token_info(&token.address).unwrap();
+        RegisteredToken {
+            address: token.address,
+            contract_hash: token.contract_hash,
+            symbol: token_info.symbol,
+            decimals: token_info.decimals,
+        }
+    }
+
+    fn add_pool_helper<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, pool: Pool) {
+        handle(
+            deps,
+            mock_env(MOCK_ADMIN, &[]),
+            HandleMsg::AddPool {
+                trade_pair: pool.trade_pair,
+                tokens: pool.tokens,
+                lp_token: pool.lp_token,
+                deposit_target: Some(pool.deposit_target),
+                dex: Some(pool.dex),
+                invariant: Some(pool.invariant),
+            },
+        )
+        .unwrap();
+    }
+
+    fn register_tokens_helper<S: Storage, A: Api, Q: Querier>(
+        deps: &mut Extern<S, A, Q>,
+        tokens: Vec<SecretContract>,
+    ) {
+        handle(
+            deps,
+            mock_env(MOCK_ADMIN, &[]),
+            HandleMsg::RegisterTokens { tokens },
+        )
+        .unwrap();
+    }
+
+    fn mock_callback() -> Callback {
+        Callback {
+            contract: HumanAddr::from("vault"),
+            code_hash: "vault-code-hash".to_string(),
+            msg: to_binary(&123).unwrap(),
+        }
+    }
+
+    fn mock_yield_optimizer() -> SecretContract {
+        SecretContract {
+            address: HumanAddr::from("yield-optimizer"),
+            contract_hash: "mock-yield-optimizer-contract-hash".to_string(),
+        }
+    }
+
+    fn mock_share_token() -> SecretContract {
+        SecretContract {
+            address: HumanAddr::from("share-token"),
+            contract_hash: "mock-share-token-contract-hash".to_string(),
+        }
+    }
+
+    fn set_vault_helper<S: Storage, A: Api, Q: Querier>(
+        deps: &mut Extern<S, A, Q>,
+        total_shares: Uint128,
+        total_lp: Uint128,
+    ) {
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(
+                VAULT_KEY,
+                &Vault {
+                    share_token: mock_share_token(),
+                    total_shares,
+                    total_lp,
+                    harvest_bounty: Decimal::percent(1),
+                    harvest_interval: 3_600,
+                    last_harvest_time: 0,
+                    pending_rewards: Uint128(0),
+                },
+            )
+            .unwrap();
+    }
+
+    // Exits once BUTT is worth target_price SWBTC, expiring after block 12_355
+    fn mock_exit_order_request(target_price: Decimal, trigger: PriceTrigger) -> ExitOrderRequest {
+        ExitOrderRequest {
+            target_price,
+            trigger,
+            belief_price: Some(Decimal::one()),
+            max_spread: Some(Decimal::percent(1)),
+            expiry: Deadline::Height(12_355),
+        }
+    }
+
+    fn place_exit_order_helper<S: Storage, A: Api, Q: Querier>(
+        deps: &mut Extern<S, A, Q>,
+        token: SecretContract,
+        amount: Uint128,
+        exit_order_request: ExitOrderRequest,
+    ) -> u64 {
+        let handle_result_unwrapped = handle(
+            deps,
+            mock_env(token.address, &[]),
+            HandleMsg::Receive {
+                sender: mock_user_address(),
+                from: mock_user_address(),
+                amount,
+                msg: Some(to_binary(&ReceiveMsg::PlaceExitOrder(exit_order_request)).unwrap()),
+            },
+        )
+        .unwrap();
+        match from_binary(&handle_result_unwrapped.data.unwrap()).unwrap() {
+            HandleAnswer::PlaceExitOrder { exit_order_id } => exit_order_id,
+            _ => panic!("Unexpected answer."),
+        }
+    }
+
+    fn mock_migration() -> Migration {
+        Migration {
+            pool_id: 1,
+            swap_to_swbtc_msgs: [Some(to_binary(&"sefi-to-swbtc").unwrap()), None],
+            token_balances: [Uint128(7), Uint128(7)],
+            swbtc_balance: Uint128(7),
+        }
+    }
+
+    // Zaps SWBTC into pool 0 once BUTT is worth target_price SWBTC, expiring after block 12_355
+    fn mock_order_request(target_price: Decimal, trigger: PriceTrigger) -> OrderRequest {
+        OrderRequest {
+            target_price,
+            trigger,
+            expiry: Deadline::Height(12_355),
+            zap_request: mock_zap_request(),
+        }
+    }
+
+    // Zaps 2 SWBTC into pool 0 every minute
+    fn mock_subscription_request(periods: u32) -> SubscriptionRequest {
+        SubscriptionRequest {
+            amount_per_period: Uint128(2),
+            periods,
+            period: 60,
+            zap_request: mock_zap_request(),
+        }
+    }
+
+    // Zaps SWBTC into pool 0
+    fn mock_zap_request() -> ZapRequest {
+        ZapRequest {
+            swap_to_swbtc_contract: None,
+            swap_to_swbtc_msg: None,
+            first_token_contract_hash: mock_swbtc().contract_hash,
+            belief_price: None,
+            max_spread: None,
+            intermediate_token: None,
+            max_price_impact: None,
+            deadline: None,
+            recipient: None,
+            callback: None,
+            hide_amounts: None,
+            vault: None,
+            pool_id: None,
+            deposit_target: None,
+        }
+    }
+
+    fn place_order_helper<S: Storage, A: Api, Q: Querier>(
+        deps: &mut Extern<S, A, Q>,
+        order_request: OrderRequest,
+    ) -> u64 {
+        let handle_result_unwrapped = handle(
+            deps,
+            mock_env(mock_swbtc().address, &[]),
+            HandleMsg::Receive {
+                sender: mock_user_address(),
+                from: mock_user_address(),
+                amount: Uint128(5),
+                msg: Some(to_binary(&ReceiveMsg::PlaceOrder(order_request)).unwrap()),
+            },
+        )
+        .unwrap();
+        match from_binary(&handle_result_unwrapped.data.unwrap()).unwrap() {
+            HandleAnswer::PlaceOrder { order_id } => order_id,
+            _ => panic!("Unexpected answer."),
+        }
+    }
+
+    fn subscribe_helper<S: Storage, A: Api, Q: Querier>(
+        deps: &mut Extern<S, A, Q>,
+        subscription_request: SubscriptionRequest,
+        amount: Uint128,
+    ) -> u64 {
+        let handle_result_unwrapped = handle(
+            deps,
+            mock_env(mock_swbtc().address, &[]),
+            HandleMsg::Receive {
+                sender: mock_user_address(),
+                from: mock_user_address(),
+                amount,
+                msg: Some(to_binary(&ReceiveMsg::Subscribe(subscription_request)).unwrap()),
+            },
+        )
+        .unwrap();
+        match from_binary(&handle_result_unwrapped.data.unwrap()).unwrap() {
+            HandleAnswer::Subscribe { subscription_id } => subscription_id,
+            _ => panic!("Unexpected answer."),
+        }
+    }
+
+    fn mock_recipient_address() -> HumanAddr {
+        HumanAddr::from("recipient")
+    }
+
+    fn mock_user_address() -> HumanAddr {
+        HumanAddr::from("gary")
+    }
+
+    // === TESTS ===
+    #[test]
+    fn test_init() {
+        let (init_result, deps) = init_helper();
+
+        // * it stores the correct config
+        let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                admin: HumanAddr::from(MOCK_ADMIN),
+                contract_address: mock_env(MOCK_ADMIN, &[]).contract.address,
+                butt: mock_butt(),
+                swbtc: mock_swbtc(),
+                butt_swbtc_farm_pool: mock_butt_swbtc_farm_pool(),
+                butt_swbtc_trade_pair: mock_butt_swbtc_trade_pair(),
+                butt_swbtc_lp: mock_butt_swbtc_lp(),
+                max_price_impact: Decimal::percent(5),
+                hide_amounts: false,
+                viewing_key: MOCK_VIEWING_KEY.to_string(),
+            }
+        );
+
+        // * it stores an empty session
+        let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session, Session::default());
+
+        // * it stores the current storage version
+        assert_eq!(storage_version(&deps.storage).unwrap(), STORAGE_VERSION);
+
+        // * it stores an empty swap routers allowlist
+        let swap_routers: Vec<HumanAddr> = TypedStore::attach(&deps.storage)
+            .load(SWAP_ROUTERS_KEY)
+            .unwrap();
+        assert_eq!(swap_routers, vec![]);
+
+        // * it stores the BUTT-SWBTC trade pair as pool 0
+        assert_eq!(pools(&deps.storage).unwrap(), vec![mock_pool()]);
+
+        // * it stores the viewing key of BUTT, SWBTC & BUTT-SWBTC LP
+        for token in pool_tokens(&mock_pool()) {
+            assert_eq!(
+                token_viewing_key(&deps.storage, &token.address).unwrap(),
+                MOCK_VIEWING_KEY
+            );
+        }
+
+        // * it sets the viewing key for BUTT, SWBTC & BUTT-SWBTC LP
+        // * it increases the allowance of the trade pair for BUTT & SWBTC
+        // * it registers to receive BUTT-SWBTC LP without registering it as a token to zap
+        assert_eq!(
+            init_result.unwrap().messages,
+            pool_setup_msgs(
+                &mock_pool(),
+                MOCK_VIEWING_KEY,
+                &mock_env(MOCK_ADMIN, &[]).contract_code_hash
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            pool_setup_msgs(
+                &mock_pool(),
+                MOCK_VIEWING_KEY,
+                &mock_env(MOCK_ADMIN, &[]).contract_code_hash
+            )
+            .unwrap(),
+            vec![
+                snip20::set_viewing_key_msg(
+                    MOCK_VIEWING_KEY.to_string(),
+                    None,
+                    BLOCK_SIZE,
+                    mock_butt().contract_hash,
+                    mock_butt().address,
+                )
+                .unwrap(),
+                snip20::set_viewing_key_msg(
+                    MOCK_VIEWING_KEY.to_string(),
+                    None,
+                    BLOCK_SIZE,
+                    mock_swbtc().contract_hash,
+                    mock_swbtc().address,
+                )
+                .unwrap(),
+                snip20::set_viewing_key_msg(
+                    MOCK_VIEWING_KEY.to_string(),
+                    None,
+                    BLOCK_SIZE,
+                    mock_butt_swbtc_lp().contract_hash,
+                    mock_butt_swbtc_lp().address,
+                )
+                .unwrap(),
+                snip20::increase_allowance_msg(
+                    mock_butt_swbtc_trade_pair().address,
+                    Uint128(u128::MAX),
+                    None,
+                    None,
+                    BLOCK_SIZE,
+                    mock_butt().contract_hash,
+                    mock_butt().address,
+                )
+                .unwrap(),
+                snip20::increase_allowance_msg(
+                    mock_butt_swbtc_trade_pair().address,
+                    Uint128(u128::MAX),
+                    None,
+                    None,
+                    BLOCK_SIZE,
+                    mock_swbtc().contract_hash,
+                    mock_swbtc().address,
+                )
+                .unwrap(),
+                snip20::register_receive_msg(
+                    mock_env(MOCK_ADMIN, &[]).contract_code_hash,
+                    None,
+                    BLOCK_SIZE,
+                    mock_butt_swbtc_lp().contract_hash,
+                    mock_butt_swbtc_lp().address,
+                )
+                .unwrap(),
+            ]
+        );
+        assert_eq!(
+            registered_token(&deps.storage, &mock_butt_swbtc_lp().address).unwrap(),
+            None
+        );
+    }
+
+    // === QUERY ===
+    #[test]
+    fn test_query_config() {
+        let (_init_result, deps) = init_helper();
+        let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+        let config_from_query: ConfigPublic =
+            from_binary(&query(&deps, QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(
+            config.with_public_attributes(None).unwrap(),
+            config_from_query
+        );
+    }
+
+    #[test]
+    fn test_query_contract_info() {
+        let (_init_result, mut deps) = init_helper();
+        let contract_info = |deps: &Extern<MockStorage, MockApi, MockContractQuerier>| {
+            from_binary::<ContractInfoResponse>(&query(deps, QueryMsg::ContractInfo {}).unwrap())
+                .unwrap()
+        };
+
+        // * it returns the crate version, storage version, features and pools
+        assert_eq!(
+            contract_info(&deps),
+            ContractInfoResponse {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                storage_version: STORAGE_VERSION,
+                features: FEATURES.iter().map(|feature| feature.to_string()).collect(),
+                pools: vec![mock_pool()],
+                status: ContractStatus::Operational,
+            }
+        );
+
+        // when a zap is in progress
+        // * it reports the contract as zapping
+        let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        session.current_user = Some(mock_user_address());
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        assert_eq!(contract_info(&deps).status, ContractStatus::Zapping);
+
+        // when the contract has a successor
+        // * it reports the contract as deprecated
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SUCCESSOR_KEY, &mock_butt())
+            .unwrap();
+        assert_eq!(contract_info(&deps).status, ContractStatus::Deprecated);
+
+        // when the storage is outdated
+        // * it reports that the storage has to be migrated
+        deps.storage.remove(STORAGE_VERSION_KEY);
+        let contract_info_unwrapped: ContractInfoResponse = contract_info(&deps);
+        assert_eq!(contract_info_unwrapped.storage_version, 1);
+        assert_eq!(
+            contract_info_unwrapped.status,
+            ContractStatus::MigrationRequired
+        );
+    }
+
+    #[test]
+    fn test_query_exit_orders() {
+        let (_init_result, mut deps) = init_helper();
+        let query_msg = QueryMsg::ExitOrders {
+            address: mock_user_address(),
+            key: "spicy".to_string(),
+            page: 0,
+            page_size: 1,
+        };
+
+        // = when the viewing key is wrong
+        // = * it raises an Unauthorized error
+        assert_eq!(
+            query(&deps, query_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when the viewing key is correct
+        handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            HandleMsg::SetViewingKey {
+                key: "spicy".to_string(),
+            },
+        )
+        .unwrap();
+        // == when the user has no exit orders
+        // == * it returns an empty list
+        let exit_orders: Vec<ExitOrder> =
+            from_binary(&query(&deps, query_msg.clone()).unwrap()).unwrap();
+        assert_eq!(exit_orders, vec![]);
+        // == when the user has exit orders
+        for trigger in [PriceTrigger::Below, PriceTrigger::Above].iter() {
+            place_exit_order_helper(
+                &mut deps,
+                mock_butt_swbtc_lp(),
+                Uint128(10),
+                mock_exit_order_request(Decimal::one(), trigger.clone()),
+            );
+        }
+        // == * it returns a page of the exit orders, newest first
+        let exit_orders: Vec<ExitOrder> = from_binary(&query(&deps, query_msg).unwrap()).unwrap();
+        assert_eq!(exit_orders, vec![exit_order(&deps.storage, 1).unwrap()]);
+    }
+
+    #[test]
+    fn test_query_export_state() {
+        let (_init_result, mut deps) = init_helper();
+        register_tokens_helper(&mut deps, vec![mock_butt(), mock_swbtc()]);
+        let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        session.id = 3;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+
+        // * it returns the config, zaps count, pools, registered tokens and swap routers
+        let exported_state: ExportedState =
+            from_binary(&query(&deps, QueryMsg::ExportState {}).unwrap()).unwrap();
+        assert_eq!(
+            exported_state,
+            ExportedState {
+                config: config.with_public_attributes(None).unwrap(),
+                zaps_count: 3,
+                pools: vec![mock_pool()],
+                registered_tokens: vec![
+                    mock_registered_token(mock_butt()),
+                    mock_registered_token(mock_swbtc())
+                ],
+                swap_routers: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_query_orders() {
+        let (_init_result, mut deps) = init_helper();
+        let query_msg = QueryMsg::Orders {
+            address: mock_user_address(),
+            key: "spicy".to_string(),
+            page: 0,
+            page_size: 1,
+        };
+
+        // = when the viewing key is wrong
+        // = * it raises an Unauthorized error
+        assert_eq!(
+            query(&deps, query_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when the viewing key is correct
+        handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            HandleMsg::SetViewingKey {
+                key: "spicy".to_string(),
+            },
+        )
+        .unwrap();
+        // == when the user has no orders
+        // == * it returns an empty list
+        let orders: Vec<Order> = from_binary(&query(&deps, query_msg.clone()).unwrap()).unwrap();
+        assert_eq!(orders, vec![]);
+        // == when the user has orders
+        register_tokens_helper(&mut deps, vec![mock_swbtc()]);
+        place_order_helper(
+            &mut deps,
+            mock_order_request(Decimal::percent(50), PriceTrigger::Above),
+        );
+        place_order_helper(
+            &mut deps,
+            mock_order_request(Decimal::percent(50), PriceTrigger::Below),
+        );
+        // == * it returns a page of the orders, newest first
+        let orders: Vec<Order> = from_binary(&query(&deps, query_msg).unwrap()).unwrap();
+        assert_eq!(orders, vec![order(&deps.storage, 1).unwrap()]);
+    }
+
+    #[test]
+    fn test_query_pair_allowances() {
+        let (_init_result, mut deps) = init_helper();
+        let pair_allowance = |allowance: Uint128, status: AllowanceStatus| {
+            vec![
+                PairAllowance {
+                    pool_id: 0,
+                    token: mock_butt().address,
+                    spender: mock_butt_swbtc_trade_pair().address,
+                    allowance,
+                    status: status.clone(),
+                },
+                PairAllowance {
+                    pool_id: 0,
+                    token: mock_swbtc().address,
+                    spender: mock_butt_swbtc_trade_pair().address,
+                    allowance,
+                    status,
+                },
+            ]
+        };
+
+        // = when the allowances are missing
+        deps.querier.pair_allowance = Uint128(0);
+        // = * it reports them as missing
+        let pair_allowances: Vec<PairAllowance> =
+            from_binary(&query(&deps, QueryMsg::PairAllowances {}).unwrap()).unwrap();
+        assert_eq!(
+            pair_allowances,
+            pair_allowance(Uint128(0), AllowanceStatus::Missing)
+        );
+
+        // = when the allowances have been drawn down
+        deps.querier.pair_allowance = Uint128(MINIMUM_PAIR_ALLOWANCE - 1);
+        // = * it reports them as insufficient
+        let pair_allowances: Vec<PairAllowance> =
+            from_binary(&query(&deps, QueryMsg::PairAllowances {}).unwrap()).unwrap();
+        assert_eq!(
+            pair_allowances,
+            pair_allowance(
+                Uint128(MINIMUM_PAIR_ALLOWANCE - 1),
+                AllowanceStatus::Insufficient
+            )
+        );
+
+        // = when the allowances are in place
+        deps.querier.pair_allowance = Uint128(MINIMUM_PAIR_ALLOWANCE);
+        // = * it reports them as sufficient
+        let pair_allowances: Vec<PairAllowance> =
+            from_binary(&query(&deps, QueryMsg::PairAllowances {}).unwrap()).unwrap();
+        assert_eq!(
+            pair_allowances,
+            pair_allowance(Uint128(MINIMUM_PAIR_ALLOWANCE), AllowanceStatus::Sufficient)
+        );
+    }
+
+    #[test]
+    fn test_query_pools() {
+        let (_init_result, deps) = init_helper();
+
+        // * it returns the registered pools
+        let pools_from_query: Vec<Pool> =
+            from_binary(&query(&deps, QueryMsg::Pools {}).unwrap()).unwrap();
+        assert_eq!(pools_from_query, vec![mock_pool()]);
+    }
+
+    #[test]
+    fn test_query_registered_tokens() {
+        let (_init_result, mut deps) = init_helper();
+
+        // = when no tokens are registered
+        // = * it returns an empty list
+        let registered_tokens: Vec<RegisteredToken> = from_binary(
+            &query(
+                &deps,
+                QueryMsg::RegisteredTokens {
+                    page: 0,
+                    page_size: 2,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(registered_tokens, vec![]);
+
+        // = when tokens are registered
+        register_tokens_helper(
+            &mut deps,
+            vec![mock_butt(), mock_swbtc(), mock_butt_swbtc_lp()],
+        );
+        // = * it returns the page of tokens in the order they were registered
+        let registered_tokens: Vec<RegisteredToken> = from_binary(
+            &query(
+                &deps,
+                QueryMsg::RegisteredTokens {
+                    page: 0,
+                    page_size: 2,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            registered_tokens,
+            vec![
+                mock_registered_token(mock_butt()),
+                mock_registered_token(mock_swbtc())
+            ]
+        );
+        let registered_tokens: Vec<RegisteredToken> = from_binary(
+            &query(
+                &deps,
+                QueryMsg::RegisteredTokens {
+                    page: 1,
+                    page_size: 2,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            registered_tokens,
+            vec![mock_registered_token(mock_butt_swbtc_lp())]
+        );
+    }
+
+    #[test]
+    fn test_query_simulate_zap() {
+        let (_init_result, mut deps) = init_helper();
+        let query_msg = QueryMsg::SimulateZap {
+            pool_id: 0,
+            intermediate_token: None,
+            amount: Uint128(1_000),
+        };
+
+        // = when the trade pair has no liquidity
+        // = * it raises an error
+        deps.querier.pool_reserve = Uint128(0);
+        assert_eq!(
+            query(&deps, query_msg.clone()).unwrap_err(),
+            StdError::generic_err("Trade pair has no liquidity.")
+        );
+
+        // = when the trade pair has liquidity
+        // = * it returns the amounts and LP expected from swapping half and providing both tokens
+        deps.querier.pool_reserve = Uint128(MOCK_AMOUNT);
+        let simulate_zap_response: SimulateZapResponse =
+            from_binary(&query(&deps, query_msg).unwrap()).unwrap();
+        assert_eq!(
+            simulate_zap_response,
+            SimulateZapResponse {
+                amount_to_swap: Uint128(500),
+                amount_to_provide: Uint128(500),
+                return_amount: Uint128(498),
+                price_impact: price_impact(Uint128(500), Uint128(MOCK_AMOUNT)),
+                lp_amount: Uint128(498),
+            }
+        );
+
+        // = when the pool doesn't exist
+        // = * it raises an error
+        assert_eq!(
+            query(
+                &deps,
+                QueryMsg::SimulateZap {
+                    pool_id: 1,
+                    intermediate_token: None,
+                    amount: Uint128(1_000),
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err("Pool 1 not found.")
+        );
+
+        // = when the pool is stable swap
+        let mut stable_pool: Pool = mock_pool();
+        stable_pool.id = 1;
+        stable_pool.invariant = Invariant::StableSwap { amplification: 100 };
+        stable_pool.deposit_target = DepositTarget::Wallet;
+        add_pool_helper(&mut deps, stable_pool);
+        // = * it swaps to the ratio of the reserves and prices the swap by the stable swap invariant
+        let simulate_zap_response: SimulateZapResponse = from_binary(
+            &query(
+                &deps,
+                QueryMsg::SimulateZap {
+                    pool_id: 1,
+                    intermediate_token: None,
+                    amount: Uint128(1_000),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            simulate_zap_response,
+            SimulateZapResponse {
+                amount_to_swap: Uint128(500),
+                amount_to_provide: Uint128(500),
+                return_amount: Uint128(499),
+                price_impact: Decimal::zero(),
+                lp_amount: Uint128(499),
+            }
+        );
+    }
+
+    #[test]
+    fn test_query_subscriptions() {
+        let (_init_result, mut deps) = init_helper();
+        let query_msg = QueryMsg::Subscriptions {
+            address: mock_user_address(),
+            key: "spicy".to_string(),
+            page: 0,
+            page_size: 1,
+        };
+
+        // = when the viewing key is wrong
+        // = * it raises an Unauthorized error
+        assert_eq!(
+            query(&deps, query_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when the viewing key is correct
+        handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            HandleMsg::SetViewingKey {
+                key: "spicy".to_string(),
+            },
+        )
+        .unwrap();
+        // == when the user has no subscriptions
+        // == * it returns an empty list
+        let subscriptions: Vec<Subscription> =
+            from_binary(&query(&deps, query_msg.clone()).unwrap()).unwrap();
+        assert_eq!(subscriptions, vec![]);
+        // == when the user has subscriptions
+        register_tokens_helper(&mut deps, vec![mock_swbtc()]);
+        subscribe_helper(&mut deps, mock_subscription_request(1), Uint128(2));
+        subscribe_helper(&mut deps, mock_subscription_request(2), Uint128(4));
+        // == * it returns a page of the subscriptions, newest first
+        let subscriptions: Vec<Subscription> =
+            from_binary(&query(&deps, query_msg).unwrap()).unwrap();
+        assert_eq!(subscriptions, vec![subscription(&deps.storage, 1).unwrap()]);
+    }
+
+    #[test]
+    fn test_query_swap_routers() {
+        let (_init_result, mut deps) = init_helper();
+        handle(
+            &mut deps,
+            mock_env(MOCK_ADMIN, &[]),
+            HandleMsg::AddSwapRouters {
+                routers: vec![mock_swap_to_swbtc_contract().address],
+            },
+        )
+        .unwrap();
+        let swap_routers_from_query: Vec<HumanAddr> =
+            from_binary(&query(&deps, QueryMsg::SwapRouters {}).unwrap()).unwrap();
+        assert_eq!(
+            swap_routers_from_query,
+            vec![mock_swap_to_swbtc_contract().address]
+        );
+    }
+
+    #[test]
+    fn test_query_vault() {
+        let (_init_result, mut deps) = init_helper();
+
+        // when the vault isn't set
+        // * it raises an error
+        assert_eq!(
+            query(&deps, QueryMsg::Vault {}).unwrap_err(),
+            StdError::generic_err("Vault is not set.")
+        );
+
+        // when the vault is empty
+        // * it returns a share price of one
+        set_vault_helper(&mut deps, Uint128(0), Uint128(0));
+        let vault_response: VaultResponse =
+            from_binary(&query(&deps, QueryMsg::Vault {}).unwrap()).unwrap();
+        assert_eq!(vault_response.share_price, Decimal::one());
+
+        // when the vault has compounded
+        // * it returns the vault with the LP each share is redeemed for
+        set_vault_helper(&mut deps, Uint128(4), Uint128(5));
+        let vault_response: VaultResponse =
+            from_binary(&query(&deps, QueryMsg::Vault {}).unwrap()).unwrap();
+        assert_eq!(
+            vault_response,
+            VaultResponse {
+                vault: Vault {
+                    share_token: mock_share_token(),
+                    total_shares: Uint128(4),
+                    total_lp: Uint128(5),
+                    harvest_bounty: Decimal::percent(1),
+                    harvest_interval: 3_600,
+                    last_harvest_time: 0,
+                    pending_rewards: Uint128(0),
+                },
+                share_price: Decimal::percent(125),
+            }
+        );
+    }
+
+    #[test]
+    fn test_query_zap_history() {
+        let (_init_result, mut deps) = init_helper();
+        let query_msg = QueryMsg::ZapHistory {
+            address: mock_user_address(),
+            key: "spicy".to_string(),
+            page: 0,
+            page_size: 2,
+        };
+
+        // = when the user has not set a viewing key
+        // = * it raises an Unauthorized error
+        assert_eq!(
+            query(&deps, query_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when the viewing key is wrong
+        handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            HandleMsg::SetViewingKey {
+                key: "mild".to_string(),
+            },
+        )
+        .unwrap();
+        // = * it raises an Unauthorized error
+        assert_eq!(
+            query(&deps, query_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when the viewing key is correct
+        handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            HandleMsg::SetViewingKey {
+                key: "spicy".to_string(),
+            },
+        )
+        .unwrap();
+        // == when the user has no zaps
+        // == * it returns an empty list
+        let zaps: Vec<Zap> = from_binary(&query(&deps, query_msg.clone()).unwrap()).unwrap();
+        assert_eq!(zaps, vec![]);
+        // == when the user has zaps
+        let zaps: Vec<Zap> = (1..4)
+            .map(|id| Zap {
+                id,
+                user: mock_user_address(),
+                recipient: mock_user_address(),
+                butt_amount: Uint128(1),
+                swbtc_amount: Uint128(1),
+                lp_amount: Uint128(1),
+                block_time: id,
+            })
+            .collect();
+        for zap in zaps.iter() {
+            record_zap(&mut deps.storage, &mock_user_address(), zap).unwrap();
+        }
+        // == * it returns the page of zaps, newest first
+        let zaps_from_query: Vec<Zap> = from_binary(&query(&deps, query_msg).unwrap()).unwrap();
+        assert_eq!(zaps_from_query, vec![zaps[2].clone(), zaps[1].clone()]);
+        let zaps_from_query: Vec<Zap> = from_binary(
+            &query(
+                &deps,
+                QueryMsg::ZapHistory {
+                    address: mock_user_address(),
+                    key: "spicy".to_string(),
+                    page: 1,
+                    page_size: 2,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(zaps_from_query, vec![zaps[0].clone()]);
+        // == when the page is past the end of the u32 range
+        // == * it returns an empty page
+        let zaps_from_query: Vec<Zap> = from_binary(
+            &query(
+                &deps,
+                QueryMsg::ZapHistory {
+                    address: mock_user_address(),
+                    key: "spicy".to_string(),
+                    page: u32::MAX,
+                    page_size: 2,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(zaps_from_query, vec![]);
+    }
+
+    // === HANDLE ===
+    #[test]
+    fn test_add_pool() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::AddPool {
+            trade_pair: mock_swap_to_swbtc_contract(),
+            tokens: [mock_swbtc(), mock_butt_swbtc_lp()],
+            lp_token: mock_butt_swbtc_farm_pool(),
+            deposit_target: None,
+            dex: None,
+            invariant: None,
+        };
+
+        // = when called by a non-admin
+        // = * it raises an Unauthorized error
+        let handle_result = handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            handle_msg.clone(),
+        );
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when called by the admin
+        // == when the pool is stable swap without amplification
+        // == * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(MOCK_ADMIN, &[]),
+                HandleMsg::AddPool {
+                    trade_pair: mock_swap_to_swbtc_contract(),
+                    tokens: [mock_swbtc(), mock_butt_swbtc_lp()],
+                    lp_token: mock_butt_swbtc_farm_pool(),
+                    deposit_target: None,
+                    dex: None,
+                    invariant: Some(Invariant::StableSwap { amplification: 0 }),
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err("Amplification must be greater than zero.")
+        );
+        // == when the pool is valid
+        let handle_result_unwrapped =
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg).unwrap();
+        let pool: Pool = Pool {
+            id: 1,
+            trade_pair: mock_swap_to_swbtc_contract(),
+            tokens: [mock_swbtc(), mock_butt_swbtc_lp()],
+            lp_token: mock_butt_swbtc_farm_pool(),
+            deposit_target: DepositTarget::Wallet,
+            dex: Dex::SecretSwap,
+            invariant: Invariant::ConstantProduct,
+        };
+        // = * it stores the pool with the next id
+        assert_eq!(
+            pools(&deps.storage).unwrap(),
+            vec![mock_pool(), pool.clone()]
+        );
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::AddPool { pool_id: 1 }
+        );
+        // = * it sets the viewing keys and allowances for the pool and registers to receive its LP
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            pool_setup_msgs(
+                &pool,
+                MOCK_VIEWING_KEY,
+                &mock_env(MOCK_ADMIN, &[]).contract_code_hash
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_add_swap_routers() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::AddSwapRouters {
+            routers: vec![
+                mock_swap_to_swbtc_contract().address,
+                mock_swap_to_swbtc_contract().address,
+            ],
+        };
+
+        // = when called by a non-admin
+        // = * it raises an Unauthorized error
+        let handle_result = handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            handle_msg.clone(),
+        );
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when called by the admin
+        // = * it adds the routers to the allowlist without duplicates
+        let handle_result_unwrapped =
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg).unwrap();
+        // = * it returns a padded success status in data
+        let data: Binary = handle_result_unwrapped.data.unwrap();
+        assert_eq!(data.len() % BLOCK_SIZE, 0);
+        assert_eq!(
+            from_binary::<HandleAnswer>(&data).unwrap(),
+            HandleAnswer::AddSwapRouters { status: Success }
+        );
+        let swap_routers: Vec<HumanAddr> = TypedStore::attach(&deps.storage)
+            .load(SWAP_ROUTERS_KEY)
+            .unwrap();
+        assert_eq!(swap_routers, vec![mock_swap_to_swbtc_contract().address]);
+    }
+
+    #[test]
+    fn test_cancel_exit_order() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::CancelExitOrder { exit_order_id: 0 };
+
+        // = when the exit order doesn't exist
+        // = * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                handle_msg.clone()
+            )
+            .unwrap_err(),
+            StdError::generic_err("Exit order 0 not found.")
+        );
+
+        // = when the exit order exists
+        place_exit_order_helper(
+            &mut deps,
+            mock_butt_swbtc_lp(),
+            Uint128(10),
+            mock_exit_order_request(Decimal::percent(50), PriceTrigger::Below),
+        );
+        // == when called by someone other than the user
+        // == * it raises an unauthorized error
+        assert_eq!(
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+        // == when called by the user
+        let handle_result_unwrapped = handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            handle_msg.clone(),
+        )
+        .unwrap();
+        // == * it refunds the escrowed LP
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![snip20::transfer_msg(
+                mock_user_address(),
+                Uint128(10),
+                None,
+                BLOCK_SIZE,
+                mock_butt_swbtc_lp().contract_hash,
+                mock_butt_swbtc_lp().address,
+            )
+            .unwrap()]
+        );
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::CancelExitOrder { status: Success }
+        );
+        // == * it marks the exit order as cancelled
+        assert_eq!(
+            exit_order(&deps.storage, 0).unwrap().status,
+            OrderStatus::Cancelled
+        );
+        // == when the exit order is no longer pending
+        // == * it raises an error
+        assert_eq!(
+            handle(&mut deps, mock_env(mock_user_address(), &[]), handle_msg).unwrap_err(),
+            StdError::generic_err("Exit order 0 is not pending.")
+        );
+    }
+
+    #[test]
+    fn test_cancel_order() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::CancelOrder { order_id: 0 };
+
+        // = when the order doesn't exist
+        // = * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                handle_msg.clone()
+            )
+            .unwrap_err(),
+            StdError::generic_err("Order 0 not found.")
+        );
+
+        // = when the order exists
+        register_tokens_helper(&mut deps, vec![mock_swbtc()]);
+        place_order_helper(
+            &mut deps,
+            mock_order_request(Decimal::percent(50), PriceTrigger::Below),
+        );
+        // == when called by someone other than the user
+        // == * it raises an unauthorized error
+        assert_eq!(
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+        // == when called by the user
+        let handle_result_unwrapped = handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            handle_msg.clone(),
+        )
+        .unwrap();
+        // == * it refunds the escrow
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![snip20::transfer_msg(
+                mock_user_address(),
+                Uint128(5),
+                None,
+                BLOCK_SIZE,
+                mock_swbtc().contract_hash,
+                mock_swbtc().address,
+            )
+            .unwrap()]
+        );
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::CancelOrder { status: Success }
+        );
+        // == * it marks the order as cancelled
+        assert_eq!(
+            order(&deps.storage, 0).unwrap().status,
+            OrderStatus::Cancelled
+        );
+        // == when the order is no longer pending
+        // == * it raises an error
+        assert_eq!(
+            handle(&mut deps, mock_env(mock_user_address(), &[]), handle_msg).unwrap_err(),
+            StdError::generic_err("Order 0 is not pending.")
+        );
+    }
+
+    #[test]
+    fn test_cancel_subscription() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::CancelSubscription { subscription_id: 0 };
+
+        // = when the subscription doesn't exist
+        // = * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                handle_msg.clone()
+            )
+            .unwrap_err(),
+            StdError::generic_err("Subscription 0 not found.")
+        );
+
+        // = when the subscription exists
+        register_tokens_helper(&mut deps, vec![mock_swbtc()]);
+        subscribe_helper(&mut deps, mock_subscription_request(3), Uint128(6));
+        // == when called by someone other than the user
+        // == * it raises an unauthorized error
+        assert_eq!(
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+        // == when called by the user
+        let handle_result_unwrapped = handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            handle_msg.clone(),
+        )
+        .unwrap();
+        // == * it refunds the balance
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![snip20::transfer_msg(
+                mock_user_address(),
+                Uint128(6),
+                None,
+                BLOCK_SIZE,
+                mock_swbtc().contract_hash,
+                mock_swbtc().address,
+            )
+            .unwrap()]
+        );
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::CancelSubscription { status: Success }
+        );
+        // == * it marks the subscription as cancelled
+        let subscription: Subscription = subscription(&deps.storage, 0).unwrap();
+        assert_eq!(subscription.balance, Uint128(0));
+        assert_eq!(subscription.status, SubscriptionStatus::Cancelled);
+        // == when the subscription is no longer active
+        // == * it raises an error
+        assert_eq!(
+            handle(&mut deps, mock_env(mock_user_address(), &[]), handle_msg).unwrap_err(),
+            StdError::generic_err("Subscription 0 is not active.")
+        );
+    }
+
+    #[test]
+    fn test_compound_harvested_rewards() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::CompoundHarvestedRewards {
+            harvester: mock_user_address(),
+            butt_balance: Uint128(200),
+        };
+        let env = mock_env(mock_env(MOCK_ADMIN, &[]).contract.address, &[]);
+
+        // when called by non-contract
+        // * it raises an unauthorized error
+        assert_eq!(
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // when called by contract
+        // = when no rewards were claimed
+        // = * it doesn't start a session
+        set_vault_helper(&mut deps, Uint128(0), Uint128(0));
+        deps.querier.balance = Uint128(200);
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg.clone()).unwrap();
+        assert_eq!(handle_result_unwrapped.messages, vec![]);
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::CompoundHarvestedRewards {
+                session_id: None,
+                reward_amount: Uint128(0),
+                bounty_amount: Uint128(0),
+            }
+        );
+        let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session, Session::default());
+
+        // = when rewards were claimed
+        // = * it only compounds the BUTT the harvest added to the contract's balance
+        deps.querier.balance = Uint128(1_200);
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg).unwrap();
+        // = * it pays the harvester the bounty
+        // = * it sends the rest of the BUTT to itself to swap half of it
+        // = * it calls the function to deposit the LP
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![
+                snip20::transfer_msg(
+                    mock_user_address(),
+                    Uint128(10),
+                    None,
+                    BLOCK_SIZE,
+                    mock_butt().contract_hash,
+                    mock_butt().address,
+                )
+                .unwrap(),
+                snip20::send_msg(
+                    env.contract.address.clone(),
+                    Uint128(990),
+                    None,
+                    None,
+                    BLOCK_SIZE,
+                    mock_butt().contract_hash,
+                    mock_butt().address,
+                )
+                .unwrap(),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: env.contract.address.clone(),
+                    callback_code_hash: env.contract_code_hash.clone(),
+                    msg: to_binary(&HandleMsg::SendLpToUserThenDepositIntoFarmContract {}).unwrap(),
+                    send: vec![],
+                })
+            ]
+        );
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::CompoundHarvestedRewards {
+                session_id: Some(1),
+                reward_amount: Uint128(1_000),
+                bounty_amount: Uint128(10),
+            }
+        );
+        // = * it starts a compounding session for the vault through BUTT
+        let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.current_user, Some(env.contract.address.clone()));
+        assert_eq!(session.recipient, Some(env.contract.address.clone()));
+        assert_eq!(session.intermediate_token, Some(mock_butt().address));
+        assert_eq!(
+            session.swap_to_swbtc_contract_address,
+            Some(env.contract.address)
+        );
+        assert!(session.vault);
+        assert!(session.compounding);
+    }
+
+    #[test]
+    fn test_execute_exit_order() {
+        let (_init_result, mut deps) = init_helper();
+        set_vault_helper(&mut deps, Uint128(10), Uint128(20));
+        place_exit_order_helper(
+            &mut deps,
+            mock_butt_swbtc_lp(),
+            Uint128(10),
+            mock_exit_order_request(Decimal::percent(50), PriceTrigger::Below),
+        );
+        place_exit_order_helper(
+            &mut deps,
+            mock_share_token(),
+            Uint128(5),
+            mock_exit_order_request(Decimal::percent(50), PriceTrigger::Below),
+        );
+        let env = mock_env(MOCK_ADMIN, &[]);
+        let adapter: Box<dyn DexAdapter<MockContractQuerier>> = dex_adapter(&Dex::SecretSwap);
+        // The trade pair returns 10 BUTT and 10 SWBTC for 10 LP at the mock reserves
+        let exit_msgs: Vec<CosmosMsg> = vec![
+            adapter
+                .withdraw_liquidity_msg(
+                    &mock_butt_swbtc_trade_pair(),
+                    &mock_butt_swbtc_lp(),
+                    Uint128(10),
+                    &env.contract.address,
+                )
+                .unwrap(),
+            adapter
+                .swap_msg(
+                    &mock_butt_swbtc_trade_pair(),
+                    &mock_butt(),
+                    Uint128(10),
+                    Some(Decimal::one()),
+                    Some(Decimal::percent(1)),
+                    Some(&mock_user_address()),
+                )
+                .unwrap(),
+            snip20::transfer_msg(
+                mock_user_address(),
+                Uint128(10),
+                None,
+                BLOCK_SIZE,
+                mock_swbtc().contract_hash,
+                mock_swbtc().address,
+            )
+            .unwrap(),
+        ];
+
+        // = when called by someone other than the contract
+        // = * it raises an unauthorized error
+        assert_eq!(
+            handle(
+                &mut deps,
+                env.clone(),
+                HandleMsg::ExecuteExitOrder { exit_order_id: 0 }
+            )
+            .unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when called by the contract
+        let env = mock_env(env.contract.address, &[]);
+        // == when the exit order hasn't been marked as executed
+        // == * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                env.clone(),
+                HandleMsg::ExecuteExitOrder { exit_order_id: 0 }
+            )
+            .unwrap_err(),
+            StdError::generic_err("Exit order 0 is not executed.")
+        );
+        for exit_order_id in 0..2 {
+            let mut exit_order: ExitOrder = exit_order(&deps.storage, exit_order_id).unwrap();
+            exit_order.status = OrderStatus::Executed;
+            store_exit_order(&mut deps.storage, &exit_order).unwrap();
+        }
+        // == when the exit order escrows LP
+        let handle_result_unwrapped = handle(
+            &mut deps,
+            env.clone(),
+            HandleMsg::ExecuteExitOrder { exit_order_id: 0 },
+        )
+        .unwrap();
+        // == * it withdraws the liquidity, swaps the BUTT to SWBTC for the user and sends the SWBTC
+        assert_eq!(handle_result_unwrapped.messages, exit_msgs);
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::ExecuteExitOrder {
+                butt_amount: Uint128(10),
+                swbtc_amount: Uint128(10),
+            }
+        );
+        // == when the exit order escrows vault shares
+        let handle_result_unwrapped = handle(
+            &mut deps,
+            env.clone(),
+            HandleMsg::ExecuteExitOrder { exit_order_id: 1 },
+        )
+        .unwrap();
+        // == * it redeems the shares for their LP from the farm before exiting it
+        let mut vault_exit_msgs: Vec<CosmosMsg> = vec![
+            snip20::burn_msg(
+                Uint128(5),
+                None,
+                BLOCK_SIZE,
+                mock_share_token().contract_hash,
+                mock_share_token().address,
+            )
+            .unwrap(),
+            FarmHandleMsg::Withdraw {
+                incentivized_token_amount: Uint128(10),
+            }
+            .to_cosmos_msg(
+                mock_butt_swbtc_farm_pool().contract_hash,
+                mock_butt_swbtc_farm_pool().address,
+                None,
+            )
+            .unwrap(),
+            // == * it records the rewards the farm pays out on the withdrawal
+            record_vault_rewards_msg(&env, Uint128(0)).unwrap(),
+        ];
+        vault_exit_msgs.extend(exit_msgs);
+        assert_eq!(handle_result_unwrapped.messages, vault_exit_msgs);
+        // == * it removes the shares and LP from the vault
+        let vault: Vault = TypedStore::attach(&deps.storage).load(VAULT_KEY).unwrap();
+        assert_eq!(vault.total_shares, Uint128(5));
+        assert_eq!(vault.total_lp, Uint128(10));
+    }
+
+    #[test]
+    fn test_execute_exit_orders() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::ExecuteExitOrders {
+            exit_order_ids: vec![0, 1, 2],
+        };
+
+        // = when more exit orders than a batch holds are given
+        // = * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                HandleMsg::ExecuteExitOrders {
+                    exit_order_ids: (0..MAX_BATCH_SIZE as u64 + 1).collect()
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err(format!(
+                "At most {} exit orders can be executed at once.",
+                MAX_BATCH_SIZE
+            ))
+        );
+
+        // = when no exit orders are given
+        // = * it executes nothing
+        let handle_result_unwrapped = handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            HandleMsg::ExecuteExitOrders {
+                exit_order_ids: vec![],
+            },
+        )
+        .unwrap();
+        assert_eq!(handle_result_unwrapped.messages, vec![]);
+
+        // = when there are exit orders
+        // BUTT is worth 1 SWBTC at the mock reserves
+        for (target_price, trigger) in [
+            (Decimal::percent(50), PriceTrigger::Below),
+            (Decimal::percent(50), PriceTrigger::Above),
+            (Decimal::percent(200), PriceTrigger::Above),
+        ]
+        .iter()
+        {
+            place_exit_order_helper(
+                &mut deps,
+                mock_butt_swbtc_lp(),
+                Uint128(10),
+                mock_exit_order_request(*target_price, trigger.clone()),
+            );
+        }
+        // == when an exit order id doesn't exist
+        // == * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                HandleMsg::ExecuteExitOrders {
+                    exit_order_ids: vec![3]
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err("Exit order 3 not found.")
+        );
+        // == when called by anyone
+        let env = mock_env(mock_user_address(), &[]);
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg.clone()).unwrap();
+        // == * it exits the liquidity of the exit orders triggered by the price
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: env.contract.address.clone(),
+                callback_code_hash: env.contract_code_hash.clone(),
+                msg: to_binary(&HandleMsg::ExecuteExitOrder { exit_order_id: 1 }).unwrap(),
+                send: vec![],
+            })]
+        );
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::ExecuteExitOrders {
+                executed_exit_order_ids: vec![1],
+                expired_exit_order_ids: vec![],
+            }
+        );
+        // == * it marks them as executed
+        assert_eq!(
+            exit_order(&deps.storage, 1).unwrap().status,
+            OrderStatus::Executed
+        );
+
+        // == when exit orders have expired
+        let mut env = mock_env(mock_user_address(), &[]);
+        env.block.height = 12_356;
+        let handle_result_unwrapped = handle(&mut deps, env, handle_msg).unwrap();
+        // == * it refunds the escrow and skips exit orders that aren't pending
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::ExecuteExitOrders {
+                executed_exit_order_ids: vec![],
+                expired_exit_order_ids: vec![0, 2],
+            }
+        );
+        assert_eq!(handle_result_unwrapped.messages.len(), 2);
+        assert_eq!(
+            exit_order(&deps.storage, 2).unwrap().status,
+            OrderStatus::Expired
+        );
+    }
+
+    #[test]
+    fn test_execute_order() {
+        let (_init_result, mut deps) = init_helper();
+        register_tokens_helper(&mut deps, vec![mock_swbtc()]);
+        place_order_helper(
+            &mut deps,
+            mock_order_request(Decimal::percent(50), PriceTrigger::Above),
+        );
+        let env = mock_env(MOCK_ADMIN, &[]);
+        let handle_msg = HandleMsg::ExecuteOrder { order_id: 0 };
+
+        // = when called by someone other than the contract
+        // = * it raises an unauthorized error
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when called by the contract
+        let env = mock_env(env.contract.address, &[]);
+        // == when the order hasn't been marked as executed
+        // == * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Order 0 is not executed.")
+        );
+        // == when the order has been marked as executed
+        let mut order: Order = order(&deps.storage, 0).unwrap();
+        order.status = OrderStatus::Executed;
+        store_order(&mut deps.storage, &order).unwrap();
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg).unwrap();
+        // == * it zaps the escrow
+        assert_eq!(
+            handle_result_unwrapped.messages[0],
+            snip20::send_msg(
+                env.contract.address.clone(),
+                Uint128(5),
+                None,
+                None,
+                BLOCK_SIZE,
+                mock_swbtc().contract_hash,
+                mock_swbtc().address,
+            )
+            .unwrap()
+        );
+        // == * it zaps on behalf of the order's user
+        let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.current_user, Some(mock_user_address()));
+        assert_eq!(session.recipient, Some(mock_user_address()));
+    }
+
+    #[test]
+    fn test_execute_orders() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::ExecuteOrders {
+            order_ids: vec![0, 1, 2],
+        };
+
+        // = when more orders than a batch holds are given
+        // = * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                HandleMsg::ExecuteOrders {
+                    order_ids: (0..MAX_BATCH_SIZE as u64 + 1).collect()
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err(format!(
+                "At most {} orders can be executed at once.",
+                MAX_BATCH_SIZE
+            ))
+        );
+
+        // = when no orders are given
+        // = * it executes nothing
+        let handle_result_unwrapped = handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            HandleMsg::ExecuteOrders { order_ids: vec![] },
+        )
+        .unwrap();
+        assert_eq!(handle_result_unwrapped.messages, vec![]);
+
+        // = when there are orders
+        // BUTT is worth 1 SWBTC at the mock reserves
+        register_tokens_helper(&mut deps, vec![mock_swbtc()]);
+        for (target_price, trigger) in [
+            (Decimal::percent(50), PriceTrigger::Above),
+            (Decimal::percent(200), PriceTrigger::Above),
+            (Decimal::percent(200), PriceTrigger::Below),
+        ]
+        .iter()
+        {
+            place_order_helper(
+                &mut deps,
+                mock_order_request(*target_price, trigger.clone()),
+            );
+        }
+        // == when the contract is being used
+        // == * it raises an error
+        let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        session.current_user = Some(mock_user_address());
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                handle_msg.clone()
+            )
+            .unwrap_err(),
+            StdError::generic_err("Contract is already being used.")
+        );
+        session.current_user = None;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // == when an order id doesn't exist
+        // == * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                HandleMsg::ExecuteOrders { order_ids: vec![3] }
+            )
+            .unwrap_err(),
+            StdError::generic_err("Order 3 not found.")
+        );
+        // == when called by anyone
+        let env = mock_env(mock_user_address(), &[]);
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg.clone()).unwrap();
+        // == * it executes the orders triggered by the price, one after the other
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: env.contract.address.clone(),
+                    callback_code_hash: env.contract_code_hash.clone(),
+                    msg: to_binary(&HandleMsg::ExecuteOrder { order_id: 0 }).unwrap(),
+                    send: vec![],
+                }),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: env.contract.address.clone(),
+                    callback_code_hash: env.contract_code_hash.clone(),
+                    msg: to_binary(&HandleMsg::ExecuteOrder { order_id: 2 }).unwrap(),
+                    send: vec![],
+                }),
+            ]
+        );
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::ExecuteOrders {
+                executed_order_ids: vec![0, 2],
+                expired_order_ids: vec![],
+            }
+        );
+        // == * it marks them as executed
+        assert_eq!(
+            order(&deps.storage, 0).unwrap().status,
+            OrderStatus::Executed
+        );
+        assert_eq!(
+            order(&deps.storage, 1).unwrap().status,
+            OrderStatus::Pending
+        );
+
+        // == when an order has expired
+        let mut env = mock_env(mock_user_address(), &[]);
+        env.block.height = 12_356;
+        let handle_result_unwrapped = handle(&mut deps, env, handle_msg).unwrap();
+        // == * it refunds the escrow and skips orders that aren't pending
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![snip20::transfer_msg(
+                mock_user_address(),
+                Uint128(5),
+                None,
+                BLOCK_SIZE,
+                mock_swbtc().contract_hash,
+                mock_swbtc().address,
+            )
+            .unwrap()]
+        );
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::ExecuteOrders {
+                executed_order_ids: vec![],
+                expired_order_ids: vec![1],
+            }
+        );
+        assert_eq!(
+            order(&deps.storage, 1).unwrap().status,
+            OrderStatus::Expired
+        );
+    }
+
+    #[test]
+    fn test_finish_migration() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::FinishMigration {};
+        let env = mock_env(mock_env(MOCK_ADMIN, &[]).contract.address, &[]);
+
+        // when called by non-contract
+        // * it raises an unauthorized error
+        assert_eq!(
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // when called by contract
+        // = when no migration is in progress
+        // = * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Contract wasn't called properly.")
+        );
+        // = when a migration is in progress
+        let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        session.current_user = Some(mock_user_address());
+        session.intermediate_token = Some(mock_swbtc().address);
+        session.swap_to_swbtc_contract_address = Some(mock_swap_to_swbtc_contract().address);
+        session.migration = Some(mock_migration());
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // == when no SWBTC was routed
+        // == * it raises an error
+        deps.querier.balance = Uint128(7);
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Migrated SWBTC must be greater than zero.")
+        );
+        // == when SWBTC was routed
+        deps.querier.balance = Uint128(10);
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg).unwrap();
+        // == * it sends the routed SWBTC to itself to swap half of it
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![snip20::send_msg(
+                env.contract.address.clone(),
+                Uint128(3),
+                None,
+                None,
+                BLOCK_SIZE,
+                mock_swbtc().contract_hash,
+                mock_swbtc().address,
+            )
+            .unwrap()]
+        );
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::FinishMigration {
+                swbtc_amount: Uint128(3)
+            }
+        );
+        // == * it ends the migration and continues the zap from the contract
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.migration, None);
+        assert_eq!(
+            session.swap_to_swbtc_contract_address,
+            Some(env.contract.address)
+        );
+        assert_eq!(session.current_user, Some(mock_user_address()));
+    }
+
+    #[test]
+    fn test_harvest() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::Harvest {};
+        let mut env = mock_env(mock_user_address(), &[]);
+
+        // = when the vault isn't set
+        // = * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Vault is not set.")
+        );
+        // = when a zap is in progress
+        // = * it raises an error
+        set_vault_helper(&mut deps, Uint128(0), Uint128(0));
+        let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        session.current_user = Some(mock_user_address());
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Contract is already being used.")
+        );
+        // = when the contract is free
+        // = * it claims the farm rewards then compounds them, paying the caller
+        session.current_user = None;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone())
+                .unwrap()
+                .messages,
+            vec![
+                FarmHandleMsg::Withdraw {
+                    incentivized_token_amount: Uint128(0),
+                }
+                .to_cosmos_msg(
+                    mock_butt_swbtc_farm_pool().contract_hash,
+                    mock_butt_swbtc_farm_pool().address,
+                    None,
+                )
+                .unwrap(),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: env.contract.address.clone(),
+                    callback_code_hash: env.contract_code_hash.clone(),
+                    msg: to_binary(&HandleMsg::CompoundHarvestedRewards {
+                        harvester: mock_user_address(),
+                        butt_balance: Uint128(0),
+                    })
+                    .unwrap(),
+                    send: vec![],
+                })
+            ]
+        );
+        // = * it records the time of the harvest
+        let vault: Vault = TypedStore::attach(&deps.storage).load(VAULT_KEY).unwrap();
+        assert_eq!(vault.last_harvest_time, env.block.time);
+
+        // = when the harvest interval hasn't passed since the last harvest
+        // = * it raises an error
+        env.block.time += 3_599;
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err(format!(
+                "Harvest is available from {}.",
+                vault.last_harvest_time + 3_600
+            ))
+        );
+        // = when the harvest interval has passed
+        // = * it harvests again
+        env.block.time += 1;
+        assert!(handle(&mut deps, env.clone(), handle_msg.clone()).is_ok());
+
+        // = when the harvest interval runs past the end of time
+        // = * it raises an error instead of overflowing
+        let mut vault: Vault = TypedStore::attach(&deps.storage).load(VAULT_KEY).unwrap();
+        vault.harvest_interval = u64::MAX;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(VAULT_KEY, &vault)
+            .unwrap();
+        assert_eq!(
+            handle(&mut deps, env, handle_msg).unwrap_err(),
+            StdError::generic_err(format!("Harvest is available from {}.", u64::MAX))
+        );
+    }
+
+    #[test]
+    fn test_increase_allowance_for_pair_contract() {
+        let (_init_result, mut deps) = init_helper();
+
+        let handle_msg = HandleMsg::IncreaseAllowanceForPairContract { pool_id: 0 };
+
+        // = when called by a non-admin
+        // = * it raises an Unauthorized error
+        let handle_result = handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            handle_msg.clone(),
+        );
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when called by the admin
+        let env = mock_env(MOCK_ADMIN, &[]);
+        // == when the pool does not exist
+        // == * it raises an error
+        let handle_result = handle(
+            &mut deps,
+            env.clone(),
+            HandleMsg::IncreaseAllowanceForPairContract { pool_id: 1 },
+        );
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err("Pool 1 not found.")
+        );
+        // == when the pool exists
+        // == * it increases the allowance of the pool's trade pair for butt and swbtc
+        let handle_result = handle(&mut deps, env, handle_msg);
+        let handle_result_unwrapped = handle_result.unwrap();
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![
+                secret_toolkit::snip20::increase_allowance_msg(
+                    mock_butt_swbtc_trade_pair().address,
+                    Uint128(u128::MAX),
+                    None,
+                    None,
+                    BLOCK_SIZE,
+                    mock_butt().contract_hash,
+                    mock_butt().address,
+                )
+                .unwrap(),
+                secret_toolkit::snip20::increase_allowance_msg(
+                    mock_butt_swbtc_trade_pair().address,
+                    Uint128(u128::MAX),
+                    None,
+                    None,
+                    BLOCK_SIZE,
+                    mock_swbtc().contract_hash,
+                    mock_swbtc().address,
+                )
+                .unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_init_swap_and_provide() {
+        let (_init_result, mut deps) = init_helper();
+        let amount: Uint128 = Uint128(2);
+        let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+        let mut session: Session;
+        let swap_to_swbtc_msg: Option<Binary> = Some(to_binary(&123).unwrap());
+        let mut receive_msg = ReceiveMsg::InitSwapAndProvide(ZapRequest {
+            swap_to_swbtc_contract: Some(mock_swap_to_swbtc_contract()),
+            swap_to_swbtc_msg: swap_to_swbtc_msg.clone(),
+            first_token_contract_hash: mock_butt().contract_hash,
+            belief_price: Some(Decimal::percent(50)),
+            max_spread: Some(Decimal::percent(1)),
+            intermediate_token: None,
+            max_price_impact: Some(Decimal::percent(2)),
+            deadline: Some(Deadline::Height(12345)),
+            recipient: Some(mock_recipient_address()),
+            callback: Some(mock_callback()),
+            hide_amounts: Some(true),
+            vault: None,
+            pool_id: None,
+            deposit_target: None,
+        });
+        let mut env = mock_env(mock_butt().address, &[]);
+        let mut handle_msg = HandleMsg::Receive {
+            sender: mock_user_address(),
+            from: mock_user_address(),
+            amount,
+            msg: Some(to_binary(&receive_msg).unwrap()),
+        };
+        // when token sent in is not registered
+        let mut handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        // * it raises an error
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err(format!("Token {} is not registered.", mock_butt().address))
+        );
+
+        // when token sent in is butt
+        register_tokens_helper(
+            &mut deps,
+            vec![mock_butt(), mock_swbtc(), mock_butt_swbtc_lp()],
+        );
+        handle_result = handle(&mut deps, env, handle_msg.clone());
+        // * it raises an error
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err(format!(
+                "Token can't be the paired token {} when ReceiveMsg present.",
+                mock_butt().address
+            ))
+        );
+
+        // when the recipient isn't the user and the LP is deposited into the farm
+        env = mock_env(mock_swbtc().address, &[]);
+        // * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err(
+                "A recipient other than the sender can only receive the LP in their wallet."
+            )
+        );
+
+        // when token sent in is swbtc
+        if let ReceiveMsg::InitSwapAndProvide(ref mut zap_request) = receive_msg {
+            zap_request.deposit_target = Some(DepositTarget::Wallet);
+        }
+        if let HandleMsg::Receive { ref mut msg, .. } = handle_msg {
+            *msg = Some(to_binary(&receive_msg).unwrap());
+        }
+        // * it sends the swbtc to itself
+        // * it calls the function to read balance of LP and send to user
+        handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        let mut handle_result_unwrapped = handle_result.unwrap();
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![
+                snip20::send_msg(
+                    env.contract.address.clone(),
+                    amount,
+                    None,
+                    None,
+                    BLOCK_SIZE,
+                    config.swbtc.contract_hash.clone(),
+                    config.swbtc.address.clone(),
+                )
+                .unwrap(),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: env.contract.address.clone(),
+                    callback_code_hash: env.contract_code_hash.clone(),
+                    msg: to_binary(&HandleMsg::SendLpToUserThenDepositIntoFarmContract {}).unwrap(),
+                    send: vec![],
+                })
+            ]
+        );
+        // * it updates config current user
+        // * it updates the config's swap_to_swbtc_contract_address to the contract address
+        // * it stores the swap parameters for the half swap
+        // * it stores the recipient and the callback and counts the zap
+        // * it returns the id of the zap's session in data
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::InitSwapAndProvide { session_id: 1 }
+        );
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.current_user, Some(mock_user_address()));
+        assert_eq!(
+            session.swap_to_swbtc_contract_address,
+            Some(env.contract.address)
+        );
+        assert_eq!(session.belief_price, Some(Decimal::percent(50)));
+        assert_eq!(session.max_spread, Some(Decimal::percent(1)));
+        assert_eq!(session.max_price_impact, Some(Decimal::percent(2)));
+        assert_eq!(session.deadline, Some(Deadline::Height(12345)));
+        assert_eq!(session.recipient, Some(mock_recipient_address()));
+        assert_eq!(session.callback, Some(mock_callback()));
+        assert!(session.hide_amounts);
+        assert_eq!(session.id, 1);
+
+        // when token sent in is not swbtc or butt
+        env = mock_env(mock_butt_swbtc_lp().address, &[]);
+        // NEED TO RESET session.current_user set from previous test
+        session.current_user = None;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // = when swap_to_swbtc_contract is not in the swap routers allowlist
+        // = * it raises an error
+        handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err("Swap to SWBTC contract is not allowed.")
+        );
+        // = when swap_to_swbtc_contract is in the swap routers allowlist
+        handle(
+            &mut deps,
+            mock_env(MOCK_ADMIN, &[]),
+            HandleMsg::AddSwapRouters {
+                routers: vec![mock_swap_to_swbtc_contract().address],
+            },
+        )
+        .unwrap();
+        // = when swap_to_swbtc_msg is present
+        // = * it sends token to a contract to be swapped to swbtc
+        // = * it calls the function to read balance of LP and send to user
+        handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        handle_result_unwrapped = handle_result.unwrap();
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![
+                snip20::send_msg(
+                    mock_swap_to_swbtc_contract().address.clone(),
+                    amount,
+                    swap_to_swbtc_msg.clone(),
+                    None,
+                    BLOCK_SIZE,
+                    mock_butt().contract_hash,
+                    env.message.sender.clone(),
+                )
+                .unwrap(),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: env.contract.address.clone(),
+                    callback_code_hash: env.contract_code_hash.clone(),
+                    msg: to_binary(&HandleMsg::SendLpToUserThenDepositIntoFarmContract {}).unwrap(),
+                    send: vec![],
+                })
+            ]
+        );
+        // * it updates config current user
+        // * it updates the config's swap_to_swbtc_contract_address to the contract address
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.current_user, Some(mock_user_address()));
+        assert_eq!(
+            session.swap_to_swbtc_contract_address,
+            Some(mock_swap_to_swbtc_contract().address)
+        );
+
+        // = when swap_to_swbtc_msg is missing
+        // NEED TO RESET session.current_user set from previous test
+        session.current_user = None;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        receive_msg = ReceiveMsg::InitSwapAndProvide(ZapRequest {
+            swap_to_swbtc_contract: Some(mock_swap_to_swbtc_contract()),
+            swap_to_swbtc_msg: None,
+            first_token_contract_hash: mock_butt_swbtc_lp().contract_hash,
+            belief_price: None,
+            max_spread: None,
+            intermediate_token: None,
+            max_price_impact: None,
+            deadline: None,
+            recipient: None,
+            callback: None,
+            hide_amounts: None,
+            vault: None,
+            pool_id: None,
+            deposit_target: None,
+        });
+        handle_msg = HandleMsg::Receive {
+            sender: mock_user_address(),
+            from: mock_user_address(),
+            amount,
+            msg: Some(to_binary(&receive_msg).unwrap()),
+        };
+        // = * it raises an error
+        handle_result = handle(&mut deps, env.clone(), handle_msg);
+        // * it raises an error
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err("Swap to SWBTC msg missing.")
+        );
+
+        // == when swap_to_swbtc_msg is present
+        // === when swap_to_swbtc_contract is missing
+        receive_msg = ReceiveMsg::InitSwapAndProvide(ZapRequest {
+            swap_to_swbtc_contract: None,
+            swap_to_swbtc_msg,
+            first_token_contract_hash: mock_butt_swbtc_lp().contract_hash,
+            belief_price: None,
+            max_spread: None,
+            intermediate_token: None,
+            max_price_impact: None,
+            deadline: None,
+            recipient: None,
+            callback: None,
+            hide_amounts: None,
+            vault: None,
+            pool_id: None,
+            deposit_target: None,
+        });
+        handle_msg = HandleMsg::Receive {
+            sender: mock_user_address(),
+            from: mock_user_address(),
+            amount,
+            msg: Some(to_binary(&receive_msg).unwrap()),
+        };
+        // === * it raises an error
+        handle_result = handle(&mut deps, env.clone(), handle_msg);
+        // * it raises an error
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err("Swap to SWBTC contract missing.")
+        );
+
+        // when intermediate_token is not BUTT or SWBTC
+        receive_msg = ReceiveMsg::InitSwapAndProvide(ZapRequest {
+            swap_to_swbtc_contract: None,
+            swap_to_swbtc_msg: None,
+            first_token_contract_hash: mock_butt().contract_hash,
+            belief_price: None,
+            max_spread: None,
+            intermediate_token: Some(mock_butt_swbtc_lp().address),
+            max_price_impact: None,
+            deadline: None,
+            recipient: None,
+            callback: None,
+            hide_amounts: None,
+            vault: None,
+            pool_id: None,
+            deposit_target: None,
+        });
+        handle_msg = HandleMsg::Receive {
+            sender: mock_user_address(),
+            from: mock_user_address(),
+            amount,
+            msg: Some(to_binary(&receive_msg).unwrap()),
+        };
+        // * it raises an error
+        handle_result = handle(&mut deps, mock_env(mock_butt().address, &[]), handle_msg);
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err("Intermediate token must be a token of pool 0.")
+        );
+
+        // when intermediate_token is BUTT
+        receive_msg = ReceiveMsg::InitSwapAndProvide(ZapRequest {
+            swap_to_swbtc_contract: None,
+            swap_to_swbtc_msg: None,
+            first_token_contract_hash: mock_butt().contract_hash,
+            belief_price: None,
+            max_spread: None,
+            intermediate_token: Some(mock_butt().address),
+            max_price_impact: None,
+            deadline: None,
+            recipient: None,
+            callback: None,
+            hide_amounts: None,
+            vault: None,
+            pool_id: None,
+            deposit_target: None,
+        });
+        handle_msg = HandleMsg::Receive {
+            sender: mock_user_address(),
+            from: mock_user_address(),
+            amount,
+            msg: Some(to_binary(&receive_msg).unwrap()),
+        };
+        // = when token sent in is swbtc
+        env = mock_env(mock_swbtc().address, &[]);
+        // = * it raises an error
+        handle_result = handle(&mut deps, env, handle_msg.clone());
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err(format!(
+                "Token can't be the paired token {} when ReceiveMsg present.",
+                mock_swbtc().address
+            ))
+        );
+        // = when token sent in is butt
+        env = mock_env(mock_butt().address, &[]);
+        // = * it sends the butt to itself
+        // = * it calls the function to read balance of LP and send to user
+        handle_result = handle(&mut deps, env.clone(), handle_msg);
+        handle_result_unwrapped = handle_result.unwrap();
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![
+                snip20::send_msg(
+                    env.contract.address.clone(),
+                    amount,
+                    None,
+                    None,
+                    BLOCK_SIZE,
+                    mock_butt().contract_hash,
+                    mock_butt().address,
+                )
+                .unwrap(),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: env.contract.address.clone(),
+                    callback_code_hash: env.contract_code_hash.clone(),
+                    msg: to_binary(&HandleMsg::SendLpToUserThenDepositIntoFarmContract {}).unwrap(),
+                    send: vec![],
+                })
+            ]
+        );
+        // = * it stores BUTT as the intermediate token
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.intermediate_token, Some(mock_butt().address));
+        assert_eq!(session.intermediate_token(&mock_pool()), mock_butt());
+        // = * it stores the user as the recipient
+        assert_eq!(session.recipient, Some(mock_user_address()));
+        assert_eq!(session.paired_token(&mock_pool()), mock_swbtc());
+
+        // when the deadline has passed
+        session.current_user = None;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        receive_msg = ReceiveMsg::InitSwapAndProvide(ZapRequest {
+            swap_to_swbtc_contract: None,
+            swap_to_swbtc_msg: None,
+            first_token_contract_hash: mock_swbtc().contract_hash,
+            belief_price: None,
+            max_spread: None,
+            intermediate_token: None,
+            max_price_impact: None,
+            deadline: Some(Deadline::Time(env.block.time - 1)),
+            recipient: None,
+            callback: None,
+            hide_amounts: None,
+            vault: None,
+            pool_id: None,
+            deposit_target: None,
+        });
+        handle_msg = HandleMsg::Receive {
+            sender: mock_user_address(),
+            from: mock_user_address(),
+            amount,
+            msg: Some(to_binary(&receive_msg).unwrap()),
+        };
+        // * it raises an error
+        handle_result = handle(&mut deps, mock_env(mock_swbtc().address, &[]), handle_msg);
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err("Zap deadline has passed.")
+        );
+
+        // when depositing into the vault
+        receive_msg = ReceiveMsg::InitSwapAndProvide(ZapRequest {
+            swap_to_swbtc_contract: None,
+            swap_to_swbtc_msg: None,
+            first_token_contract_hash: mock_swbtc().contract_hash,
+            belief_price: None,
+            max_spread: None,
+            intermediate_token: None,
+            max_price_impact: None,
+            deadline: None,
+            recipient: None,
+            callback: None,
+            hide_amounts: None,
+            vault: Some(true),
+            pool_id: None,
+            deposit_target: None,
+        });
+        handle_msg = HandleMsg::Receive {
+            sender: mock_user_address(),
+            from: mock_user_address(),
+            amount,
+            msg: Some(to_binary(&receive_msg).unwrap()),
+        };
+        // = when the vault isn't set
+        // = * it raises an error
+        handle_result = handle(
+            &mut deps,
+            mock_env(mock_swbtc().address, &[]),
+            handle_msg.clone(),
+        );
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err("Vault is not set.")
+        );
+        // = when the vault is set
+        // = * it stores that the LP goes into the vault
+        set_vault_helper(&mut deps, Uint128(0), Uint128(0));
+        handle(&mut deps, mock_env(mock_swbtc().address, &[]), handle_msg).unwrap();
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert!(session.vault);
+
+        // when zapping claimed BUTT rewards into another pool
+        session.current_user = None;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        let mut zap_request: ZapRequest = ZapRequest {
+            swap_to_swbtc_contract: Some(mock_swap_to_swbtc_contract()),
+            swap_to_swbtc_msg: Some(Binary::from(
+                r#"{ "some_msg": { "some_value": 1 } }"#.as_bytes(),
+            )),
+            first_token_contract_hash: mock_butt().contract_hash,
+            belief_price: None,
+            max_spread: None,
+            intermediate_token: None,
+            max_price_impact: None,
+            deadline: None,
+            recipient: None,
+            callback: None,
+            hide_amounts: None,
+            vault: None,
+            pool_id: Some(1),
+            deposit_target: None,
+        };
+        let env = mock_env(mock_butt().address, &[]);
+        // = when the pool doesn't exist
+        // = * it raises an error
+        handle_msg = HandleMsg::Receive {
+            sender: mock_user_address(),
+            from: mock_user_address(),
+            amount,
+            msg: Some(to_binary(&ReceiveMsg::InitSwapAndProvide(zap_request.clone())).unwrap()),
+        };
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Pool 1 not found.")
+        );
+        // = when the pool exists
+        add_pool_helper(&mut deps, mock_sefi_swbtc_pool());
+        // == when depositing into the vault
+        // == * it raises an error
+        zap_request.vault = Some(true);
+        assert_eq!(
+            handle(
+                &mut deps,
+                env.clone(),
+                HandleMsg::Receive {
+                    sender: mock_user_address(),
+                    from: mock_user_address(),
+                    amount,
+                    msg: Some(
+                        to_binary(&ReceiveMsg::InitSwapAndProvide(zap_request.clone())).unwrap()
+                    ),
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err("Vault only holds pool 0.")
+        );
+        // == when the deposit target isn't a valid contract
+        // == * it raises an error
+        zap_request.vault = None;
+        zap_request.deposit_target = Some(DepositTarget::YieldOptimizer(SecretContract {
+            address: HumanAddr::from("an-address-too-long-to-canonicalize"),
+            contract_hash: "hash".to_string(),
+        }));
+        assert!(handle(
+            &mut deps,
+            env.clone(),
+            HandleMsg::Receive {
+                sender: mock_user_address(),
+                from: mock_user_address(),
+                amount,
+                msg: Some(to_binary(&ReceiveMsg::InitSwapAndProvide(zap_request.clone())).unwrap()),
+            }
+        )
+        .is_err());
+        zap_request.deposit_target = None;
+        // == when the intermediate token isn't a token of the pool
+        // == * it raises an error
+        zap_request.vault = None;
+        zap_request.intermediate_token = Some(mock_butt().address);
+        assert_eq!(
+            handle(
+                &mut deps,
+                env.clone(),
+                HandleMsg::Receive {
+                    sender: mock_user_address(),
+                    from: mock_user_address(),
+                    amount,
+                    msg: Some(
+                        to_binary(&ReceiveMsg::InitSwapAndProvide(zap_request.clone())).unwrap()
+                    ),
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err("Intermediate token must be a token of pool 1.")
+        );
+        // == when the zap is valid
+        // == * it swaps the BUTT to the pool's second token through the swap router
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg).unwrap();
+        assert_eq!(
+            handle_result_unwrapped.messages[0],
+            snip20::send_msg(
+                mock_swap_to_swbtc_contract().address,
+                amount,
+                zap_request.swap_to_swbtc_msg,
+                None,
+                BLOCK_SIZE,
+                mock_butt().contract_hash,
+                mock_butt().address,
+            )
+            .unwrap()
+        );
+        // == * it stores the pool of the zap
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.pool_id, 1);
+        assert_eq!(session.deposit_target, None);
+        assert_eq!(
+            session.intermediate_token(&mock_sefi_swbtc_pool()),
+            mock_swbtc()
+        );
+    }
+
+    #[test]
+    fn test_migrate_liquidity() {
+        let (_init_result, mut deps) = init_helper();
+        let migration_request: MigrationRequest = MigrationRequest {
+            swap_to_swbtc_contract: mock_swap_to_swbtc_contract(),
+            swap_to_swbtc_msgs: mock_migration().swap_to_swbtc_msgs,
+            belief_price: None,
+            max_spread: None,
+            max_price_impact: None,
+            deadline: None,
+            recipient: Some(mock_recipient_address()),
+            hide_amounts: None,
+        };
+        let handle_msg = HandleMsg::Receive {
+            sender: mock_user_address(),
+            from: mock_user_address(),
+            amount: Uint128(5),
+            msg: Some(to_binary(&ReceiveMsg::MigrateLiquidity(migration_request)).unwrap()),
+        };
+        let env = mock_env(mock_sefi_swbtc_lp().address, &[]);
+
+        // when the token isn't the LP token of a pool other than BUTT-SWBTC
+        // * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_butt_swbtc_lp().address, &[]),
+                handle_msg.clone()
+            )
+            .unwrap_err(),
+            StdError::generic_err(format!(
+                "Token {} is not the LP token of a pool to migrate from.",
+                mock_butt_swbtc_lp().address
+            ))
+        );
+
+        // when the token is the LP token of a pool
+        add_pool_helper(&mut deps, mock_sefi_swbtc_pool());
+        // = when the swap router isn't allowed
+        // = * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Swap to SWBTC contract is not allowed.")
+        );
+        // = when the swap router is allowed
+        handle(
+            &mut deps,
+            mock_env(MOCK_ADMIN, &[]),
+            HandleMsg::AddSwapRouters {
+                routers: vec![mock_swap_to_swbtc_contract().address],
+            },
+        )
+        .unwrap();
+        // == when the recipient isn't the user and the LP is deposited into the farm
+        // == * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err(
+                "A recipient other than the sender can only receive the LP in their wallet."
+            )
+        );
+        // == when the recipient receives the LP in their wallet
+        handle(
+            &mut deps,
+            mock_env(MOCK_ADMIN, &[]),
+            HandleMsg::SetDepositTarget {
+                pool_id: 0,
+                deposit_target: DepositTarget::Wallet,
+            },
+        )
+        .unwrap();
+        deps.querier.balance = Uint128(7);
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg.clone()).unwrap();
+        // = * it withdraws the liquidity, routes the tokens, then zaps the SWBTC into the farm
+        let contract_address: HumanAddr = env.contract.address.clone();
+        let self_msg = |msg: HandleMsg| {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_address.clone(),
+                callback_code_hash: env.contract_code_hash.clone(),
+                msg: to_binary(&msg).unwrap(),
+                send: vec![],
+            })
+        };
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![
+                snip20::send_msg(
+                    mock_sefi_swbtc_pool().trade_pair.address,
+                    Uint128(5),
+                    Some(to_binary(&SecretSwapReceiveMsg::WithdrawLiquidity {}).unwrap()),
+                    None,
+                    BLOCK_SIZE,
+                    mock_sefi_swbtc_lp().contract_hash,
+                    mock_sefi_swbtc_lp().address,
+                )
+                .unwrap(),
+                self_msg(HandleMsg::RouteMigratedTokens {}),
+                self_msg(HandleMsg::FinishMigration {}),
+                self_msg(HandleMsg::SendLpToUserThenDepositIntoFarmContract {}),
+            ]
+        );
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::MigrateLiquidity { session_id: 1 }
+        );
+        // = * it starts a session through SWBTC with the balances before the withdrawal
+        let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.current_user, Some(mock_user_address()));
+        assert_eq!(session.recipient, Some(mock_recipient_address()));
+        assert_eq!(session.pool_id, 0);
+        assert_eq!(session.intermediate_token, Some(mock_swbtc().address));
+        assert_eq!(
+            session.swap_to_swbtc_contract_address,
+            Some(mock_swap_to_swbtc_contract().address)
+        );
+        assert_eq!(session.migration, Some(mock_migration()));
+
+        // when the contract is already being used
+        // * it raises an error
+        assert_eq!(
+            handle(&mut deps, env, handle_msg).unwrap_err(),
+            StdError::generic_err("Contract is already being used.")
+        );
+    }
+
+    #[test]
+    fn test_migrate_state() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::MigrateState {};
+
+        // when storage is up to date
+        // * it raises an error
+        assert_eq!(
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Storage is already up to date.")
+        );
+
+        // when storage is version 1
+        deps.storage = MockStorage::new();
+        let mut config_v1: ConfigV1 = ConfigV1 {
+            admin: HumanAddr::from(MOCK_ADMIN),
+            current_user: Some(mock_user_address()),
+            butt: mock_butt(),
+            swbtc: mock_swbtc(),
+            butt_swbtc_farm_pool: mock_butt_swbtc_farm_pool(),
+            butt_swbtc_trade_pair: mock_butt_swbtc_trade_pair(),
+            butt_swbtc_lp: mock_butt_swbtc_lp(),
+            swap_to_swbtc_contract_address: None,
+            butt_amount_to_provide: None,
+            swbtc_amount_to_provide: None,
+            viewing_key: MOCK_VIEWING_KEY.to_string(),
+        };
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(CONFIG_KEY, &config_v1)
+            .unwrap();
+        // = when handling anything else
+        // = * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(MOCK_ADMIN, &[]),
+                HandleMsg::UpdateConfig {
+                    max_price_impact: None,
+                    hide_amounts: None,
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err("Storage is outdated, it must be migrated with MigrateState.")
+        );
+        // = when querying
+        // = * it raises an error
+        assert_eq!(
+            query(&deps, QueryMsg::Config {}).unwrap_err(),
+            StdError::generic_err("Storage is outdated, it must be migrated with MigrateState.")
+        );
+        // = when called by a non-admin
+        // = * it raises an unauthorized error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                handle_msg.clone()
+            )
+            .unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+        // = when a zap is in progress
+        // = * it raises an error
+        assert_eq!(
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Contract is already being used.")
+        );
+        // = when called by the admin
+        config_v1.current_user = None;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(CONFIG_KEY, &config_v1)
+            .unwrap();
+        let handle_result_unwrapped =
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone()).unwrap();
+        // = * it splits the config into the settings and an empty session
+        // = * it doesn't limit the price impact or hide amounts, as version 1 didn't
+        let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                admin: HumanAddr::from(MOCK_ADMIN),
+                contract_address: mock_env(MOCK_ADMIN, &[]).contract.address,
+                butt: mock_butt(),
+                swbtc: mock_swbtc(),
+                butt_swbtc_farm_pool: mock_butt_swbtc_farm_pool(),
+                butt_swbtc_trade_pair: mock_butt_swbtc_trade_pair(),
+                butt_swbtc_lp: mock_butt_swbtc_lp(),
+                max_price_impact: Decimal::one(),
+                hide_amounts: false,
+                viewing_key: MOCK_VIEWING_KEY.to_string(),
+            }
+        );
+        let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session, Session::default());
+        // = * it stores the BUTT-SWBTC trade pair as pool 0 with its viewing keys
+        assert_eq!(pools(&deps.storage).unwrap(), vec![mock_pool()]);
+        for token in pool_tokens(&mock_pool()) {
+            assert_eq!(
+                token_viewing_key(&deps.storage, &token.address).unwrap(),
+                MOCK_VIEWING_KEY
+            );
+        }
+        // = * it sets up the pool's tokens
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            pool_setup_msgs(
+                &mock_pool(),
+                MOCK_VIEWING_KEY,
+                &mock_env(MOCK_ADMIN, &[]).contract_code_hash
+            )
+            .unwrap()
+        );
+        // = * it stores and returns the current storage version
+        assert_eq!(storage_version(&deps.storage).unwrap(), STORAGE_VERSION);
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::MigrateState {
+                storage_version: STORAGE_VERSION
+            }
+        );
+        // = * it accepts queries again
+        assert!(query(&deps, QueryMsg::Config {}).is_ok());
+
+        // when storage is version 2
+        deps.storage = MockStorage::new();
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(CONFIG_KEY, &config)
+            .unwrap();
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(STORAGE_VERSION_KEY, &2u32)
+            .unwrap();
+        let mut session_v2: SessionV2 = SessionV2 {
+            id: 3,
+            current_user: Some(mock_user_address()),
+            ..SessionV2::default()
+        };
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session_v2)
+            .unwrap();
+        let mut pools_storage = PrefixedStorage::new(POOLS_PREFIX, &mut deps.storage);
+        let mut pools_store = AppendStoreMut::attach_or_create(&mut pools_storage).unwrap();
+        for pool in [mock_pool(), mock_sefi_swbtc_pool()].iter() {
+            pools_store
+                .push(&PoolV2 {
+                    id: pool.id,
+                    trade_pair: pool.trade_pair.clone(),
+                    tokens: pool.tokens.clone(),
+                    lp_token: pool.lp_token.clone(),
+                })
+                .unwrap();
+        }
+        // = when called by a non-admin
+        // = * it raises an unauthorized error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                handle_msg.clone()
+            )
+            .unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+        // = when a zap is in progress
+        // = * it raises an error
+        assert_eq!(
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Contract is already being used.")
+        );
+        // = when called by the admin
+        session_v2.current_user = None;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session_v2)
+            .unwrap();
+        let handle_result_unwrapped =
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg).unwrap();
+        // = * it deposits pool 0 into the farm and the other pools into the wallet, as version 2 did
+        assert_eq!(
+            pools(&deps.storage).unwrap(),
+            vec![mock_pool(), mock_sefi_swbtc_pool()]
+        );
+        // = * it keeps the id of the session
+        let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(
+            session,
+            Session {
+                id: 3,
+                ..Session::default()
+            }
+        );
+        // = * it registers to receive the LP token of every pool
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            [mock_pool(), mock_sefi_swbtc_pool()]
+                .iter()
+                .map(|pool| snip20::register_receive_msg(
+                    mock_env(MOCK_ADMIN, &[]).contract_code_hash,
+                    None,
+                    BLOCK_SIZE,
+                    pool.lp_token.contract_hash.clone(),
+                    pool.lp_token.address.clone(),
+                )
+                .unwrap())
+                .collect::<Vec<CosmosMsg>>()
+        );
+        // = * it stores and returns the current storage version
+        assert_eq!(storage_version(&deps.storage).unwrap(), STORAGE_VERSION);
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::MigrateState {
+                storage_version: STORAGE_VERSION
+            }
+        );
+    }
+
+    #[test]
+    fn test_place_exit_order() {
+        let (_init_result, mut deps) = init_helper();
+        let mut exit_order_request: ExitOrderRequest =
+            mock_exit_order_request(Decimal::percent(50), PriceTrigger::Below);
+        let receive = |exit_order_request: &ExitOrderRequest| HandleMsg::Receive {
+            sender: mock_user_address(),
+            from: mock_user_address(),
+            amount: Uint128(5),
+            msg: Some(to_binary(&ReceiveMsg::PlaceExitOrder(exit_order_request.clone())).unwrap()),
+        };
+
+        // = when sent a token other than the BUTT-SWBTC LP or the vault's share token
+        // = * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_share_token().address, &[]),
+                receive(&exit_order_request)
+            )
+            .unwrap_err(),
+            StdError::generic_err(format!(
+                "Token {} is neither the BUTT-SWBTC LP nor the vault's share token.",
+                mock_share_token().address
+            ))
+        );
+
+        // = when sent vault shares
+        set_vault_helper(&mut deps, Uint128(0), Uint128(0));
+        let env = mock_env(mock_share_token().address, &[]);
+        // == when the shares aren't worth any LP
+        // == * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), receive(&exit_order_request)).unwrap_err(),
+            StdError::generic_err("LP amount to exit must be greater than zero.")
+        );
+        set_vault_helper(&mut deps, Uint128(10), Uint128(20));
+        // == when the expiry has passed
+        // == * it raises an error
+        exit_order_request.expiry = Deadline::Height(env.block.height - 1);
+        assert_eq!(
+            handle(&mut deps, env.clone(), receive(&exit_order_request)).unwrap_err(),
+            StdError::generic_err("Exit order expiry has passed.")
+        );
+        exit_order_request.expiry = Deadline::Height(env.block.height);
+        // == when the exit order is valid
+        let handle_result_unwrapped = handle(&mut deps, env, receive(&exit_order_request)).unwrap();
+        // == * it escrows the shares in a pending exit order
+        assert_eq!(handle_result_unwrapped.messages, vec![]);
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::PlaceExitOrder { exit_order_id: 0 }
+        );
+        assert_eq!(
+            exit_order(&deps.storage, 0).unwrap(),
+            ExitOrder {
+                id: 0,
+                user: mock_user_address(),
+                token: mock_share_token(),
+                amount: Uint128(5),
+                target_price: Decimal::percent(50),
+                trigger: PriceTrigger::Below,
+                belief_price: Some(Decimal::one()),
+                max_spread: Some(Decimal::percent(1)),
+                expiry: exit_order_request.expiry,
+                status: OrderStatus::Pending,
+            }
+        );
+
+        // = when sent BUTT-SWBTC LP
+        handle(
+            &mut deps,
+            mock_env(mock_butt_swbtc_lp().address, &[]),
+            receive(&exit_order_request),
+        )
+        .unwrap();
+        // = * it escrows the LP in a pending exit order
+        assert_eq!(
+            exit_order(&deps.storage, 1).unwrap().token,
+            mock_butt_swbtc_lp()
+        );
+    }
+
+    #[test]
+    fn test_place_order() {
+        let (_init_result, mut deps) = init_helper();
+        let mut order_request: OrderRequest =
+            mock_order_request(Decimal::percent(50), PriceTrigger::Above);
+        let env = mock_env(mock_swbtc().address, &[]);
+        let receive = |order_request: &OrderRequest| HandleMsg::Receive {
+            sender: mock_user_address(),
+            from: mock_user_address(),
+            amount: Uint128(5),
+            msg: Some(to_binary(&ReceiveMsg::PlaceOrder(order_request.clone())).unwrap()),
+        };
+
+        // = when the token isn't registered
+        // = * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), receive(&order_request)).unwrap_err(),
+            StdError::generic_err(format!("Token {} is not registered.", mock_swbtc().address))
+        );
+
+        // = when the token is registered
+        register_tokens_helper(&mut deps, vec![mock_swbtc()]);
+        // == when the pool of the zap doesn't exist
+        // == * it raises an error
+        order_request.zap_request.pool_id = Some(1);
+        assert_eq!(
+            handle(&mut deps, env.clone(), receive(&order_request)).unwrap_err(),
+            StdError::generic_err("Pool 1 not found.")
+        );
+        // == when the zap request is invalid
+        // == * it raises an error
+        order_request.zap_request.pool_id = None;
+        order_request.zap_request.intermediate_token = Some(mock_user_address());
+        assert_eq!(
+            handle(&mut deps, env.clone(), receive(&order_request)).unwrap_err(),
+            StdError::generic_err("Intermediate token must be a token of pool 0.")
+        );
+        // == when the expiry has passed
+        // == * it raises an error
+        order_request.zap_request.intermediate_token = None;
+        order_request.expiry = Deadline::Height(env.block.height - 1);
+        assert_eq!(
+            handle(&mut deps, env.clone(), receive(&order_request)).unwrap_err(),
+            StdError::generic_err("Order expiry has passed.")
+        );
+        // == when the order is valid
+        order_request.expiry = Deadline::Height(env.block.height);
+        let handle_result_unwrapped = handle(&mut deps, env, receive(&order_request)).unwrap();
+        // == * it escrows the tokens in a pending order
+        assert_eq!(handle_result_unwrapped.messages, vec![]);
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::PlaceOrder { order_id: 0 }
+        );
+        assert_eq!(
+            order(&deps.storage, 0).unwrap(),
+            Order {
+                id: 0,
+                user: mock_user_address(),
+                token: mock_swbtc(),
+                amount: Uint128(5),
+                target_price: Decimal::percent(50),
+                trigger: PriceTrigger::Above,
+                expiry: order_request.expiry,
+                zap_request: order_request.zap_request,
+                status: OrderStatus::Pending,
+            }
+        );
+    }
+
+    #[test]
+    fn test_process_subscriptions() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::ProcessSubscriptions {
+            subscription_ids: vec![0, 1],
+        };
+        let installment_msg = |env: &Env, subscription_id: u64, amount: u128| {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: env.contract.address.clone(),
+                callback_code_hash: env.contract_code_hash.clone(),
+                msg: to_binary(&HandleMsg::ZapInstallment {
+                    subscription_id,
+                    amount: Uint128(amount),
+                })
+                .unwrap(),
+                send: vec![],
+            })
+        };
+
+        // = when more subscriptions than a batch holds are given
+        // = * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                HandleMsg::ProcessSubscriptions {
+                    subscription_ids: (0..MAX_BATCH_SIZE as u64 + 1).collect()
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err(format!(
+                "At most {} subscriptions can be processed at once.",
+                MAX_BATCH_SIZE
+            ))
+        );
+
+        // = when no subscriptions are given
+        // = * it processes nothing
+        let handle_result_unwrapped = handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            HandleMsg::ProcessSubscriptions {
+                subscription_ids: vec![],
+            },
+        )
+        .unwrap();
+        assert_eq!(handle_result_unwrapped.messages, vec![]);
+
+        // = when there are subscriptions
+        register_tokens_helper(&mut deps, vec![mock_swbtc()]);
+        subscribe_helper(&mut deps, mock_subscription_request(2), Uint128(4));
+        subscribe_helper(&mut deps, mock_subscription_request(1), Uint128(2));
+        // == when the contract is being used
+        // == * it raises an error
+        let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        session.current_user = Some(mock_user_address());
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                handle_msg.clone()
+            )
+            .unwrap_err(),
+            StdError::generic_err("Contract is already being used.")
+        );
+        session.current_user = None;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // == when a subscription id doesn't exist
+        // == * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                HandleMsg::ProcessSubscriptions {
+                    subscription_ids: vec![2]
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err("Subscription 2 not found.")
+        );
+        // == when installments are due
+        let mut env = mock_env(mock_user_address(), &[]);
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg.clone()).unwrap();
+        // == * it zaps an installment of each, one after the other
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![installment_msg(&env, 0, 2), installment_msg(&env, 1, 2)]
+        );
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::ProcessSubscriptions {
+                processed_subscription_ids: vec![0, 1],
+            }
+        );
+        // == * it takes the installments from the balances and schedules the next ones
+        let subscription_0: Subscription = subscription(&deps.storage, 0).unwrap();
+        assert_eq!(subscription_0.balance, Uint128(2));
+        assert_eq!(subscription_0.next_installment_time, env.block.time + 60);
+        assert_eq!(subscription_0.status, SubscriptionStatus::Active);
+        // == * it completes the subscriptions whose balance is used up
+        assert_eq!(
+            subscription(&deps.storage, 1).unwrap().status,
+            SubscriptionStatus::Completed
+        );
+        // == when no installment is due
+        // == * it processes nothing
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg.clone()).unwrap();
+        assert_eq!(handle_result_unwrapped.messages, vec![]);
+        // == when the next period has passed
+        env.block.time += 60;
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg).unwrap();
+        // == * it zaps the next installment of the active subscriptions
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![installment_msg(&env, 0, 2)]
+        );
+        assert_eq!(
+            subscription(&deps.storage, 0).unwrap().status,
+            SubscriptionStatus::Completed
+        );
+        // == when the next installment time would overflow
+        let mut subscription_request: SubscriptionRequest = mock_subscription_request(2);
+        subscription_request.period = u64::MAX;
+        let subscription_id: u64 = subscribe_helper(&mut deps, subscription_request, Uint128(4));
+        handle(
+            &mut deps,
+            env.clone(),
+            HandleMsg::ProcessSubscriptions {
+                subscription_ids: vec![subscription_id],
+            },
+        )
+        .unwrap();
+        // == * it schedules the next installment at the latest possible time
+        assert_eq!(
+            subscription(&deps.storage, subscription_id)
+                .unwrap()
+                .next_installment_time,
+            u64::MAX
+        );
+    }
+
+    #[test]
+    fn test_provide_liquidity_to_trade_pair() {
+        let (_init_result, mut deps) = init_helper();
+        let butt_amount: Uint128 = Uint128(5);
+        let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+        let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+
+        // = when called by BUTT
+        let env: Env = mock_env(mock_butt().address, &[]);
+        // == when called from non butt_swbtc_trade_pair
+        let handle_msg = HandleMsg::Receive {
+            sender: config.butt_swbtc_lp.address.clone(),
+            from: config.butt_swbtc_lp.address.clone(),
+            amount: butt_amount,
+            msg: None,
+        };
+        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        // == * it raises an unauthorized error
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+        // == when called from butt_swbtc_trade_pair
+        // === when the SWBTC amount to provide is missing
+        let handle_msg = HandleMsg::Receive {
+            sender: config.butt_swbtc_trade_pair.address.clone(),
+            from: config.butt_swbtc_trade_pair.address.clone(),
+            amount: butt_amount,
+            msg: None,
+        };
+        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        // === * it raises an error
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err(format!(
+                "Amount of {} to provide is missing.",
+                mock_swbtc().address
+            ))
+        );
+        // === when the SWBTC amount to provide is zero
+        session.amounts_to_provide[1] = Some(Uint128(0));
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // === * it raises an error
+        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err(format!(
+                "Amount of {} to provide must be greater than zero.",
+                mock_swbtc().address
+            ))
+        );
+        // === when the SWBTC amount to provide is present
+        session.amounts_to_provide[1] = Some(Uint128(10));
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+
+        // === * it provides the balance of BUTT and SWBTC of contract to trade pair contract
+        let handle_result = handle(&mut deps, env, handle_msg.clone());
+        let handle_result_unwrapped = handle_result.unwrap();
+        let provide_liquidity_msg = SecretSwapHandleMsg::ProvideLiquidity {
+            assets: [
+                Asset {
+                    amount: Uint128(10),
+                    info: AssetInfo::Token {
+                        contract_addr: config.swbtc.address,
+                        token_code_hash: config.swbtc.contract_hash,
+                        viewing_key: "SecretSwap".to_string(),
+                    },
+                },
+                Asset {
+                    amount: butt_amount,
+                    info: AssetInfo::Token {
+                        contract_addr: config.butt.address,
+                        token_code_hash: config.butt.contract_hash.clone(),
+                        viewing_key: "SecretSwap".to_string(),
+                    },
+                },
+            ],
+            slippage_tolerance: None,
+        };
+        let cosmos_msg = provide_liquidity_msg
+            .to_cosmos_msg(
+                mock_butt_swbtc_trade_pair().contract_hash,
+                mock_butt_swbtc_trade_pair().address,
+                None,
+            )
+            .unwrap();
+        assert_eq!(handle_result_unwrapped.messages, vec![cosmos_msg]);
+        // === * it returns the provided amounts in data
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::ProvideLiquidityToTradePair {
+                butt_amount,
+                swbtc_amount: Uint128(10),
+            }
+        );
+        // === * it records the contract's LP balance before the LP is minted
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.lp_balance, Some(Uint128(MOCK_AMOUNT)));
+
+        // = when intermediate token is BUTT
+        session.intermediate_token = Some(mock_butt().address);
+        session.amounts_to_provide[0] = None;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // == when called by SWBTC from butt_swbtc_trade_pair
+        let env: Env = mock_env(mock_swbtc().address, &[]);
+        let swbtc_amount: Uint128 = Uint128(3);
+        let handle_msg = HandleMsg::Receive {
+            sender: config.butt_swbtc_trade_pair.address.clone(),
+            from: config.butt_swbtc_trade_pair.address.clone(),
+            amount: swbtc_amount,
+            msg: None,
+        };
+        // === when the BUTT amount to provide is missing
+        // === * it raises an error
+        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err(format!(
+                "Amount of {} to provide is missing.",
+                mock_butt().address
+            ))
+        );
+        // === when the BUTT amount to provide is present
+        session.amounts_to_provide[0] = Some(butt_amount);
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // === when the deadline has passed
+        session.deadline = Some(Deadline::Height(env.block.height - 1));
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // === * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Zap deadline has passed.")
+        );
+        // === when the deadline has not passed
+        session.deadline = Some(Deadline::Height(env.block.height));
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // === * it provides the received SWBTC and the stored BUTT to the trade pair contract
+        let handle_result = handle(&mut deps, env, handle_msg);
+        let provide_liquidity_msg = SecretSwapHandleMsg::ProvideLiquidity {
+            assets: [
+                Asset {
+                    amount: swbtc_amount,
+                    info: AssetInfo::Token {
+                        contract_addr: mock_swbtc().address,
+                        token_code_hash: mock_swbtc().contract_hash,
+                        viewing_key: "SecretSwap".to_string(),
+                    },
+                },
+                Asset {
+                    amount: butt_amount,
+                    info: AssetInfo::Token {
+                        contract_addr: mock_butt().address,
+                        token_code_hash: mock_butt().contract_hash,
+                        viewing_key: "SecretSwap".to_string(),
+                    },
+                },
+            ],
+            slippage_tolerance: None,
+        };
+        assert_eq!(
+            handle_result.unwrap().messages,
+            vec![provide_liquidity_msg
+                .to_cosmos_msg(
+                    mock_butt_swbtc_trade_pair().contract_hash,
+                    mock_butt_swbtc_trade_pair().address,
+                    None,
+                )
+                .unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_receive() {
+        let (_init_result, mut deps) = init_helper();
+
+        // when sent and not BUTT or SWBTC and without a ReceiveMsg
+        let env = mock_env(mock_butt_swbtc_lp().address, &[]);
+        let handle_msg = HandleMsg::Receive {
+            sender: mock_user_address(),
+            from: mock_user_address(),
+            amount: Uint128(5),
+            msg: None,
+        };
+        let handle_result = handle(&mut deps, env, handle_msg);
+        // == * it raises an error
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err("Receive message combination is wrong.")
+        );
+
+        // when a migration is in progress
+        let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        session.current_user = Some(mock_user_address());
+        session.swap_to_swbtc_contract_address = Some(mock_swap_to_swbtc_contract().address);
+        session.migration = Some(mock_migration());
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        let env = mock_env(mock_swbtc().address, &[]);
+        // = when SWBTC is sent by someone other than the swap router
+        // = * it raises an unauthorized error
+        assert_eq!(
+            handle(
+                &mut deps,
+                env.clone(),
+                HandleMsg::Receive {
+                    sender: mock_user_address(),
+                    from: mock_user_address(),
+                    amount: Uint128(5),
+                    msg: None,
+                }
+            )
+            .unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+        // = when SWBTC is sent by the swap router
+        let handle_result_unwrapped = handle(
+            &mut deps,
+            env,
+            HandleMsg::Receive {
+                sender: mock_swap_to_swbtc_contract().address,
+                from: mock_swap_to_swbtc_contract().address,
+                amount: Uint128(5),
+                msg: None,
+            },
+        )
+        .unwrap();
+        // = * it keeps the SWBTC until the migration finishes
+        assert_eq!(handle_result_unwrapped.messages, vec![]);
+        // = * it reports the SWBTC received, padded like every other answer
+        let data: Binary = handle_result_unwrapped.data.unwrap();
+        assert_eq!(data.len() % BLOCK_SIZE, 0);
+        assert_eq!(
+            from_binary::<HandleAnswer>(&data).unwrap(),
+            HandleAnswer::Receive {
+                swbtc_amount: Uint128(5)
+            }
+        );
+    }
+
+    #[test]
+    fn test_record_vault_rewards() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::RecordVaultRewards {
+            butt_balance: Uint128(100),
+        };
+        let env = mock_env(mock_env(MOCK_ADMIN, &[]).contract.address, &[]);
+
+        // when called by non-contract
+        // * it raises an unauthorized error
+        assert_eq!(
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // when called by contract after the vault deposits into the farm
+        set_vault_helper(&mut deps, Uint128(10), Uint128(20));
+        deps.querier.balance = Uint128(150);
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg).unwrap();
+        // * it adds the rewards the farm paid out to the vault's pending rewards
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::RecordVaultRewards {
+                reward_amount: Uint128(50)
+            }
+        );
+        let vault: Vault = TypedStore::attach(&deps.storage).load(VAULT_KEY).unwrap();
+        assert_eq!(vault.pending_rewards, Uint128(50));
+
+        // = when the vault is harvested next
+        deps.querier.balance = Uint128(1_150);
+        let handle_result_unwrapped = handle(
+            &mut deps,
+            env,
+            HandleMsg::CompoundHarvestedRewards {
+                harvester: mock_user_address(),
+                butt_balance: Uint128(150),
+            },
+        )
+        .unwrap();
+        // = * it compounds the rewards of the deposit with those of the harvest
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::CompoundHarvestedRewards {
+                session_id: Some(1),
+                reward_amount: Uint128(1_050),
+                bounty_amount: Uint128(10),
+            }
+        );
+        // = * it clears the pending rewards
+        let vault: Vault = TypedStore::attach(&deps.storage).load(VAULT_KEY).unwrap();
+        assert_eq!(vault.pending_rewards, Uint128(0));
+    }
+
+    #[test]
+    fn test_register_tokens() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::RegisterTokens {
+            tokens: vec![mock_butt(), mock_swbtc()],
+        };
+
+        // = when called by a non-admin
+        // = * it raises an Unauthorized error
+        let handle_result = handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            handle_msg.clone(),
+        );
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when called by the admin
+        let env = mock_env(MOCK_ADMIN, &[]);
+        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        let handle_result_unwrapped = handle_result.unwrap();
+        // = * it stores the tokens with their token info
+        assert_eq!(
+            registered_token(&deps.storage, &mock_butt().address).unwrap(),
+            Some(mock_registered_token(mock_butt()))
+        );
+        assert_eq!(
+            registered_token(&deps.storage, &mock_swbtc().address).unwrap(),
+            Some(mock_registered_token(mock_swbtc()))
+        );
+        // = * it sends a message to register receive for the token
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![
+                snip20::register_receive_msg(
+                    env.contract_code_hash.clone(),
+                    None,
+                    BLOCK_SIZE,
+                    mock_butt().contract_hash,
+                    mock_butt().address,
+                )
+                .unwrap(),
+                snip20::register_receive_msg(
+                    env.contract_code_hash.clone(),
+                    None,
+                    BLOCK_SIZE,
+                    mock_swbtc().contract_hash,
+                    mock_swbtc().address,
+                )
+                .unwrap(),
+            ]
+        );
+
+        // = when a token is registered again
+        handle(&mut deps, env, handle_msg).unwrap();
+        // = * it does not list the token twice
+        let registered_tokens_list_storage =
+            ReadonlyPrefixedStorage::new(REGISTERED_TOKENS_LIST_PREFIX, &deps.storage);
+        let registered_tokens_list_store =
+            AppendStore::<HumanAddr, _>::attach(&registered_tokens_list_storage)
+                .unwrap()
+                .unwrap();
+        assert_eq!(registered_tokens_list_store.len(), 2);
+
+        // = when a token does not answer the token info query
+        // = * it raises an error
+        let handle_result = handle(
+            &mut deps,
+            mock_env(MOCK_ADMIN, &[]),
+            HandleMsg::RegisterTokens {
+                tokens: vec![mock_swap_to_swbtc_contract()],
+            },
+        );
+        assert!(handle_result.is_err());
+    }
+
+    #[test]
+    fn test_remove_swap_routers() {
+        let (_init_result, mut deps) = init_helper();
+        handle(
+            &mut deps,
+            mock_env(MOCK_ADMIN, &[]),
+            HandleMsg::AddSwapRouters {
+                routers: vec![
+                    mock_swap_to_swbtc_contract().address,
+                    mock_butt_swbtc_trade_pair().address,
+                ],
+            },
+        )
+        .unwrap();
+        let handle_msg = HandleMsg::RemoveSwapRouters {
+            routers: vec![mock_swap_to_swbtc_contract().address],
+        };
+
+        // = when called by a non-admin
+        // = * it raises an Unauthorized error
+        let handle_result = handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            handle_msg.clone(),
+        );
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when called by the admin
+        // = * it removes the routers from the allowlist
+        handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg).unwrap();
+        let swap_routers: Vec<HumanAddr> = TypedStore::attach(&deps.storage)
+            .load(SWAP_ROUTERS_KEY)
+            .unwrap();
+        assert_eq!(swap_routers, vec![mock_butt_swbtc_trade_pair().address]);
+    }
+
+    #[test]
+    fn test_rescue_tokens() {
+        let (_init_result, mut deps) = init_helper();
+        let denom: String = "uscrt".to_string();
+        let mut handle_msg = HandleMsg::RescueTokens {
+            amount: Uint128(MOCK_AMOUNT),
+            denom: Some(denom.clone()),
+            token: Some(mock_butt()),
+        };
+        // = when called by a non-admin
+        // = * it raises an Unauthorized error
+        let mut env: Env = mock_env(mock_user_address(), &[]);
+        let handle_result = handle(&mut deps, env, handle_msg.clone());
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when called by the admin
+        env = mock_env(MOCK_ADMIN, &[]);
+        // == when only denom is specified
+        handle_msg = HandleMsg::RescueTokens {
+            amount: Uint128(MOCK_AMOUNT),
+            denom: Some(denom.clone()),
+            token: None,
+        };
+        // === * it sends the amount specified of the coin of the denom to the admin
+        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        let handle_result_unwrapped = handle_result.unwrap();
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: env.contract.address,
+                to_address: HumanAddr(MOCK_ADMIN.to_string()),
+                amount: vec![Coin {
+                    denom: denom,
+                    amount: Uint128(MOCK_AMOUNT)
+                }],
+            })]
         );
 
-        // * it sets the viewing key for BUTT, SWBTC & BUTT-SWBTC LP
+        // == when only token is specified
+        handle_msg = HandleMsg::RescueTokens {
+            amount: Uint128(MOCK_AMOUNT),
+            denom: None,
+            token: Some(mock_butt()),
+        };
+        // == * it sends the amount specified of the token to the admin
+        let handle_result = handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone());
+        let handle_result_unwrapped = handle_result.unwrap();
         assert_eq!(
-            init_result.unwrap().messages,
-            vec![snip20::set_viewing_key_msg(
-                MOCK_VIEWING_KEY.to_string(),
+            handle_result_unwrapped.messages,
+            vec![snip20::transfer_msg(
+                HumanAddr::from(MOCK_ADMIN),
+                Uint128(MOCK_AMOUNT),
                 None,
                 BLOCK_SIZE,
-                mock_butt_swbtc_lp().contract_hash,
-                mock_butt_swbtc_lp().address,
+                mock_butt().contract_hash,
+                mock_butt().address,
             )
-            .unwrap(),]
+            .unwrap()]
         );
     }
 
-    // === QUERY ===
     #[test]
-    fn test_query_config() {
-        let (_init_result, deps) = init_helper();
+    fn test_rotate_viewing_key() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::RotateViewingKey {
+            entropy: "pepper".to_string(),
+        };
+
+        // = when called by a non-admin
+        // = * it raises an Unauthorized error
+        let handle_result = handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            handle_msg.clone(),
+        );
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when called by the admin
+        let handle_result_unwrapped =
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone()).unwrap();
         let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
-        let config_from_query: ConfigPublic =
-            from_binary(&query(&deps, QueryMsg::Config {}).unwrap()).unwrap();
-        assert_eq!(config.with_public_attributes().unwrap(), config_from_query);
+        // = * it derives a fresh viewing key
+        assert_ne!(config.viewing_key, MOCK_VIEWING_KEY);
+        // = * it sets and stores the key for BUTT, SWBTC & BUTT-SWBTC LP
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            set_viewing_key_msgs(&pool_tokens(&mock_pool()), &config.viewing_key).unwrap()
+        );
+        for token in pool_tokens(&mock_pool()) {
+            assert_eq!(
+                token_viewing_key(&deps.storage, &token.address).unwrap(),
+                config.viewing_key
+            );
+        }
+
+        // = when rotated again with the same entropy
+        handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg).unwrap();
+        // = * it derives a different key
+        let rotated_config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+        assert_ne!(rotated_config.viewing_key, config.viewing_key);
     }
 
-    // === HANDLE ===
     #[test]
-    fn test_increase_allowance_for_pair_contract() {
+    fn test_route_migrated_tokens() {
         let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::RouteMigratedTokens {};
+        let env = mock_env(mock_env(MOCK_ADMIN, &[]).contract.address, &[]);
 
-        // context when called by anyone
-        let env = mock_env(mock_user_address(), &[]);
-        // = * it increases the allowance for butt and swbtc
-        let handle_msg = HandleMsg::IncreaseAllowanceForPairContract {};
-        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
-        let handle_result_unwrapped = handle_result.unwrap();
+        // when called by non-contract
+        // * it raises an unauthorized error
+        assert_eq!(
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // when called by contract
+        // = when no migration is in progress
+        // = * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Contract wasn't called properly.")
+        );
+        // = when a migration is in progress
+        add_pool_helper(&mut deps, mock_sefi_swbtc_pool());
+        let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        let mut migration: Migration = mock_migration();
+        migration.swap_to_swbtc_msgs = [None, None];
+        session.current_user = Some(mock_user_address());
+        session.swap_to_swbtc_contract_address = Some(mock_swap_to_swbtc_contract().address);
+        session.migration = Some(migration);
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        deps.querier.balance = Uint128(10);
+        // == when a withdrawn token has no swap msg
+        // == * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Swap to SWBTC msg missing.")
+        );
+        // == when the withdrawn tokens have swap msgs
+        session.migration = Some(mock_migration());
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        let handle_result_unwrapped = handle(&mut deps, env, handle_msg).unwrap();
+        // == * it sends the withdrawn tokens other than SWBTC to the swap router
         assert_eq!(
             handle_result_unwrapped.messages,
-            vec![
-                secret_toolkit::snip20::increase_allowance_msg(
-                    mock_butt_swbtc_trade_pair().address,
-                    Uint128(u128::MAX),
-                    None,
-                    None,
-                    BLOCK_SIZE,
-                    mock_butt().contract_hash,
-                    mock_butt().address,
-                )
-                .unwrap(),
-                secret_toolkit::snip20::increase_allowance_msg(
-                    mock_butt_swbtc_trade_pair().address,
-                    Uint128(u128::MAX),
-                    None,
-                    None,
-                    BLOCK_SIZE,
-                    mock_swbtc().contract_hash,
-                    mock_swbtc().address,
-                )
-                .unwrap()
-            ]
+            vec![snip20::send_msg(
+                mock_swap_to_swbtc_contract().address,
+                Uint128(3),
+                mock_migration().swap_to_swbtc_msgs[0].clone(),
+                None,
+                BLOCK_SIZE,
+                mock_sefi().contract_hash,
+                mock_sefi().address,
+            )
+            .unwrap()]
+        );
+        // == * it returns the withdrawn amounts in data
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::RouteMigratedTokens {
+                withdrawn_amounts: [Uint128(3), Uint128(3)]
+            }
         );
     }
 
     #[test]
-    fn test_init_swap_and_provide() {
+    fn test_send_lp_to_user_then_deposit_into_farm_contract() {
         let (_init_result, mut deps) = init_helper();
-        let amount: Uint128 = Uint128(2);
         let mut config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
-        let swap_to_swbtc_msg: Option<Binary> = Some(to_binary(&123).unwrap());
-        let mut receive_msg = ReceiveMsg::InitSwapAndProvide {
-            swap_to_swbtc_contract: Some(mock_swap_to_swbtc_contract()),
-            swap_to_swbtc_msg: swap_to_swbtc_msg.clone(),
-            first_token_contract_hash: mock_butt().contract_hash,
-        };
-        // when token sent in is butt
-        let mut env = mock_env(mock_butt().address, &[]);
-        let mut handle_msg = HandleMsg::Receive {
-            sender: mock_user_address(),
-            from: mock_user_address(),
-            amount,
-            msg: Some(to_binary(&receive_msg).unwrap()),
-        };
-        let mut handle_result = handle(&mut deps, env, handle_msg.clone());
-        // * it raises an error
+        let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        let handle_msg = HandleMsg::SendLpToUserThenDepositIntoFarmContract {};
+
+        // when called by non-contract
+        let mut env = mock_env(MOCK_ADMIN, &[]);
+        // = * it raises an unauthorized error
+        let mut handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
         assert_eq!(
             handle_result.unwrap_err(),
-            StdError::generic_err("Token can't be BUTT when ReceiveMsg present.")
+            StdError::Unauthorized { backtrace: None }
         );
 
-        // when token sent in is swbtc
-        env = mock_env(mock_swbtc().address, &[]);
-        // * it sends the swbtc to itself
-        // * it calls the function to read balance of LP and send to user
+        // when called by contract
+        env = mock_env(env.contract.address, &[]);
+        // = when session current_user is missing
+        // = * it raises an error
         handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
-        let mut handle_result_unwrapped = handle_result.unwrap();
         assert_eq!(
-            handle_result_unwrapped.messages,
-            vec![
-                snip20::send_msg(
-                    env.contract.address.clone(),
-                    amount,
-                    None,
-                    None,
-                    BLOCK_SIZE,
-                    config.swbtc.contract_hash.clone(),
-                    config.swbtc.address.clone(),
-                )
-                .unwrap(),
-                CosmosMsg::Wasm(WasmMsg::Execute {
-                    contract_addr: env.contract.address.clone(),
-                    callback_code_hash: env.contract_code_hash.clone(),
-                    msg: to_binary(&HandleMsg::SendLpToUserThenDepositIntoFarmContract {}).unwrap(),
-                    send: vec![],
-                })
-            ]
+            handle_result.unwrap_err(),
+            StdError::generic_err("Contract wasn't called properly.")
         );
-        // * it updates config current user
-        // * it updates the config's swap_to_swbtc_contract_address to the contract address
-        config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
-        assert_eq!(config.current_user, Some(mock_user_address()));
+
+        // = when session current_user is present
+        session.current_user = Some(mock_user_address());
+        session.recipient = Some(mock_recipient_address());
+        session.callback = Some(mock_callback());
+        session.id = 1;
+        session.amounts_to_provide[0] = Some(Uint128(1));
+        session.amounts_to_provide[1] = Some(Uint128(1));
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // == when contract's balance of butt-swbtc-lp is zero
+        // == * it raises an error
+        // handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        // assert_eq!(
+        //     handle_result.unwrap_err(),
+        //     StdError::generic_err("Result BUTT-SWBTC LP must be greater than zero.",)
+        // );
+        // == when the deadline has passed
+        session.deadline = Some(Deadline::Time(env.block.time - 1));
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // == * it raises an error
         assert_eq!(
-            config.swap_to_swbtc_contract_address,
-            Some(env.contract.address)
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Zap deadline has passed.")
         );
-
-        // when token sent in is not swbtc or butt
-        env = mock_env(mock_butt_swbtc_lp().address, &[]);
-        // NEED TO RESET config.current_user set from previous test
-        config.current_user = None;
+        // == when the deadline has not passed
+        session.deadline = Some(Deadline::Time(env.block.time));
         TypedStoreMut::attach(&mut deps.storage)
-            .store(CONFIG_KEY, &config)
+            .store(SESSION_KEY, &session)
             .unwrap();
-        // = when swap_to_swbtc_msg is present
-        // = * it sends token to a contract to be swapped to swbtc
-        // = * it calls the function to read balance of LP and send to user
+        // == when contract's balance of butt-swbtc-lp is greater than zero
+        // == * it sends the balance of the token to the recipient
+        // == * it deposits the recipient's LP into the farm contract
+        // == * it executes the callback with the result of the zap
         handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
-        handle_result_unwrapped = handle_result.unwrap();
+        let handle_result_unwrapped = handle_result.unwrap();
         assert_eq!(
             handle_result_unwrapped.messages,
             vec![
-                snip20::send_msg(
-                    mock_swap_to_swbtc_contract().address.clone(),
-                    amount,
-                    swap_to_swbtc_msg.clone(),
+                snip20::transfer_msg(
+                    mock_recipient_address(),
+                    Uint128(MOCK_AMOUNT),
                     None,
                     BLOCK_SIZE,
-                    mock_butt().contract_hash,
-                    env.message.sender.clone(),
+                    config.butt_swbtc_lp.contract_hash.clone(),
+                    config.butt_swbtc_lp.address.clone(),
                 )
                 .unwrap(),
-                CosmosMsg::Wasm(WasmMsg::Execute {
-                    contract_addr: env.contract.address.clone(),
-                    callback_code_hash: env.contract_code_hash.clone(),
-                    msg: to_binary(&HandleMsg::SendLpToUserThenDepositIntoFarmContract {}).unwrap(),
-                    send: vec![],
-                })
+                snip20::send_from_msg(
+                    mock_recipient_address(),
+                    config.butt_swbtc_farm_pool.address.clone(),
+                    Uint128(MOCK_AMOUNT),
+                    Some(to_binary(&FarmReceiveMsg::DepositIncentivizedToken {}).unwrap()),
+                    None,
+                    BLOCK_SIZE,
+                    config.butt_swbtc_lp.contract_hash.clone(),
+                    config.butt_swbtc_lp.address.clone(),
+                )
+                .unwrap(),
+                CallbackHandleMsg::ZapCompleted {
+                    user: mock_user_address(),
+                    lp_amount: Uint128(MOCK_AMOUNT),
+                    butt_provided: Uint128(1),
+                    swbtc_provided: Uint128(1),
+                    session_id: 1,
+                    msg: mock_callback().msg,
+                }
+                .to_cosmos_msg(mock_callback().code_hash, mock_callback().contract, None)
+                .unwrap()
             ]
         );
-        // * it updates config current user
-        // * it updates the config's swap_to_swbtc_contract_address to the contract address
-        config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
-        assert_eq!(config.current_user, Some(mock_user_address()));
-        assert_eq!(
-            config.swap_to_swbtc_contract_address,
-            Some(mock_swap_to_swbtc_contract().address)
-        );
-
-        // = when swap_to_swbtc_msg is missing
-        // NEED TO RESET config.current_user set from previous test
-        config.current_user = None;
-        TypedStoreMut::attach(&mut deps.storage)
-            .store(CONFIG_KEY, &config)
-            .unwrap();
-        receive_msg = ReceiveMsg::InitSwapAndProvide {
-            swap_to_swbtc_contract: Some(mock_swap_to_swbtc_contract()),
-            swap_to_swbtc_msg: None,
-            first_token_contract_hash: mock_butt_swbtc_lp().contract_hash,
-        };
-        handle_msg = HandleMsg::Receive {
-            sender: mock_user_address(),
-            from: mock_user_address(),
-            amount,
-            msg: Some(to_binary(&receive_msg).unwrap()),
-        };
-        // = * it raises an error
-        handle_result = handle(&mut deps, env.clone(), handle_msg);
-        // * it raises an error
         assert_eq!(
-            handle_result.unwrap_err(),
-            StdError::generic_err("Swap to SWBTC msg missing.")
+            handle_result_unwrapped.log,
+            vec![
+                log("recipient", mock_recipient_address()),
+                log("swbtc_amount", Uint128(1).to_string()),
+                log("butt_amount", Uint128(1).to_string()),
+                log("lp_amount", Uint128(MOCK_AMOUNT).to_string()),
+            ]
         );
-
-        // == when swap_to_swbtc_msg is present
-        // === when swap_to_swbtc_contract is missing
-        receive_msg = ReceiveMsg::InitSwapAndProvide {
-            swap_to_swbtc_contract: None,
-            swap_to_swbtc_msg,
-            first_token_contract_hash: mock_butt_swbtc_lp().contract_hash,
-        };
-        handle_msg = HandleMsg::Receive {
-            sender: mock_user_address(),
-            from: mock_user_address(),
-            amount,
-            msg: Some(to_binary(&receive_msg).unwrap()),
-        };
-        // === * it raises an error
-        handle_result = handle(&mut deps, env.clone(), handle_msg);
-        // * it raises an error
+        // == * it returns the zap's session id, recipient and LP amount in data
         assert_eq!(
-            handle_result.unwrap_err(),
-            StdError::generic_err("Swap to SWBTC contract missing.")
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::SendLpToUserThenDepositIntoFarmContract {
+                session_id: 1,
+                recipient: mock_recipient_address(),
+                lp_amount: Uint128(MOCK_AMOUNT),
+                share_amount: None,
+            }
         );
-    }
+        // == * it records the zap in the history of the user and the recipient
+        let zap: Zap = Zap {
+            id: 1,
+            user: mock_user_address(),
+            recipient: mock_recipient_address(),
+            butt_amount: Uint128(1),
+            swbtc_amount: Uint128(1),
+            lp_amount: Uint128(MOCK_AMOUNT),
+            block_time: env.block.time,
+        };
+        for address in [mock_user_address(), mock_recipient_address()].iter() {
+            let zaps_storage = ReadonlyPrefixedStorage::multilevel(
+                &[ZAPS_PREFIX, address.0.as_bytes()],
+                &deps.storage,
+            );
+            let zaps_store = AppendStore::<Zap, _>::attach(&zaps_storage)
+                .unwrap()
+                .unwrap();
+            assert_eq!(zaps_store.len(), 1);
+            assert_eq!(zaps_store.get_at(0).unwrap(), zap);
+        }
+        // == * it clears the session
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.current_user, None);
+        assert_eq!(session.recipient, None);
+        assert_eq!(session.callback, None);
 
-    #[test]
-    fn test_provide_liquidity_to_trade_pair() {
-        let (_init_result, mut deps) = init_helper();
-        let butt_amount: Uint128 = Uint128(5);
-        let mut config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+        // == when amounts are hidden by the admin or the zap
+        for (hide_amounts, zap_hide_amounts) in [(true, false), (false, true)].iter() {
+            config.hide_amounts = *hide_amounts;
+            TypedStoreMut::attach(&mut deps.storage)
+                .store(CONFIG_KEY, &config)
+                .unwrap();
+            session.current_user = Some(mock_user_address());
+            session.amounts_to_provide[0] = Some(Uint128(1));
+            session.amounts_to_provide[1] = Some(Uint128(1));
+            session.hide_amounts = *zap_hide_amounts;
+            TypedStoreMut::attach(&mut deps.storage)
+                .store(SESSION_KEY, &session)
+                .unwrap();
+            // == * it keeps the amounts out of the logs
+            handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+            assert_eq!(
+                handle_result.unwrap().log,
+                vec![log("recipient", mock_user_address())]
+            );
+            // == * it clears the zap's hide_amounts
+            session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+            assert!(!session.hide_amounts);
+        }
 
-        // = when called by BUTT
-        let env: Env = mock_env(mock_butt().address, &[]);
-        // == when called from non butt_swbtc_trade_pair
-        let handle_msg = HandleMsg::Receive {
-            sender: config.butt_swbtc_lp.address.clone(),
-            from: config.butt_swbtc_lp.address.clone(),
-            amount: butt_amount,
-            msg: None,
-        };
-        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
-        // == * it raises an unauthorized error
+        // == when depositing into the vault
+        set_vault_helper(&mut deps, Uint128(10), Uint128(20));
+        session.id = 2;
+        session.current_user = Some(mock_user_address());
+        session.amounts_to_provide[0] = Some(Uint128(1));
+        session.amounts_to_provide[1] = Some(Uint128(1));
+        session.vault = true;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg.clone()).unwrap();
+        // == * it deposits the LP into the farm itself
+        // == * it records the rewards the farm pays out on the deposit
+        // == * it mints shares to the recipient at the current share price
         assert_eq!(
-            handle_result.unwrap_err(),
-            StdError::Unauthorized { backtrace: None }
+            handle_result_unwrapped.messages,
+            vec![
+                snip20::send_msg(
+                    config.butt_swbtc_farm_pool.address.clone(),
+                    Uint128(MOCK_AMOUNT),
+                    Some(to_binary(&FarmReceiveMsg::DepositIncentivizedToken {}).unwrap()),
+                    None,
+                    BLOCK_SIZE,
+                    config.butt_swbtc_lp.contract_hash.clone(),
+                    config.butt_swbtc_lp.address.clone(),
+                )
+                .unwrap(),
+                record_vault_rewards_msg(&env, Uint128(0)).unwrap(),
+                snip20::mint_msg(
+                    mock_user_address(),
+                    Uint128(MOCK_AMOUNT / 2),
+                    None,
+                    BLOCK_SIZE,
+                    mock_share_token().contract_hash,
+                    mock_share_token().address,
+                )
+                .unwrap(),
+            ]
         );
-        // == when called from butt_swbtc_trade_pair
-        // === when swbtc_amount_to_provide is none
-        let handle_msg = HandleMsg::Receive {
-            sender: config.butt_swbtc_trade_pair.address.clone(),
-            from: config.butt_swbtc_trade_pair.address.clone(),
-            amount: butt_amount,
-            msg: None,
-        };
-        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
-        // === * it raises an error
         assert_eq!(
-            handle_result.unwrap_err(),
-            StdError::generic_err("swbtc_amount_to_provide is missing.")
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::SendLpToUserThenDepositIntoFarmContract {
+                session_id: 2,
+                recipient: mock_user_address(),
+                lp_amount: Uint128(MOCK_AMOUNT),
+                share_amount: Some(Uint128(MOCK_AMOUNT / 2)),
+            }
         );
-        // === when swbtc_amount_to_provide is zero
-        config.swbtc_amount_to_provide = Some(Uint128(0));
+        // == * it adds the LP and shares to the vault
+        let vault: Vault = TypedStore::attach(&deps.storage).load(VAULT_KEY).unwrap();
+        assert_eq!(vault.total_shares, Uint128(10 + MOCK_AMOUNT / 2));
+        assert_eq!(vault.total_lp, Uint128(20 + MOCK_AMOUNT));
+
+        // == when compounding the vault's rewards
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        session.id = 3;
+        session.current_user = Some(env.contract.address.clone());
+        session.recipient = Some(env.contract.address.clone());
+        session.amounts_to_provide[0] = Some(Uint128(1));
+        session.amounts_to_provide[1] = Some(Uint128(1));
+        session.vault = true;
+        session.compounding = true;
         TypedStoreMut::attach(&mut deps.storage)
-            .store(CONFIG_KEY, &config)
+            .store(SESSION_KEY, &session)
             .unwrap();
-        // === * it raises an error
-        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        // == * it deposits the LP into the farm without minting shares
         assert_eq!(
-            handle_result.unwrap_err(),
-            StdError::generic_err("SWBTC amount to provide must be greater than zero.")
+            handle(&mut deps, env.clone(), handle_msg.clone())
+                .unwrap()
+                .messages,
+            vec![
+                snip20::send_msg(
+                    config.butt_swbtc_farm_pool.address,
+                    Uint128(MOCK_AMOUNT),
+                    Some(to_binary(&FarmReceiveMsg::DepositIncentivizedToken {}).unwrap()),
+                    None,
+                    BLOCK_SIZE,
+                    config.butt_swbtc_lp.contract_hash,
+                    config.butt_swbtc_lp.address,
+                )
+                .unwrap(),
+                record_vault_rewards_msg(&env, Uint128(0)).unwrap(),
+            ]
+        );
+        // == * it raises the LP of each share
+        let vault: Vault = TypedStore::attach(&deps.storage).load(VAULT_KEY).unwrap();
+        assert_eq!(vault.total_shares, Uint128(10 + MOCK_AMOUNT / 2));
+        assert_eq!(vault.total_lp, Uint128(20 + 2 * MOCK_AMOUNT));
+        // == * it doesn't record the zap in the history of the contract
+        let zaps_storage = ReadonlyPrefixedStorage::multilevel(
+            &[ZAPS_PREFIX, env.contract.address.0.as_bytes()],
+            &deps.storage,
+        );
+        assert!(AppendStore::<Zap, _>::attach(&zaps_storage).is_none());
+
+        // == when the contract holds LP escrowed for an exit order
+        place_exit_order_helper(
+            &mut deps,
+            mock_butt_swbtc_lp(),
+            Uint128(10),
+            mock_exit_order_request(Decimal::percent(50), PriceTrigger::Below),
         );
-        // === when swbtc_amount_to_provide is zero
-        config.swbtc_amount_to_provide = Some(Uint128(10));
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        session.id = 4;
+        session.current_user = Some(mock_user_address());
+        session.amounts_to_provide[0] = Some(Uint128(1));
+        session.amounts_to_provide[1] = Some(Uint128(1));
+        session.lp_balance = Some(Uint128(10));
         TypedStoreMut::attach(&mut deps.storage)
-            .store(CONFIG_KEY, &config)
+            .store(SESSION_KEY, &session)
             .unwrap();
-
-        // === * it provides the balance of BUTT and SWBTC of contract to trade pair contract
-        let handle_result = handle(&mut deps, env, handle_msg.clone());
-        let handle_result_unwrapped = handle_result.unwrap();
-        let provide_liquidity_msg = SecretSwapHandleMsg::ProvideLiquidity {
-            assets: [
-                Asset {
-                    amount: Uint128(10),
-                    info: AssetInfo::Token {
-                        contract_addr: config.swbtc.address,
-                        token_code_hash: config.swbtc.contract_hash,
-                        viewing_key: "SecretSwap".to_string(),
-                    },
-                },
-                Asset {
-                    amount: butt_amount,
-                    info: AssetInfo::Token {
-                        contract_addr: config.butt.address,
-                        token_code_hash: config.butt.contract_hash.clone(),
-                        viewing_key: "SecretSwap".to_string(),
-                    },
-                },
-            ],
-            slippage_tolerance: None,
-        };
-        let cosmos_msg = provide_liquidity_msg
-            .to_cosmos_msg(
-                mock_butt_swbtc_trade_pair().contract_hash,
-                mock_butt_swbtc_trade_pair().address,
+        // == * it only sends the LP the zap minted
+        // == * it leaves the escrow in the contract
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone())
+                .unwrap()
+                .messages[0],
+            snip20::transfer_msg(
+                mock_user_address(),
+                Uint128(MOCK_AMOUNT - 10),
                 None,
+                BLOCK_SIZE,
+                mock_butt_swbtc_lp().contract_hash,
+                mock_butt_swbtc_lp().address,
             )
-            .unwrap();
-        assert_eq!(handle_result_unwrapped.messages, vec![cosmos_msg]);
-    }
-
-    #[test]
-    fn test_receive() {
-        let (_init_result, mut deps) = init_helper();
-
-        // when sent and not BUTT or SWBTC and without a ReceiveMsg
-        let env = mock_env(mock_butt_swbtc_lp().address, &[]);
-        let handle_msg = HandleMsg::Receive {
-            sender: mock_user_address(),
-            from: mock_user_address(),
-            amount: Uint128(5),
-            msg: None,
-        };
-        let handle_result = handle(&mut deps, env, handle_msg);
-        // == * it raises an error
+            .unwrap()
+        );
+        assert_eq!(exit_order(&deps.storage, 0).unwrap().amount, Uint128(10));
         assert_eq!(
-            handle_result.unwrap_err(),
-            StdError::generic_err("Receive message combination is wrong.")
+            exit_order(&deps.storage, 0).unwrap().status,
+            OrderStatus::Pending
         );
-    }
+        // == * it clears the LP balance of the session
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.lp_balance, None);
 
-    #[test]
-    fn test_register_tokens() {
-        let (_init_result, mut deps) = init_helper();
-        let env = mock_env(mock_user_address(), &[]);
+        // == when zapping into a pool whose deposit target is the wallet
+        add_pool_helper(&mut deps, mock_sefi_swbtc_pool());
+        deps.querier.balance = Uint128(5);
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        session.id = 5;
+        session.pool_id = 1;
+        session.current_user = Some(mock_user_address());
+        session.amounts_to_provide[0] = Some(Uint128(1));
+        session.amounts_to_provide[1] = Some(Uint128(1));
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // == * it sends the pool's LP to the recipient
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone())
+                .unwrap()
+                .messages,
+            vec![snip20::transfer_msg(
+                mock_user_address(),
+                Uint128(5),
+                None,
+                BLOCK_SIZE,
+                mock_sefi_swbtc_lp().contract_hash,
+                mock_sefi_swbtc_lp().address,
+            )
+            .unwrap()]
+        );
+        // == * it clears the pool of the session
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.pool_id, 0);
 
-        // When tokens are in the parameter
-        let handle_msg = HandleMsg::RegisterTokens {
-            tokens: vec![mock_butt(), mock_swbtc()],
-        };
-        let handle_result = handle(&mut deps, env.clone(), handle_msg);
-        let handle_result_unwrapped = handle_result.unwrap();
-        // * it sends a message to register receive for the token
+        // == when the zap sets the yield optimizer as its deposit target
+        session.id = 6;
+        session.pool_id = 1;
+        session.current_user = Some(mock_user_address());
+        session.amounts_to_provide[0] = Some(Uint128(1));
+        session.amounts_to_provide[1] = Some(Uint128(1));
+        session.deposit_target = Some(DepositTarget::YieldOptimizer(mock_yield_optimizer()));
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // == * it sends the LP to the recipient then deposits it into the yield optimizer
         assert_eq!(
-            handle_result_unwrapped.messages,
+            handle(&mut deps, env, handle_msg).unwrap().messages,
             vec![
-                snip20::register_receive_msg(
-                    env.contract_code_hash.clone(),
+                snip20::transfer_msg(
+                    mock_user_address(),
+                    Uint128(5),
                     None,
                     BLOCK_SIZE,
-                    mock_butt().contract_hash,
-                    mock_butt().address,
+                    mock_sefi_swbtc_lp().contract_hash,
+                    mock_sefi_swbtc_lp().address,
                 )
                 .unwrap(),
-                snip20::register_receive_msg(
-                    env.contract_code_hash,
+                snip20::send_from_msg(
+                    mock_user_address(),
+                    mock_yield_optimizer().address,
+                    Uint128(5),
+                    Some(
+                        to_binary(&YieldOptimizerReceiveMsg::DepositIncentivizedToken {}).unwrap()
+                    ),
                     None,
                     BLOCK_SIZE,
-                    mock_swbtc().contract_hash,
-                    mock_swbtc().address,
+                    mock_sefi_swbtc_lp().contract_hash,
+                    mock_sefi_swbtc_lp().address,
                 )
                 .unwrap(),
             ]
         );
+        // == * it clears the deposit target of the session
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.deposit_target, None);
     }
 
     #[test]
-    fn test_rescue_tokens() {
+    fn test_set_deposit_target() {
         let (_init_result, mut deps) = init_helper();
-        let denom: String = "uscrt".to_string();
-        let mut handle_msg = HandleMsg::RescueTokens {
-            amount: Uint128(MOCK_AMOUNT),
-            denom: Some(denom.clone()),
-            token: Some(mock_butt()),
+        let handle_msg = HandleMsg::SetDepositTarget {
+            pool_id: 0,
+            deposit_target: DepositTarget::YieldOptimizer(mock_yield_optimizer()),
         };
-        // = when called by a non-admin
-        // = * it raises an Unauthorized error
-        let mut env: Env = mock_env(mock_user_address(), &[]);
-        let handle_result = handle(&mut deps, env, handle_msg.clone());
+
+        // when called by a non-admin
+        // * it raises an unauthorized error
         assert_eq!(
-            handle_result.unwrap_err(),
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                handle_msg.clone()
+            )
+            .unwrap_err(),
             StdError::Unauthorized { backtrace: None }
         );
 
-        // = when called by the admin
-        env = mock_env(MOCK_ADMIN, &[]);
-        // == when only denom is specified
-        handle_msg = HandleMsg::RescueTokens {
-            amount: Uint128(MOCK_AMOUNT),
-            denom: Some(denom.clone()),
-            token: None,
+        // when called by the admin
+        // = when the pool doesn't exist
+        // = * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(MOCK_ADMIN, &[]),
+                HandleMsg::SetDepositTarget {
+                    pool_id: 1,
+                    deposit_target: DepositTarget::Wallet,
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err("Pool 1 not found.")
+        );
+        // = when the pool exists
+        let handle_result_unwrapped =
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg).unwrap();
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::SetDepositTarget { status: Success }
+        );
+        // = * it updates the deposit target of the pool
+        let mut pool: Pool = mock_pool();
+        pool.deposit_target = DepositTarget::YieldOptimizer(mock_yield_optimizer());
+        assert_eq!(pools(&deps.storage).unwrap(), vec![pool]);
+    }
+
+    #[test]
+    fn test_set_successor() {
+        let (_init_result, mut deps) = init_helper();
+        register_tokens_helper(&mut deps, vec![mock_swbtc()]);
+        place_order_helper(
+            &mut deps,
+            mock_order_request(Decimal::percent(50), PriceTrigger::Above),
+        );
+        let successor: SecretContract = SecretContract {
+            address: HumanAddr::from("new-zapper"),
+            contract_hash: "new-zapper-hash".to_string(),
         };
-        // === * it sends the amount specified of the coin of the denom to the admin
-        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
-        let handle_result_unwrapped = handle_result.unwrap();
+        let handle_msg = HandleMsg::SetSuccessor {
+            contract: successor.clone(),
+        };
+
+        // when called by a non-admin
+        // * it raises an unauthorized error
         assert_eq!(
-            handle_result_unwrapped.messages,
-            vec![CosmosMsg::Bank(BankMsg::Send {
-                from_address: env.contract.address,
-                to_address: HumanAddr(MOCK_ADMIN.to_string()),
-                amount: vec![Coin {
-                    denom: denom,
-                    amount: Uint128(MOCK_AMOUNT)
-                }],
-            })]
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                handle_msg.clone()
+            )
+            .unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
         );
 
-        // == when only token is specified
-        handle_msg = HandleMsg::RescueTokens {
-            amount: Uint128(MOCK_AMOUNT),
-            denom: None,
-            token: Some(mock_butt()),
+        // when called by the admin
+        let handle_result_unwrapped =
+            handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg).unwrap();
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::SetSuccessor { status: Success }
+        );
+        // * it reports the successor in the config
+        let config_from_query: ConfigPublic =
+            from_binary(&query(&deps, QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(config_from_query.successor, Some(successor));
+        // * it rejects new zaps with an error naming the successor
+        let receive_msg = ReceiveMsg::InitSwapAndProvide(ZapRequest {
+            swap_to_swbtc_contract: None,
+            swap_to_swbtc_msg: None,
+            first_token_contract_hash: mock_swbtc().contract_hash,
+            belief_price: None,
+            max_spread: None,
+            intermediate_token: None,
+            max_price_impact: None,
+            deadline: None,
+            recipient: None,
+            callback: None,
+            hide_amounts: None,
+            vault: None,
+            pool_id: None,
+            deposit_target: None,
+        });
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_swbtc().address, &[]),
+                HandleMsg::Receive {
+                    sender: mock_user_address(),
+                    from: mock_user_address(),
+                    amount: Uint128(2),
+                    msg: Some(to_binary(&receive_msg).unwrap()),
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err(
+                "Contract is deprecated, zap with its successor new-zapper instead."
+            )
+        );
+        // * it still zaps the orders placed before
+        let mut order: Order = order(&deps.storage, 0).unwrap();
+        order.status = OrderStatus::Executed;
+        store_order(&mut deps.storage, &order).unwrap();
+        let env = mock_env(MOCK_ADMIN, &[]);
+        handle(
+            &mut deps,
+            mock_env(env.contract.address, &[]),
+            HandleMsg::ExecuteOrder { order_id: 0 },
+        )
+        .unwrap();
+        let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.current_user, Some(mock_user_address()));
+    }
+
+    #[test]
+    fn test_set_vault() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::SetVault {
+            share_token: mock_share_token(),
+            harvest_bounty: Decimal::percent(1),
+            harvest_interval: 3_600,
         };
-        // == * it sends the amount specified of the token to the admin
-        let handle_result = handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg.clone());
-        let handle_result_unwrapped = handle_result.unwrap();
+        let env = mock_env(MOCK_ADMIN, &[]);
+
+        // when called by a non-admin
+        // * it raises an unauthorized error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                handle_msg.clone()
+            )
+            .unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // when called by the admin
+        // = when the harvest bounty is 100% or more
+        // = * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                env.clone(),
+                HandleMsg::SetVault {
+                    share_token: mock_share_token(),
+                    harvest_bounty: Decimal::one(),
+                    harvest_interval: 3_600,
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err("Harvest bounty must be less than 100%.")
+        );
+        // = when the harvest bounty is valid
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg.clone()).unwrap();
+        // = * it stores an empty vault
+        let vault: Vault = TypedStore::attach(&deps.storage).load(VAULT_KEY).unwrap();
+        assert_eq!(
+            vault,
+            Vault {
+                share_token: mock_share_token(),
+                total_shares: Uint128(0),
+                total_lp: Uint128(0),
+                harvest_bounty: Decimal::percent(1),
+                harvest_interval: 3_600,
+                last_harvest_time: 0,
+                pending_rewards: Uint128(0),
+            }
+        );
+        // = * it registers to receive the share token
         assert_eq!(
             handle_result_unwrapped.messages,
-            vec![snip20::transfer_msg(
-                HumanAddr::from(MOCK_ADMIN),
-                Uint128(MOCK_AMOUNT),
+            vec![snip20::register_receive_msg(
+                env.contract_code_hash.clone(),
                 None,
                 BLOCK_SIZE,
-                mock_butt().contract_hash,
-                mock_butt().address,
+                mock_share_token().contract_hash,
+                mock_share_token().address,
             )
             .unwrap()]
         );
+
+        // when the vault is already set
+        // * it raises an error
+        assert_eq!(
+            handle(&mut deps, env, handle_msg).unwrap_err(),
+            StdError::generic_err("Vault is already set.")
+        );
     }
 
     #[test]
-    fn test_send_lp_to_user_then_deposit_into_farm_contract() {
+    fn test_set_viewing_key() {
         let (_init_result, mut deps) = init_helper();
-        let mut config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
-        let handle_msg = HandleMsg::SendLpToUserThenDepositIntoFarmContract {};
 
-        // when called by non-contract
-        let mut env = mock_env(MOCK_ADMIN, &[]);
-        // = * it raises an unauthorized error
-        let mut handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        // * it stores the hash of the key for the sender
+        handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            HandleMsg::SetViewingKey {
+                key: "spicy".to_string(),
+            },
+        )
+        .unwrap();
+        let viewing_keys_storage = ReadonlyPrefixedStorage::new(VIEWING_KEYS_PREFIX, &deps.storage);
         assert_eq!(
-            handle_result.unwrap_err(),
-            StdError::Unauthorized { backtrace: None }
+            viewing_keys_storage.get(mock_user_address().0.as_bytes()),
+            Some(sha_256(b"spicy").to_vec())
         );
+    }
 
-        // when called by contract
-        env = mock_env(env.contract.address, &[]);
-        // = when config current_user is missing
+    #[test]
+    fn test_subscribe() {
+        let (_init_result, mut deps) = init_helper();
+        let mut subscription_request: SubscriptionRequest = mock_subscription_request(3);
+        let env = mock_env(mock_swbtc().address, &[]);
+        let receive =
+            |subscription_request: &SubscriptionRequest, amount: u128| HandleMsg::Receive {
+                sender: mock_user_address(),
+                from: mock_user_address(),
+                amount: Uint128(amount),
+                msg: Some(to_binary(&ReceiveMsg::Subscribe(subscription_request.clone())).unwrap()),
+            };
+
+        // = when the token isn't registered
         // = * it raises an error
-        handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
         assert_eq!(
-            handle_result.unwrap_err(),
-            StdError::generic_err("Contract wasn't called properly.")
+            handle(&mut deps, env.clone(), receive(&subscription_request, 6)).unwrap_err(),
+            StdError::generic_err(format!("Token {} is not registered.", mock_swbtc().address))
         );
 
-        // = when config current_user is present
-        config.current_user = Some(mock_user_address());
-        config.butt_amount_to_provide = Some(Uint128(1));
-        config.swbtc_amount_to_provide = Some(Uint128(1));
-        TypedStoreMut::attach(&mut deps.storage)
-            .store(CONFIG_KEY, &config)
-            .unwrap();
-        // == when contract's balance of butt-swbtc-lp is zero
+        // = when the token is registered
+        register_tokens_helper(&mut deps, vec![mock_swbtc()]);
+        // == when the pool of the zap doesn't exist
         // == * it raises an error
-        // handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
-        // assert_eq!(
-        //     handle_result.unwrap_err(),
-        //     StdError::generic_err("Result BUTT-SWBTC LP must be greater than zero.",)
-        // );
-        // == when contract's balance of butt-swbtc-lp is greater than zero
-        // == * it sends the balance of the token to the user
-        handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
-        let handle_result_unwrapped = handle_result.unwrap();
-        // * it sends a message to register receive for the token
+        subscription_request.zap_request.pool_id = Some(1);
         assert_eq!(
-            handle_result_unwrapped.messages,
-            vec![
-                snip20::transfer_msg(
-                    mock_user_address(),
-                    Uint128(MOCK_AMOUNT),
-                    None,
-                    BLOCK_SIZE,
-                    config.butt_swbtc_lp.contract_hash.clone(),
-                    config.butt_swbtc_lp.address.clone(),
-                )
-                .unwrap(),
-                snip20::send_from_msg(
-                    mock_user_address(),
-                    config.butt_swbtc_farm_pool.address,
-                    Uint128(MOCK_AMOUNT),
-                    Some(Binary::from(
-                        r#"{ "deposit_incentivized_token": {} }"#.as_bytes(),
-                    )),
-                    None,
-                    BLOCK_SIZE,
-                    config.butt_swbtc_lp.contract_hash,
-                    config.butt_swbtc_lp.address,
-                )
-                .unwrap()
-            ]
+            handle(&mut deps, env.clone(), receive(&subscription_request, 6)).unwrap_err(),
+            StdError::generic_err("Pool 1 not found.")
         );
+        subscription_request.zap_request.pool_id = None;
+        // == when the zap request is invalid
+        // == * it raises an error
+        subscription_request.zap_request.intermediate_token = Some(mock_user_address());
         assert_eq!(
-            handle_result_unwrapped.log,
-            vec![
-                log("swbtc_amount", Uint128(1).to_string()),
-                log("butt_amount", Uint128(1).to_string()),
-                log("lp_amount", Uint128(MOCK_AMOUNT).to_string()),
-            ]
+            handle(&mut deps, env.clone(), receive(&subscription_request, 6)).unwrap_err(),
+            StdError::generic_err("Intermediate token must be a token of pool 0.")
+        );
+        subscription_request.zap_request.intermediate_token = None;
+        // == when the schedule has a zero
+        // == * it raises an error
+        subscription_request.period = 0;
+        assert_eq!(
+            handle(&mut deps, env.clone(), receive(&subscription_request, 6)).unwrap_err(),
+            StdError::generic_err(
+                "Amount per period, periods and period must be greater than zero."
+            )
+        );
+        subscription_request.period = 60;
+        // == when the amount isn't the budget of the schedule
+        // == * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), receive(&subscription_request, 5)).unwrap_err(),
+            StdError::generic_err("Amount must be the amount per period times the periods.")
+        );
+        // == when the zap has a deadline
+        // == * it raises an error
+        subscription_request.zap_request.deadline = Some(Deadline::Height(12_355));
+        assert_eq!(
+            handle(&mut deps, env.clone(), receive(&subscription_request, 6)).unwrap_err(),
+            StdError::generic_err("Subscriptions can't have a zap deadline.")
+        );
+        subscription_request.zap_request.deadline = None;
+        // == when the subscription is valid
+        let handle_result_unwrapped =
+            handle(&mut deps, env.clone(), receive(&subscription_request, 6)).unwrap();
+        // == * it escrows the budget in an active subscription, due right away
+        assert_eq!(handle_result_unwrapped.messages, vec![]);
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::Subscribe { subscription_id: 0 }
+        );
+        assert_eq!(
+            subscription(&deps.storage, 0).unwrap(),
+            Subscription {
+                id: 0,
+                user: mock_user_address(),
+                token: mock_swbtc(),
+                amount_per_period: Uint128(2),
+                period: 60,
+                balance: Uint128(6),
+                next_installment_time: env.block.time,
+                zap_request: subscription_request.zap_request,
+                status: SubscriptionStatus::Active,
+            }
         );
     }
 
     #[test]
-    fn test_swap_half_of_swbtc_to_butt() {
+    fn test_swap_half_of_intermediate_token() {
         let (_init_result, mut deps) = init_helper();
         let swbtc_amount: Uint128 = Uint128(5);
 
         // = when called by SWBTC
         let env: Env = mock_env(mock_swbtc().address, &[]);
         // == when swap_to_swbtc_contract_address is missing
-        let mut config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+        let config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+        let mut session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
         let handle_msg = HandleMsg::Receive {
             sender: config.swbtc.address.clone(),
             from: config.swbtc.address.clone(),
@@ -1122,10 +7742,11 @@ mod tests {
             StdError::generic_err("Swap to SWBTC contract missing.")
         );
         // == when swap_to_swbtc_contract_address is present
-        config.swap_to_swbtc_contract_address = Some(env.contract.address.clone());
+        session.swap_to_swbtc_contract_address = Some(env.contract.address.clone());
+        session.max_spread = Some(Decimal::percent(1));
         // === when called from an address that is not the swap_to_swbtc_contract_address
         TypedStoreMut::attach(&mut deps.storage)
-            .store(CONFIG_KEY, &config)
+            .store(SESSION_KEY, &session)
             .unwrap();
         // === * it raises an error
         let mut handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
@@ -1140,7 +7761,22 @@ mod tests {
             amount: swbtc_amount,
             msg: None,
         };
-        // === * it sends half the balance of swbtc to swap
+        // === when the deadline has passed
+        session.deadline = Some(Deadline::Height(env.block.height - 1));
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // === * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Zap deadline has passed.")
+        );
+        // === when the deadline has not passed
+        session.deadline = Some(Deadline::Height(env.block.height));
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // === * it sends half the balance of swbtc to swap with the stored swap parameters
         handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
         let handle_result_unwrapped = handle_result.unwrap();
         let amount_to_swap = Uint128(swbtc_amount.u128() / 2);
@@ -1149,7 +7785,15 @@ mod tests {
             vec![secret_toolkit::snip20::send_msg(
                 config.butt_swbtc_trade_pair.address,
                 Uint128(swbtc_amount.u128() / 2),
-                Some(Binary::from(r#"{ "swap": {} }"#.as_bytes())),
+                Some(
+                    to_binary(&SecretSwapReceiveMsg::Swap {
+                        expected_return: None,
+                        belief_price: None,
+                        max_spread: Some(Decimal::percent(1)),
+                        to: None,
+                    })
+                    .unwrap()
+                ),
                 None,
                 BLOCK_SIZE,
                 config.swbtc.contract_hash,
@@ -1157,11 +7801,379 @@ mod tests {
             )
             .unwrap()]
         );
-        // === * it stores the other half in config as swbtc_amount_to_provide
-        config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+        // === * it stores the other half in the session as the SWBTC amount to provide
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
         assert_eq!(
-            config.swbtc_amount_to_provide,
+            session.amounts_to_provide[1],
             Some((swbtc_amount - amount_to_swap).unwrap())
         );
+        // === * it returns the swapped and stored amounts in data
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::SwapHalfOfIntermediateToken {
+                amount_to_swap,
+                amount_to_provide: session.amounts_to_provide[1].unwrap(),
+            }
+        );
+
+        // = when intermediate token is BUTT
+        session.intermediate_token = Some(mock_butt().address);
+        session.max_spread = None;
+        session.max_price_impact = None;
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // == when called by BUTT from the swap_to_swbtc_contract_address
+        let env: Env = mock_env(mock_butt().address, &[]);
+        let butt_amount: Uint128 = Uint128(7);
+        let handle_msg = HandleMsg::Receive {
+            sender: env.contract.address.clone(),
+            from: env.contract.address.clone(),
+            amount: butt_amount,
+            msg: None,
+        };
+        // == * it sends half the butt to swap
+        handle_result = handle(&mut deps, env, handle_msg);
+        assert_eq!(
+            handle_result.unwrap().messages,
+            vec![secret_toolkit::snip20::send_msg(
+                mock_butt_swbtc_trade_pair().address,
+                Uint128(3),
+                Some(
+                    to_binary(&SecretSwapReceiveMsg::Swap {
+                        expected_return: None,
+                        belief_price: None,
+                        max_spread: None,
+                        to: None,
+                    })
+                    .unwrap()
+                ),
+                None,
+                BLOCK_SIZE,
+                mock_butt().contract_hash,
+                mock_butt().address,
+            )
+            .unwrap()]
+        );
+        // == * it stores the other half in the session as the BUTT amount to provide
+        session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.amounts_to_provide[0], Some(Uint128(4)));
+
+        // = when the price impact of the swap exceeds the admin's max_price_impact
+        deps.querier.pool_reserve = Uint128(36);
+        let env: Env = mock_env(mock_butt().address, &[]);
+        let handle_msg = HandleMsg::Receive {
+            sender: env.contract.address.clone(),
+            from: env.contract.address.clone(),
+            amount: Uint128(8),
+            msg: None,
+        };
+        // = * it raises an error
+        handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err("Price impact of 0.1 exceeds maximum of 0.05.")
+        );
+        // = when the price impact of the swap exceeds the zap's max_price_impact
+        deps.querier.pool_reserve = Uint128(96);
+        session.max_price_impact = Some(Decimal::percent(3));
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // = * it raises an error
+        handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err("Price impact of 0.04 exceeds maximum of 0.03.")
+        );
+        // = when the price impact of the swap is within both maximums
+        session.max_price_impact = Some(Decimal::percent(4));
+        TypedStoreMut::attach(&mut deps.storage)
+            .store(SESSION_KEY, &session)
+            .unwrap();
+        // = * it sends half the amount to swap
+        handle_result = handle(&mut deps, env, handle_msg);
+        assert_eq!(handle_result.unwrap().messages.len(), 1);
+    }
+
+    #[test]
+    fn test_top_up_subscription() {
+        let (_init_result, mut deps) = init_helper();
+        let receive = |from: HumanAddr| HandleMsg::Receive {
+            sender: from.clone(),
+            from,
+            amount: Uint128(3),
+            msg: Some(to_binary(&ReceiveMsg::TopUpSubscription { subscription_id: 0 }).unwrap()),
+        };
+        let env = mock_env(mock_swbtc().address, &[]);
+
+        // = when the subscription doesn't exist
+        // = * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), receive(mock_user_address())).unwrap_err(),
+            StdError::generic_err("Subscription 0 not found.")
+        );
+
+        // = when the subscription exists
+        register_tokens_helper(&mut deps, vec![mock_swbtc()]);
+        subscribe_helper(&mut deps, mock_subscription_request(1), Uint128(2));
+        // == when sent by someone other than the user
+        // == * it raises an unauthorized error
+        assert_eq!(
+            handle(&mut deps, env.clone(), receive(mock_recipient_address())).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+        // == when sent a token other than the subscription's
+        // == * it raises an error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_butt().address, &[]),
+                receive(mock_user_address())
+            )
+            .unwrap_err(),
+            StdError::generic_err(format!(
+                "Subscription 0 is funded with {}.",
+                mock_swbtc().address
+            ))
+        );
+        // == when sent the subscription's token by the user
+        let handle_result_unwrapped =
+            handle(&mut deps, env.clone(), receive(mock_user_address())).unwrap();
+        // == * it adds the tokens to the balance
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::TopUpSubscription {
+                balance: Uint128(5)
+            }
+        );
+        assert_eq!(subscription(&deps.storage, 0).unwrap().balance, Uint128(5));
+        // == when the subscription is no longer active
+        // == * it raises an error
+        handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            HandleMsg::CancelSubscription { subscription_id: 0 },
+        )
+        .unwrap();
+        assert_eq!(
+            handle(&mut deps, env, receive(mock_user_address())).unwrap_err(),
+            StdError::generic_err("Subscription 0 is not active.")
+        );
+    }
+
+    #[test]
+    fn test_update_config() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::UpdateConfig {
+            max_price_impact: Some(Decimal::percent(10)),
+            hide_amounts: Some(true),
+        };
+
+        // = when called by a non-admin
+        // = * it raises an Unauthorized error
+        let handle_result = handle(
+            &mut deps,
+            mock_env(mock_user_address(), &[]),
+            handle_msg.clone(),
+        );
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when called by the admin
+        // = * it updates the max_price_impact and hide_amounts
+        handle(&mut deps, mock_env(MOCK_ADMIN, &[]), handle_msg).unwrap();
+        let mut config: Config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+        assert_eq!(config.max_price_impact, Decimal::percent(10));
+        assert!(config.hide_amounts);
+        // = * it leaves settings that are not given unchanged
+        handle(
+            &mut deps,
+            mock_env(MOCK_ADMIN, &[]),
+            HandleMsg::UpdateConfig {
+                max_price_impact: None,
+                hide_amounts: None,
+            },
+        )
+        .unwrap();
+        config = TypedStore::attach(&deps.storage).load(CONFIG_KEY).unwrap();
+        assert_eq!(config.max_price_impact, Decimal::percent(10));
+        assert!(config.hide_amounts);
+    }
+
+    #[test]
+    fn test_update_vault() {
+        let (_init_result, mut deps) = init_helper();
+        let handle_msg = HandleMsg::UpdateVault {
+            harvest_bounty: Some(Decimal::percent(2)),
+            harvest_interval: None,
+        };
+        let env = mock_env(MOCK_ADMIN, &[]);
+
+        // when called by a non-admin
+        // * it raises an unauthorized error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_user_address(), &[]),
+                handle_msg.clone()
+            )
+            .unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // when called by the admin
+        // = when the vault isn't set
+        // = * it raises an error
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::generic_err("Vault is not set.")
+        );
+        // = when the harvest bounty is 100% or more
+        // = * it raises an error
+        set_vault_helper(&mut deps, Uint128(4), Uint128(5));
+        assert_eq!(
+            handle(
+                &mut deps,
+                env.clone(),
+                HandleMsg::UpdateVault {
+                    harvest_bounty: Some(Decimal::percent(100)),
+                    harvest_interval: None,
+                }
+            )
+            .unwrap_err(),
+            StdError::generic_err("Harvest bounty must be less than 100%.")
+        );
+        // = when the harvest bounty is valid
+        let handle_result_unwrapped = handle(&mut deps, env, handle_msg).unwrap();
+        // = * it updates the settings that are provided
+        let vault: Vault = TypedStore::attach(&deps.storage).load(VAULT_KEY).unwrap();
+        assert_eq!(vault.harvest_bounty, Decimal::percent(2));
+        assert_eq!(vault.harvest_interval, 3_600);
+        assert_eq!(vault.total_shares, Uint128(4));
+        // = * it returns a success status in data
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::UpdateVault { status: Success }
+        );
+    }
+
+    #[test]
+    fn test_withdraw_from_vault() {
+        let (_init_result, mut deps) = init_helper();
+        set_vault_helper(&mut deps, Uint128(10), Uint128(20));
+        let handle_msg = HandleMsg::Receive {
+            sender: mock_user_address(),
+            from: mock_user_address(),
+            amount: Uint128(5),
+            msg: Some(to_binary(&ReceiveMsg::WithdrawFromVault {}).unwrap()),
+        };
+
+        // when sent a token other than the share token
+        // * it raises an unauthorized error
+        assert_eq!(
+            handle(
+                &mut deps,
+                mock_env(mock_butt().address, &[]),
+                handle_msg.clone()
+            )
+            .unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // when sent the share token
+        let handle_result_unwrapped = handle(
+            &mut deps,
+            mock_env(mock_share_token().address, &[]),
+            handle_msg,
+        )
+        .unwrap();
+        // * it burns the shares, withdraws their LP from the farm and sends it to the user
+        assert_eq!(
+            handle_result_unwrapped.messages,
+            vec![
+                snip20::burn_msg(
+                    Uint128(5),
+                    None,
+                    BLOCK_SIZE,
+                    mock_share_token().contract_hash,
+                    mock_share_token().address,
+                )
+                .unwrap(),
+                FarmHandleMsg::Withdraw {
+                    incentivized_token_amount: Uint128(10),
+                }
+                .to_cosmos_msg(
+                    mock_butt_swbtc_farm_pool().contract_hash,
+                    mock_butt_swbtc_farm_pool().address,
+                    None,
+                )
+                .unwrap(),
+                // * it records the rewards the farm pays out on the withdrawal
+                record_vault_rewards_msg(&mock_env(MOCK_ADMIN, &[]), Uint128(0)).unwrap(),
+                snip20::transfer_msg(
+                    mock_user_address(),
+                    Uint128(10),
+                    None,
+                    BLOCK_SIZE,
+                    mock_butt_swbtc_lp().contract_hash,
+                    mock_butt_swbtc_lp().address,
+                )
+                .unwrap(),
+            ]
+        );
+        assert_eq!(
+            from_binary::<HandleAnswer>(&handle_result_unwrapped.data.unwrap()).unwrap(),
+            HandleAnswer::WithdrawFromVault {
+                lp_amount: Uint128(10)
+            }
+        );
+        // * it removes the shares and LP from the vault
+        let vault: Vault = TypedStore::attach(&deps.storage).load(VAULT_KEY).unwrap();
+        assert_eq!(vault.total_shares, Uint128(5));
+        assert_eq!(vault.total_lp, Uint128(10));
+    }
+
+    #[test]
+    fn test_zap_installment() {
+        let (_init_result, mut deps) = init_helper();
+        register_tokens_helper(&mut deps, vec![mock_swbtc()]);
+        subscribe_helper(&mut deps, mock_subscription_request(3), Uint128(6));
+        let env = mock_env(MOCK_ADMIN, &[]);
+        let handle_msg = HandleMsg::ZapInstallment {
+            subscription_id: 0,
+            amount: Uint128(2),
+        };
+
+        // = when called by someone other than the contract
+        // = * it raises an unauthorized error
+        assert_eq!(
+            handle(&mut deps, env.clone(), handle_msg.clone()).unwrap_err(),
+            StdError::Unauthorized { backtrace: None }
+        );
+
+        // = when called by the contract
+        let env = mock_env(env.contract.address, &[]);
+        let handle_result_unwrapped = handle(&mut deps, env.clone(), handle_msg).unwrap();
+        // = * it zaps the installment
+        assert_eq!(
+            handle_result_unwrapped.messages[0],
+            snip20::send_msg(
+                env.contract.address.clone(),
+                Uint128(2),
+                None,
+                None,
+                BLOCK_SIZE,
+                mock_swbtc().contract_hash,
+                mock_swbtc().address,
+            )
+            .unwrap()
+        );
+        // = * it zaps on behalf of the subscription's user
+        let session: Session = TypedStore::attach(&deps.storage).load(SESSION_KEY).unwrap();
+        assert_eq!(session.current_user, Some(mock_user_address()));
+        assert_eq!(session.recipient, Some(mock_user_address()));
     }
 }
@@ -0,0 +1,556 @@
+use crate::constants::BLOCK_SIZE;
+use crate::math::minimum_return_amount;
+use crate::msg::{
+    Asset, AssetInfo, PoolResponse, SecretSwapHandleMsg, SecretSwapQueryMsg, SecretSwapReceiveMsg,
+    SiennaSwapHandleMsg, SiennaSwapQueryMsg, SiennaSwapQueryResponse, SiennaSwapReceiveMsg,
+    TokenPair, TokenPairAmount, TokenType,
+};
+use crate::state::{Dex, SecretContract};
+use cosmwasm_std::{
+    to_binary, CosmosMsg, Decimal, HumanAddr, Querier, StdError, StdResult, Uint128,
+};
+use secret_toolkit::snip20;
+use secret_toolkit::utils::{HandleCallback, Query};
+
+// Builds the messages for a DEX's trade pair interface, so pools can live on different DEXes
+pub trait DexAdapter<Q: Querier> {
+    // Commission the trade pair keeps from each swap
+    fn commission_rate(&self) -> Decimal;
+
+    fn provide_liquidity_msg(
+        &self,
+        trade_pair: &SecretContract,
+        assets: [(&SecretContract, Uint128); 2],
+    ) -> StdResult<CosmosMsg>;
+
+    fn query_reserves(&self, querier: &Q, trade_pair: &SecretContract) -> StdResult<PairReserves>;
+
+    // The trade pair returns the ask token to the recipient, or to the sender without one
+    fn swap_msg(
+        &self,
+        trade_pair: &SecretContract,
+        offer_token: &SecretContract,
+        amount: Uint128,
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        recipient: Option<&HumanAddr>,
+    ) -> StdResult<CosmosMsg>;
+
+    // Sends the LP to the trade pair, which returns the tokens to the recipient
+    fn withdraw_liquidity_msg(
+        &self,
+        trade_pair: &SecretContract,
+        lp_token: &SecretContract,
+        amount: Uint128,
+        recipient: &HumanAddr,
+    ) -> StdResult<CosmosMsg>;
+}
+
+pub fn dex_adapter<Q: Querier>(dex: &Dex) -> Box<dyn DexAdapter<Q>> {
+    match dex {
+        Dex::SecretSwap => Box::new(SecretSwapAdapter),
+        Dex::SiennaSwap => Box::new(SiennaSwapAdapter),
+    }
+}
+
+// A trade pair's reserves of its SNIP-20 tokens and the LP it has minted
+#[derive(Clone, Debug, PartialEq)]
+pub struct PairReserves {
+    pub reserves: Vec<(HumanAddr, Uint128)>,
+    pub total_share: Uint128,
+}
+impl PairReserves {
+    pub fn reserve(&self, token: &HumanAddr) -> StdResult<Uint128> {
+        self.reserves
+            .iter()
+            .find(|(address, _)| address == token)
+            .map(|(_, amount)| *amount)
+            .ok_or_else(|| StdError::generic_err("Trade pair reserve not found."))
+    }
+}
+
+pub struct SecretSwapAdapter;
+impl<Q: Querier> DexAdapter<Q> for SecretSwapAdapter {
+    fn commission_rate(&self) -> Decimal {
+        Decimal::permille(3)
+    }
+
+    // The trade pair queries with its own viewing keys, so the contract's keys aren't shared with it
+    fn provide_liquidity_msg(
+        &self,
+        trade_pair: &SecretContract,
+        assets: [(&SecretContract, Uint128); 2],
+    ) -> StdResult<CosmosMsg> {
+        let [first_asset, second_asset] = assets;
+        let asset = |(token, amount): (&SecretContract, Uint128)| Asset {
+            amount,
+            info: AssetInfo::Token {
+                contract_addr: token.address.clone(),
+                token_code_hash: token.contract_hash.clone(),
+                viewing_key: "SecretSwap".to_string(),
+            },
+        };
+        SecretSwapHandleMsg::ProvideLiquidity {
+            assets: [asset(first_asset), asset(second_asset)],
+            slippage_tolerance: None,
+        }
+        .to_cosmos_msg(
+            trade_pair.contract_hash.clone(),
+            trade_pair.address.clone(),
+            None,
+        )
+    }
+
+    fn query_reserves(&self, querier: &Q, trade_pair: &SecretContract) -> StdResult<PairReserves> {
+        let pool_response: PoolResponse = SecretSwapQueryMsg::Pool {}.query(
+            querier,
+            trade_pair.contract_hash.clone(),
+            trade_pair.address.clone(),
+        )?;
+        let mut reserves: Vec<(HumanAddr, Uint128)> = vec![];
+        for asset in pool_response.assets.iter() {
+            if let AssetInfo::Token { contract_addr, .. } = &asset.info {
+                reserves.push((contract_addr.clone(), asset.amount));
+            }
+        }
+
+        Ok(PairReserves {
+            reserves,
+            total_share: pool_response.total_share,
+        })
+    }
+
+    fn swap_msg(
+        &self,
+        trade_pair: &SecretContract,
+        offer_token: &SecretContract,
+        amount: Uint128,
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        recipient: Option<&HumanAddr>,
+    ) -> StdResult<CosmosMsg> {
+        snip20::send_msg(
+            trade_pair.address.clone(),
+            amount,
+            Some(to_binary(&SecretSwapReceiveMsg::Swap {
+                expected_return: None,
+                belief_price,
+                max_spread,
+                to: recipient.cloned(),
+            })?),
+            None,
+            BLOCK_SIZE,
+            offer_token.contract_hash.clone(),
+            offer_token.address.clone(),
+        )
+    }
+
+    // The trade pair always returns the tokens to the sender of the LP
+    fn withdraw_liquidity_msg(
+        &self,
+        trade_pair: &SecretContract,
+        lp_token: &SecretContract,
+        amount: Uint128,
+        _recipient: &HumanAddr,
+    ) -> StdResult<CosmosMsg> {
+        snip20::send_msg(
+            trade_pair.address.clone(),
+            amount,
+            Some(to_binary(&SecretSwapReceiveMsg::WithdrawLiquidity {})?),
+            None,
+            BLOCK_SIZE,
+            lp_token.contract_hash.clone(),
+            lp_token.address.clone(),
+        )
+    }
+}
+
+pub struct SiennaSwapAdapter;
+impl<Q: Querier> DexAdapter<Q> for SiennaSwapAdapter {
+    fn commission_rate(&self) -> Decimal {
+        Decimal::permille(3)
+    }
+
+    fn provide_liquidity_msg(
+        &self,
+        trade_pair: &SecretContract,
+        assets: [(&SecretContract, Uint128); 2],
+    ) -> StdResult<CosmosMsg> {
+        let [(token_0, amount_0), (token_1, amount_1)] = assets;
+        SiennaSwapHandleMsg::AddLiquidity {
+            deposit: TokenPairAmount {
+                pair: TokenPair(custom_token(token_0), custom_token(token_1)),
+                amount_0,
+                amount_1,
+            },
+            slippage_tolerance: None,
+        }
+        .to_cosmos_msg(
+            trade_pair.contract_hash.clone(),
+            trade_pair.address.clone(),
+            None,
+        )
+    }
+
+    fn query_reserves(&self, querier: &Q, trade_pair: &SecretContract) -> StdResult<PairReserves> {
+        let SiennaSwapQueryResponse::PairInfo {
+            pair,
+            amount_0,
+            amount_1,
+            total_liquidity,
+        } = SiennaSwapQueryMsg::PairInfo.query(
+            querier,
+            trade_pair.contract_hash.clone(),
+            trade_pair.address.clone(),
+        )?;
+        let mut reserves: Vec<(HumanAddr, Uint128)> = vec![];
+        for (token, amount) in [(pair.0, amount_0), (pair.1, amount_1)].iter() {
+            if let TokenType::CustomToken { contract_addr, .. } = token {
+                reserves.push((contract_addr.clone(), *amount));
+            }
+        }
+
+        Ok(PairReserves {
+            reserves,
+            total_share: total_liquidity,
+        })
+    }
+
+    // Sienna only guards swaps with a minimum return,
+    // so it's worked out from the belief price, allowing a 1% spread by default
+    fn swap_msg(
+        &self,
+        trade_pair: &SecretContract,
+        offer_token: &SecretContract,
+        amount: Uint128,
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        recipient: Option<&HumanAddr>,
+    ) -> StdResult<CosmosMsg> {
+        let expected_return: Option<Uint128> = match belief_price {
+            Some(belief_price) => Some(minimum_return_amount(
+                amount,
+                belief_price,
+                max_spread.unwrap_or_else(|| Decimal::percent(1)),
+            )?),
+            None => None,
+        };
+        snip20::send_msg(
+            trade_pair.address.clone(),
+            amount,
+            Some(to_binary(&SiennaSwapReceiveMsg::Swap {
+                expected_return,
+                to: recipient.cloned(),
+            })?),
+            None,
+            BLOCK_SIZE,
+            offer_token.contract_hash.clone(),
+            offer_token.address.clone(),
+        )
+    }
+
+    fn withdraw_liquidity_msg(
+        &self,
+        trade_pair: &SecretContract,
+        lp_token: &SecretContract,
+        amount: Uint128,
+        recipient: &HumanAddr,
+    ) -> StdResult<CosmosMsg> {
+        snip20::send_msg(
+            trade_pair.address.clone(),
+            amount,
+            Some(to_binary(&SiennaSwapReceiveMsg::RemoveLiquidity {
+                recipient: recipient.clone(),
+            })?),
+            None,
+            BLOCK_SIZE,
+            lp_token.contract_hash.clone(),
+            lp_token.address.clone(),
+        )
+    }
+}
+
+fn custom_token(token: &SecretContract) -> TokenType {
+    TokenType::CustomToken {
+        contract_addr: token.address.clone(),
+        token_code_hash: token.contract_hash.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockQuerier;
+    use cosmwasm_std::{
+        from_slice, Binary, Empty, QuerierResult, QueryRequest, SystemError, WasmMsg, WasmQuery,
+    };
+    use std::str::FromStr;
+
+    // Answers the trade pair's reserve query if it's in the expected format
+    struct MockPairQuerier {
+        expected_msg: &'static str,
+        response: &'static str,
+    }
+    impl Querier for MockPairQuerier {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let request: QueryRequest<Empty> = from_slice(bin_request).unwrap();
+            match request {
+                QueryRequest::Wasm(WasmQuery::Smart { msg, .. }) => {
+                    assert_eq!(
+                        String::from_utf8(msg.0).unwrap().trim_end(),
+                        self.expected_msg
+                    );
+                    Ok(Ok(Binary::from(self.response.as_bytes())))
+                }
+                _ => Err(SystemError::UnsupportedRequest {
+                    kind: "non-wasm query".to_string(),
+                }),
+            }
+        }
+    }
+
+    fn mock_token(name: &str) -> SecretContract {
+        SecretContract {
+            address: HumanAddr::from(name),
+            contract_hash: format!("{}-hash", name),
+        }
+    }
+
+    // The JSON of a handle message, without the padding
+    fn execute_msg_json(cosmos_msg: CosmosMsg) -> String {
+        match cosmos_msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => {
+                String::from_utf8(msg.0).unwrap().trim_end().to_string()
+            }
+            _ => panic!("Not a wasm execute message."),
+        }
+    }
+
+    #[test]
+    fn test_pair_reserves() {
+        let pair_reserves = PairReserves {
+            reserves: vec![(HumanAddr::from("butt"), Uint128(5))],
+            total_share: Uint128(1),
+        };
+        // = when the trade pair holds the token
+        // = * it returns the reserve
+        assert_eq!(
+            pair_reserves.reserve(&HumanAddr::from("butt")).unwrap(),
+            Uint128(5)
+        );
+        // = when the trade pair doesn't hold the token
+        // = * it raises an error
+        assert_eq!(
+            pair_reserves
+                .reserve(&HumanAddr::from("swbtc"))
+                .unwrap_err(),
+            StdError::generic_err("Trade pair reserve not found.")
+        );
+    }
+
+    #[test]
+    fn test_secret_swap_adapter_msg_format() {
+        let adapter: Box<dyn DexAdapter<MockQuerier>> = dex_adapter(&Dex::SecretSwap);
+        let (pair, butt, swbtc) = (mock_token("pair"), mock_token("butt"), mock_token("swbtc"));
+
+        // = when swapping
+        // = * it sends the offer token with the swap hook, returning to the recipient
+        assert_eq!(
+            adapter
+                .swap_msg(
+                    &pair,
+                    &butt,
+                    Uint128(5),
+                    Some(Decimal::percent(50)),
+                    None,
+                    Some(&HumanAddr::from("gary"))
+                )
+                .unwrap(),
+            snip20::send_msg(
+                pair.address.clone(),
+                Uint128(5),
+                Some(Binary::from(
+                    r#"{"swap":{"expected_return":null,"belief_price":"0.5","max_spread":null,"to":"gary"}}"#
+                        .as_bytes()
+                )),
+                None,
+                BLOCK_SIZE,
+                butt.contract_hash.clone(),
+                butt.address.clone(),
+            )
+            .unwrap()
+        );
+
+        // = when providing liquidity
+        // = * it sends the assets in the given order
+        assert_eq!(
+            execute_msg_json(
+                adapter
+                    .provide_liquidity_msg(&pair, [(&swbtc, Uint128(1)), (&butt, Uint128(2))])
+                    .unwrap()
+            ),
+            r#"{"provide_liquidity":{"assets":[{"info":{"token":{"contract_addr":"swbtc","token_code_hash":"swbtc-hash","viewing_key":"SecretSwap"}},"amount":"1"},{"info":{"token":{"contract_addr":"butt","token_code_hash":"butt-hash","viewing_key":"SecretSwap"}},"amount":"2"}],"slippage_tolerance":null}}"#
+        );
+
+        // = when withdrawing liquidity
+        // = * it sends the LP with the withdrawal hook
+        let lp = mock_token("lp");
+        assert_eq!(
+            adapter
+                .withdraw_liquidity_msg(&pair, &lp, Uint128(5), &HumanAddr::from("zapper"))
+                .unwrap(),
+            snip20::send_msg(
+                pair.address.clone(),
+                Uint128(5),
+                Some(Binary::from(r#"{"withdraw_liquidity":{}}"#.as_bytes())),
+                None,
+                BLOCK_SIZE,
+                lp.contract_hash,
+                lp.address,
+            )
+            .unwrap()
+        );
+
+        // = when querying the reserves
+        // = * it reads the token reserves from the pool query
+        let querier = MockPairQuerier {
+            expected_msg: r#"{"pool":{}}"#,
+            response: r#"{"assets":[{"info":{"token":{"contract_addr":"butt","token_code_hash":"butt-hash","viewing_key":""}},"amount":"3"},{"info":{"native_token":{"denom":"uscrt"}},"amount":"4"}],"total_share":"2"}"#,
+        };
+        assert_eq!(
+            SecretSwapAdapter.query_reserves(&querier, &pair).unwrap(),
+            PairReserves {
+                reserves: vec![(HumanAddr::from("butt"), Uint128(3))],
+                total_share: Uint128(2),
+            }
+        );
+    }
+
+    #[test]
+    fn test_sienna_swap_adapter_msg_format() {
+        let adapter: Box<dyn DexAdapter<MockQuerier>> = dex_adapter(&Dex::SiennaSwap);
+        let (pair, butt, swbtc) = (mock_token("pair"), mock_token("butt"), mock_token("swbtc"));
+
+        // = when swapping without a belief price
+        // = * it sends the offer token with the swap hook and no minimum return
+        assert_eq!(
+            adapter
+                .swap_msg(&pair, &butt, Uint128(1_000), None, None, None)
+                .unwrap(),
+            snip20::send_msg(
+                pair.address.clone(),
+                Uint128(1_000),
+                Some(Binary::from(
+                    r#"{"swap":{"expected_return":null,"to":null}}"#.as_bytes()
+                )),
+                None,
+                BLOCK_SIZE,
+                butt.contract_hash.clone(),
+                butt.address.clone(),
+            )
+            .unwrap()
+        );
+        // = when swapping with a belief price
+        // = * it sets the minimum return at the belief price less the max spread
+        assert_eq!(
+            adapter
+                .swap_msg(
+                    &pair,
+                    &butt,
+                    Uint128(1_000),
+                    Some(Decimal::percent(50)),
+                    None,
+                    None
+                )
+                .unwrap(),
+            snip20::send_msg(
+                pair.address.clone(),
+                Uint128(1_000),
+                Some(Binary::from(
+                    r#"{"swap":{"expected_return":"1980","to":null}}"#.as_bytes()
+                )),
+                None,
+                BLOCK_SIZE,
+                butt.contract_hash.clone(),
+                butt.address.clone(),
+            )
+            .unwrap()
+        );
+
+        // = when swapping with a belief price in the hundreds of thousands
+        // = * it sets the minimum return without overflowing
+        assert_eq!(
+            adapter
+                .swap_msg(
+                    &pair,
+                    &butt,
+                    Uint128(1_000_000_000),
+                    Some(Decimal::from_str("100000").unwrap()),
+                    None,
+                    None
+                )
+                .unwrap(),
+            snip20::send_msg(
+                pair.address.clone(),
+                Uint128(1_000_000_000),
+                Some(Binary::from(
+                    r#"{"swap":{"expected_return":"9900","to":null}}"#.as_bytes()
+                )),
+                None,
+                BLOCK_SIZE,
+                butt.contract_hash.clone(),
+                butt.address.clone(),
+            )
+            .unwrap()
+        );
+
+        // = when providing liquidity
+        // = * it deposits the pair's token amounts
+        assert_eq!(
+            execute_msg_json(
+                adapter
+                    .provide_liquidity_msg(&pair, [(&swbtc, Uint128(1)), (&butt, Uint128(2))])
+                    .unwrap()
+            ),
+            r#"{"add_liquidity":{"deposit":{"pair":[{"custom_token":{"contract_addr":"swbtc","token_code_hash":"swbtc-hash"}},{"custom_token":{"contract_addr":"butt","token_code_hash":"butt-hash"}}],"amount_0":"1","amount_1":"2"},"slippage_tolerance":null}}"#
+        );
+
+        // = when withdrawing liquidity
+        // = * it sends the LP with the removal hook naming the recipient
+        let lp = mock_token("lp");
+        assert_eq!(
+            adapter
+                .withdraw_liquidity_msg(&pair, &lp, Uint128(5), &HumanAddr::from("zapper"))
+                .unwrap(),
+            snip20::send_msg(
+                pair.address.clone(),
+                Uint128(5),
+                Some(Binary::from(
+                    r#"{"remove_liquidity":{"recipient":"zapper"}}"#.as_bytes()
+                )),
+                None,
+                BLOCK_SIZE,
+                lp.contract_hash,
+                lp.address,
+            )
+            .unwrap()
+        );
+
+        // = when querying the reserves
+        // = * it reads the token reserves from the pair info
+        let querier = MockPairQuerier {
+            expected_msg: r#""pair_info""#,
+            response: r#"{"pair_info":{"liquidity_token":{"address":"lp","code_hash":"lp-hash"},"pair":[{"custom_token":{"contract_addr":"butt","token_code_hash":"butt-hash"}},{"custom_token":{"contract_addr":"swbtc","token_code_hash":"swbtc-hash"}}],"amount_0":"3","amount_1":"4","total_liquidity":"2","contract_version":1}}"#,
+        };
+        assert_eq!(
+            SiennaSwapAdapter.query_reserves(&querier, &pair).unwrap(),
+            PairReserves {
+                reserves: vec![
+                    (HumanAddr::from("butt"), Uint128(3)),
+                    (HumanAddr::from("swbtc"), Uint128(4))
+                ],
+                total_share: Uint128(2),
+            }
+        );
+    }
+}
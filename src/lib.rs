@@ -1,5 +1,7 @@
 mod constants;
 pub mod contract;
+mod dex;
+mod math;
 pub mod msg;
 pub mod state;
 mod validations;
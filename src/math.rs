@@ -0,0 +1,722 @@
+use cosmwasm_std::{Decimal, StdError, StdResult, Uint128};
+use primitive_types::U256;
+use std::str::FromStr;
+
+const DECIMAL_FRACTIONAL: u128 = 1_000_000_000_000_000_000;
+// Curve gives up on its invariant after this many rounds of Newton's method
+const STABLE_SWAP_ITERATIONS: usize = 255;
+
+// Price of the base token in the quote token at a pool's reserves of them
+pub fn price(base_reserve: Uint128, quote_reserve: Uint128) -> StdResult<Decimal> {
+    if base_reserve.is_zero() {
+        return Err(StdError::generic_err("Trade pair has no liquidity."));
+    }
+
+    let atomics: U256 = U256::from(quote_reserve.u128()) * U256::from(DECIMAL_FRACTIONAL)
+        / U256::from(base_reserve.u128());
+    if atomics > U256::from(u128::MAX) {
+        return Err(StdError::generic_err("Amount is too large."));
+    }
+    // Decimal::from_ratio would overflow on prices above a few hundred, so it's built from its digits
+    let atomics: u128 = atomics.as_u128();
+    Decimal::from_str(&format!(
+        "{}.{:018}",
+        atomics / DECIMAL_FRACTIONAL,
+        atomics % DECIMAL_FRACTIONAL
+    ))
+}
+
+// Price impact of offering offer_amount into a constant product pool holding offer_pool
+// of the offered token: 1 - (execution price / spot price) = offer_amount / (offer_pool + offer_amount)
+pub fn price_impact(offer_amount: Uint128, offer_pool: Uint128) -> Decimal {
+    if offer_amount.is_zero() {
+        return Decimal::zero();
+    }
+
+    let numerator: U256 = U256::from(offer_amount.u128()) * U256::from(DECIMAL_FRACTIONAL);
+    let denominator: U256 = U256::from(offer_pool.u128()) + U256::from(offer_amount.u128());
+    Decimal::from_ratio((numerator / denominator).as_u128(), DECIMAL_FRACTIONAL)
+}
+
+// LP minted by a constant product pool for providing amounts of its tokens
+// at reserves with total_share LP in circulation, limited by the scarcer token
+pub fn lp_amount_for_liquidity(
+    amounts: [Uint128; 2],
+    reserves: [Uint128; 2],
+    total_share: Uint128,
+) -> StdResult<Uint128> {
+    if total_share.is_zero() || reserves[0].is_zero() || reserves[1].is_zero() {
+        return Err(StdError::generic_err("Trade pair has no liquidity."));
+    }
+
+    let first_lp_amount: Uint128 = multiply_ratio(amounts[0], total_share, reserves[0])?;
+    let second_lp_amount: Uint128 = multiply_ratio(amounts[1], total_share, reserves[1])?;
+    Ok(std::cmp::min(first_lp_amount, second_lp_amount))
+}
+
+// Tokens a trade pair returns for withdrawing lp_amount: each reserve times the LP's share of total_share
+pub fn liquidity_for_lp_amount(
+    lp_amount: Uint128,
+    reserves: [Uint128; 2],
+    total_share: Uint128,
+) -> StdResult<[Uint128; 2]> {
+    if total_share.is_zero() {
+        return Err(StdError::generic_err("Trade pair has no liquidity."));
+    }
+
+    Ok([
+        multiply_ratio(reserves[0], lp_amount, total_share)?,
+        multiply_ratio(reserves[1], lp_amount, total_share)?,
+    ])
+}
+
+// LP a vault share is redeemed for: shares * total_lp / total_shares
+pub fn lp_amount_for_shares(
+    share_amount: Uint128,
+    total_shares: Uint128,
+    total_lp: Uint128,
+) -> StdResult<Uint128> {
+    if total_shares.is_zero() {
+        return Ok(Uint128(0));
+    }
+
+    multiply_ratio(share_amount, total_lp, total_shares)
+}
+
+// Vault shares minted for depositing lp_amount, at the current share price.
+// The first deposit sets the price at one share per LP.
+pub fn share_amount_for_lp(
+    lp_amount: Uint128,
+    total_shares: Uint128,
+    total_lp: Uint128,
+) -> StdResult<Uint128> {
+    if total_shares.is_zero() || total_lp.is_zero() {
+        return Ok(lp_amount);
+    }
+
+    multiply_ratio(lp_amount, total_shares, total_lp)
+}
+
+// Least amount of the ask token to accept for offer_amount at belief_price,
+// in offer token per ask token, allowing for max_spread
+pub fn minimum_return_amount(
+    offer_amount: Uint128,
+    belief_price: Decimal,
+    max_spread: Decimal,
+) -> StdResult<Uint128> {
+    let belief_price_atomics: U256 = decimal_atomics(belief_price)?;
+    if belief_price_atomics.is_zero() {
+        return Err(StdError::generic_err(
+            "Belief price must be greater than zero.",
+        ));
+    }
+
+    let expected_return: U256 =
+        U256::from(offer_amount.u128()) * U256::from(DECIMAL_FRACTIONAL) / belief_price_atomics;
+    if expected_return > U256::from(u128::MAX) {
+        return Err(StdError::generic_err("Amount is too large."));
+    }
+    let spread: U256 =
+        expected_return * decimal_atomics(max_spread)? / U256::from(DECIMAL_FRACTIONAL);
+    Ok(Uint128(expected_return.saturating_sub(spread).as_u128()))
+}
+
+// Decimal doesn't expose its atomics and scaling it up by its fractional overflows on prices
+// above a few hundred, so they're read back from its digits
+fn decimal_atomics(decimal: Decimal) -> StdResult<U256> {
+    let digits: String = decimal.to_string();
+    let mut parts = digits.splitn(2, '.');
+    let whole: u128 = parts
+        .next()
+        .unwrap_or("0")
+        .parse()
+        .map_err(|_| StdError::generic_err("Decimal is malformed."))?;
+    let fractional: u128 = match parts.next() {
+        Some(fractional) => format!("{:0<18}", fractional)
+            .parse()
+            .map_err(|_| StdError::generic_err("Decimal is malformed."))?,
+        None => 0,
+    };
+
+    Ok(U256::from(whole) * U256::from(DECIMAL_FRACTIONAL) + U256::from(fractional))
+}
+
+// Price impact of offering offer_amount into a stable swap pool: 1 - (execution price / spot price),
+// with the spot price taken from offering a thousandth of the offer pool
+pub fn stable_swap_price_impact(
+    offer_amount: Uint128,
+    offer_pool: Uint128,
+    ask_pool: Uint128,
+    amplification: u64,
+) -> StdResult<Decimal> {
+    if offer_amount.is_zero() {
+        return Ok(Decimal::zero());
+    }
+
+    let spot_offer_amount: Uint128 = Uint128(std::cmp::max(offer_pool.u128() / 1_000, 1));
+    let spot_return_amount: Uint128 =
+        stable_swap_output(spot_offer_amount, offer_pool, ask_pool, amplification)?;
+    if spot_return_amount.is_zero() {
+        return Ok(Decimal::one());
+    }
+    let return_amount: Uint128 =
+        stable_swap_output(offer_amount, offer_pool, ask_pool, amplification)?;
+    let spot_rate: U256 = U256::from(spot_return_amount.u128()) * U256::from(DECIMAL_FRACTIONAL)
+        / U256::from(spot_offer_amount.u128());
+    let execution_rate: U256 = U256::from(return_amount.u128()) * U256::from(DECIMAL_FRACTIONAL)
+        / U256::from(offer_amount.u128());
+    if execution_rate >= spot_rate {
+        return Ok(Decimal::zero());
+    }
+
+    let impact: U256 = (spot_rate - execution_rate) * U256::from(DECIMAL_FRACTIONAL) / spot_rate;
+    Ok(Decimal::from_ratio(impact.as_u128(), DECIMAL_FRACTIONAL))
+}
+
+// Amount of the ask token a stable swap pool returns for offer_amount, after its commission
+pub fn stable_swap_return_amount(
+    offer_amount: Uint128,
+    offer_pool: Uint128,
+    ask_pool: Uint128,
+    amplification: u64,
+    commission_rate: Decimal,
+) -> StdResult<Uint128> {
+    let return_amount: Uint128 =
+        stable_swap_output(offer_amount, offer_pool, ask_pool, amplification)?;
+    return_amount - return_amount * commission_rate
+}
+
+// Amount of amount to swap so that what's left and what the swap returns match the ratio of
+// a stable swap pool's reserves after the swap. The return isn't linear in the amount swapped,
+// so the split is bisected for: swapping more leaves less and returns more.
+pub fn stable_swap_split(
+    amount: Uint128,
+    offer_pool: Uint128,
+    ask_pool: Uint128,
+    amplification: u64,
+    commission_rate: Decimal,
+) -> StdResult<Uint128> {
+    if offer_pool.is_zero() || ask_pool.is_zero() {
+        return Err(StdError::generic_err("Trade pair has no liquidity."));
+    }
+
+    let mut low: u128 = 0;
+    let mut high: u128 = amount.u128();
+    while high - low > 1 {
+        let middle: u128 = low + (high - low) / 2;
+        let return_amount: Uint128 = stable_swap_return_amount(
+            Uint128(middle),
+            offer_pool,
+            ask_pool,
+            amplification,
+            commission_rate,
+        )?;
+        let left: U256 = checked_mul(
+            U256::from(amount.u128() - middle),
+            U256::from(ask_pool.u128().saturating_sub(return_amount.u128())),
+        )?;
+        let right: U256 = checked_mul(
+            U256::from(return_amount.u128()),
+            U256::from(offer_pool.u128()) + U256::from(middle),
+        )?;
+        if left > right {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    Ok(Uint128(low))
+}
+
+// Amount of the ask token a constant product pool returns for offer_amount, after its commission
+pub fn swap_return_amount(
+    offer_amount: Uint128,
+    offer_pool: Uint128,
+    ask_pool: Uint128,
+    commission_rate: Decimal,
+) -> StdResult<Uint128> {
+    let offer_pool_after_swap: Uint128 = Uint128(
+        offer_pool
+            .u128()
+            .checked_add(offer_amount.u128())
+            .ok_or_else(|| StdError::generic_err("Amount is too large."))?,
+    );
+    if offer_pool_after_swap.is_zero() {
+        return Ok(Uint128(0));
+    }
+
+    let return_amount: Uint128 = multiply_ratio(ask_pool, offer_amount, offer_pool_after_swap)?;
+    return_amount - return_amount * commission_rate
+}
+
+// Amount of the ask token a two token stable swap pool returns for offer_amount before its commission.
+// The pool keeps the Curve invariant A * n^n * sum(x) + D = A * D * n^n + D^(n + 1) / (n^n * prod(x)),
+// so the new ask pool is solved for with the offer pool raised by offer_amount and D unchanged.
+// Assumes both tokens have the same decimals.
+fn stable_swap_output(
+    offer_amount: Uint128,
+    offer_pool: Uint128,
+    ask_pool: Uint128,
+    amplification: u64,
+) -> StdResult<Uint128> {
+    if offer_pool.is_zero() || ask_pool.is_zero() {
+        return Err(StdError::generic_err("Trade pair has no liquidity."));
+    }
+    if amplification == 0 {
+        return Err(StdError::generic_err(
+            "Amplification must be greater than zero.",
+        ));
+    }
+
+    let n: U256 = U256::from(2);
+    // Curve folds n^n into A as A * n, leaving one factor of n in the formulas below
+    let ann: U256 = U256::from(amplification) * n;
+    let x: U256 = U256::from(offer_pool.u128());
+    let y: U256 = U256::from(ask_pool.u128());
+    let sum: U256 = x + y;
+
+    // Newton's method for D
+    let mut d: U256 = sum;
+    let mut converged: bool = false;
+    for _ in 0..STABLE_SWAP_ITERATIONS {
+        let d_product: U256 = checked_mul(checked_mul(d, d)? / (x * n), d)? / (y * n);
+        let previous_d: U256 = d;
+        d = checked_mul(checked_mul(ann, sum)? + d_product * n, d)?
+            / ((ann - 1) * d + (n + 1) * d_product);
+        if within_one(d, previous_d) {
+            converged = true;
+            break;
+        }
+    }
+    if !converged {
+        return Err(StdError::generic_err(
+            "Stable swap invariant didn't converge.",
+        ));
+    }
+
+    // Newton's method for the ask pool given the new offer pool
+    let new_x: U256 = x
+        .checked_add(U256::from(offer_amount.u128()))
+        .ok_or_else(|| StdError::generic_err("Amount is too large."))?;
+    let c: U256 = checked_mul(checked_mul(d, d)? / (new_x * n), d)? / (ann * n);
+    let b: U256 = new_x + d / ann;
+    let mut new_y: U256 = d;
+    converged = false;
+    for _ in 0..STABLE_SWAP_ITERATIONS {
+        let previous_y: U256 = new_y;
+        let denominator: U256 = (new_y * n + b)
+            .checked_sub(d)
+            .ok_or_else(|| StdError::generic_err("Stable swap invariant didn't converge."))?;
+        new_y = (checked_mul(new_y, new_y)? + c) / denominator;
+        if within_one(new_y, previous_y) {
+            converged = true;
+            break;
+        }
+    }
+    if !converged {
+        return Err(StdError::generic_err(
+            "Stable swap invariant didn't converge.",
+        ));
+    }
+
+    if new_y >= y {
+        return Ok(Uint128(0));
+    }
+    Ok(Uint128((y - new_y).as_u128()))
+}
+
+fn checked_mul(a: U256, b: U256) -> StdResult<U256> {
+    a.checked_mul(b)
+        .ok_or_else(|| StdError::generic_err("Amount is too large."))
+}
+
+fn within_one(a: U256, b: U256) -> bool {
+    if a > b {
+        a - b <= U256::one()
+    } else {
+        b - a <= U256::one()
+    }
+}
+
+// amount * numerator / denominator, rounded down, without overflowing in between
+fn multiply_ratio(amount: Uint128, numerator: Uint128, denominator: Uint128) -> StdResult<Uint128> {
+    let result: U256 =
+        U256::from(amount.u128()) * U256::from(numerator.u128()) / U256::from(denominator.u128());
+    if result > U256::from(u128::MAX) {
+        return Err(StdError::generic_err("Amount is too large."));
+    }
+
+    Ok(Uint128(result.as_u128()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_liquidity_for_lp_amount() {
+        // = when the trade pair has no liquidity
+        // = * it raises an error
+        assert_eq!(
+            liquidity_for_lp_amount(Uint128(5), [Uint128(0), Uint128(0)], Uint128(0)).unwrap_err(),
+            StdError::generic_err("Trade pair has no liquidity.")
+        );
+        // = when the trade pair has liquidity
+        // = * it returns the LP's share of each reserve, rounded down
+        assert_eq!(
+            liquidity_for_lp_amount(Uint128(1), [Uint128(10), Uint128(20)], Uint128(3)).unwrap(),
+            [Uint128(3), Uint128(6)]
+        );
+        // = * it doesn't overflow with large amounts
+        assert_eq!(
+            liquidity_for_lp_amount(
+                Uint128(u128::MAX / 2),
+                [Uint128(u128::MAX), Uint128(2)],
+                Uint128(u128::MAX / 2)
+            )
+            .unwrap(),
+            [Uint128(u128::MAX), Uint128(2)]
+        );
+    }
+
+    #[test]
+    fn test_lp_amount_for_shares() {
+        // = when there are no shares
+        // = * it returns zero
+        assert_eq!(
+            lp_amount_for_shares(Uint128(5), Uint128(0), Uint128(0)).unwrap(),
+            Uint128(0)
+        );
+        // = when the vault has compounded
+        // = * it returns the share of the vault's LP, rounded down
+        assert_eq!(
+            lp_amount_for_shares(Uint128(1), Uint128(3), Uint128(10)).unwrap(),
+            Uint128(3)
+        );
+        // = * it doesn't overflow with large amounts
+        assert_eq!(
+            lp_amount_for_shares(
+                Uint128(u128::MAX / 2),
+                Uint128(u128::MAX / 2),
+                Uint128(u128::MAX)
+            )
+            .unwrap(),
+            Uint128(u128::MAX)
+        );
+    }
+
+    #[test]
+    fn test_lp_amount_for_liquidity() {
+        // = when the trade pair has no liquidity
+        // = * it raises an error
+        assert_eq!(
+            lp_amount_for_liquidity(
+                [Uint128(5), Uint128(5)],
+                [Uint128(0), Uint128(0)],
+                Uint128(0)
+            )
+            .unwrap_err(),
+            StdError::generic_err("Trade pair has no liquidity.")
+        );
+        // = when the amounts match the ratio of the reserves
+        // = * it returns the same share of the LP
+        assert_eq!(
+            lp_amount_for_liquidity(
+                [Uint128(10), Uint128(20)],
+                [Uint128(100), Uint128(200)],
+                Uint128(50)
+            )
+            .unwrap(),
+            Uint128(5)
+        );
+        // = when one of the amounts is scarcer than the reserves
+        // = * it returns the LP for the scarcer token
+        assert_eq!(
+            lp_amount_for_liquidity(
+                [Uint128(10), Uint128(10)],
+                [Uint128(100), Uint128(200)],
+                Uint128(50)
+            )
+            .unwrap(),
+            Uint128(2)
+        );
+    }
+
+    #[test]
+    fn test_minimum_return_amount() {
+        // = when the belief price is zero
+        // = * it raises an error
+        assert_eq!(
+            minimum_return_amount(Uint128(5), Decimal::zero(), Decimal::zero()).unwrap_err(),
+            StdError::generic_err("Belief price must be greater than zero.")
+        );
+        // = when there is no spread
+        // = * it returns the offer amount at the belief price
+        assert_eq!(
+            minimum_return_amount(Uint128(1_000), Decimal::percent(50), Decimal::zero()).unwrap(),
+            Uint128(2_000)
+        );
+        // = when there is a spread
+        // = * it takes the spread off the expected return
+        assert_eq!(
+            minimum_return_amount(Uint128(1_000), Decimal::percent(50), Decimal::percent(1))
+                .unwrap(),
+            Uint128(1_980)
+        );
+        // = when the belief price is above what scaling a Decimal up by its fractional holds
+        // = * it returns the offer amount at the belief price
+        assert_eq!(
+            minimum_return_amount(
+                Uint128(1_000_000_000),
+                Decimal::from_str("100000").unwrap(),
+                Decimal::zero()
+            )
+            .unwrap(),
+            Uint128(10_000)
+        );
+    }
+
+    #[test]
+    fn test_price() {
+        // = when the pool has none of the base token
+        // = * it raises an error
+        assert_eq!(
+            price(Uint128(0), Uint128(5)).unwrap_err(),
+            StdError::generic_err("Trade pair has no liquidity.")
+        );
+        // = when the pool has both tokens
+        // = * it returns the quote reserve per base reserve
+        assert_eq!(price(Uint128(4), Uint128(1)).unwrap(), Decimal::percent(25));
+        // = * it doesn't overflow with large reserves
+        assert_eq!(
+            price(Uint128(u128::MAX), Uint128(u128::MAX)).unwrap(),
+            Decimal::one()
+        );
+        assert_eq!(
+            price(Uint128(1), Uint128(1_000_000)).unwrap(),
+            Decimal::from_str("1000000").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_price_impact() {
+        // = when offer amount is zero
+        // = * it returns zero
+        assert_eq!(price_impact(Uint128(0), Uint128(0)), Decimal::zero());
+        // = when offer pool is empty
+        // = * it returns one
+        assert_eq!(price_impact(Uint128(5), Uint128(0)), Decimal::one());
+        // = when offer amount is a third of the offer pool
+        // = * it returns a quarter
+        assert_eq!(price_impact(Uint128(1), Uint128(3)), Decimal::percent(25));
+        // = when amounts are too large for 128 bit intermediate math
+        // = * it returns the impact without overflowing
+        assert_eq!(
+            price_impact(Uint128(u128::MAX / 2), Uint128(u128::MAX / 2)),
+            Decimal::percent(50)
+        );
+    }
+
+    #[test]
+    fn test_stable_swap_price_impact() {
+        // = when offer amount is zero
+        // = * it returns zero
+        assert_eq!(
+            stable_swap_price_impact(Uint128(0), Uint128(0), Uint128(0), 100).unwrap(),
+            Decimal::zero()
+        );
+        // = when the pool has no liquidity
+        // = * it raises an error
+        assert_eq!(
+            stable_swap_price_impact(Uint128(5), Uint128(0), Uint128(0), 100).unwrap_err(),
+            StdError::generic_err("Trade pair has no liquidity.")
+        );
+        // = when offer amount is small next to the balanced pool
+        // = * it returns zero
+        assert_eq!(
+            stable_swap_price_impact(Uint128(1_000), Uint128(1_000_000), Uint128(1_000_000), 100)
+                .unwrap(),
+            Decimal::zero()
+        );
+        // = when offer amount is half of the offer pool
+        // = * it returns far less than a constant product pool would
+        let impact: Decimal = stable_swap_price_impact(
+            Uint128(500_000),
+            Uint128(1_000_000),
+            Uint128(1_000_000),
+            100,
+        )
+        .unwrap();
+        assert_eq!(impact, Decimal::from_str("0.006494").unwrap());
+        assert!(impact < price_impact(Uint128(500_000), Uint128(1_000_000)));
+    }
+
+    #[test]
+    fn test_stable_swap_return_amount() {
+        // = when the amplification is zero
+        // = * it raises an error
+        assert_eq!(
+            stable_swap_return_amount(
+                Uint128(5),
+                Uint128(1_000),
+                Uint128(1_000),
+                0,
+                Decimal::zero()
+            )
+            .unwrap_err(),
+            StdError::generic_err("Amplification must be greater than zero.")
+        );
+        // = when offer amount is small next to the balanced pool
+        // = * it returns about the offer amount less the commission
+        assert_eq!(
+            stable_swap_return_amount(
+                Uint128(1_000),
+                Uint128(1_000_000),
+                Uint128(1_000_000),
+                100,
+                Decimal::permille(3)
+            )
+            .unwrap(),
+            Uint128(997)
+        );
+        // = when offer amount is half of the offer pool
+        // = * it returns more with a higher amplification
+        assert_eq!(
+            stable_swap_return_amount(
+                Uint128(500_000),
+                Uint128(1_000_000),
+                Uint128(1_000_000),
+                100,
+                Decimal::zero()
+            )
+            .unwrap(),
+            Uint128(496_753)
+        );
+        assert_eq!(
+            stable_swap_return_amount(
+                Uint128(500_000),
+                Uint128(1_000_000),
+                Uint128(1_000_000),
+                1,
+                Decimal::zero()
+            )
+            .unwrap(),
+            Uint128(396_088)
+        );
+        // = * it returns more than a constant product pool would
+        assert!(
+            Uint128(396_088)
+                > swap_return_amount(
+                    Uint128(500_000),
+                    Uint128(1_000_000),
+                    Uint128(1_000_000),
+                    Decimal::zero()
+                )
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_stable_swap_split() {
+        // = when the pool has no liquidity
+        // = * it raises an error
+        assert_eq!(
+            stable_swap_split(Uint128(5), Uint128(0), Uint128(0), 100, Decimal::zero())
+                .unwrap_err(),
+            StdError::generic_err("Trade pair has no liquidity.")
+        );
+        // = when the pool is balanced
+        // = * it swaps about half
+        assert_eq!(
+            stable_swap_split(
+                Uint128(1_000),
+                Uint128(1_000_000),
+                Uint128(1_000_000),
+                100,
+                Decimal::zero()
+            )
+            .unwrap(),
+            Uint128(499)
+        );
+        // = when the pool holds more of the ask token
+        // = * it swaps less than the share of the ask token, as the offer token is worth more
+        let amount_to_swap: Uint128 = stable_swap_split(
+            Uint128(1_000),
+            Uint128(1_000_000),
+            Uint128(3_000_000),
+            10,
+            Decimal::zero(),
+        )
+        .unwrap();
+        assert_eq!(amount_to_swap, Uint128(721));
+        // = * what's left and what the swap returns match the reserves after the swap
+        let return_amount: Uint128 = stable_swap_return_amount(
+            amount_to_swap,
+            Uint128(1_000_000),
+            Uint128(3_000_000),
+            10,
+            Decimal::zero(),
+        )
+        .unwrap();
+        let left_ratio: u128 = (1_000 - amount_to_swap.u128()) * 1_000_000 / return_amount.u128();
+        let reserve_ratio: u128 =
+            (1_000_000 + amount_to_swap.u128()) * 1_000_000 / (3_000_000 - return_amount.u128());
+        assert!((left_ratio as i128 - reserve_ratio as i128).abs() < 2_000);
+    }
+
+    #[test]
+    fn test_swap_return_amount() {
+        // = when the pools are empty
+        // = * it returns zero
+        assert_eq!(
+            swap_return_amount(Uint128(0), Uint128(0), Uint128(0), Decimal::permille(3)).unwrap(),
+            Uint128(0)
+        );
+        // = when offer amount is a third of the offer pool
+        // = * it returns a quarter of the ask pool less the commission
+        assert_eq!(
+            swap_return_amount(
+                Uint128(1_000),
+                Uint128(3_000),
+                Uint128(4_000),
+                Decimal::permille(3)
+            )
+            .unwrap(),
+            Uint128(997)
+        );
+        // = * it doesn't overflow with large amounts
+        assert_eq!(
+            swap_return_amount(
+                Uint128(u128::MAX / 2),
+                Uint128(u128::MAX / 2),
+                Uint128(u128::MAX),
+                Decimal::zero()
+            )
+            .unwrap(),
+            Uint128(u128::MAX / 2)
+        );
+    }
+
+    #[test]
+    fn test_share_amount_for_lp() {
+        // = when the vault is empty
+        // = * it mints one share per LP
+        assert_eq!(
+            share_amount_for_lp(Uint128(5), Uint128(0), Uint128(0)).unwrap(),
+            Uint128(5)
+        );
+        // = when the vault has compounded
+        // = * it mints shares at the current share price, rounded down
+        assert_eq!(
+            share_amount_for_lp(Uint128(5), Uint128(10), Uint128(20)).unwrap(),
+            Uint128(2)
+        );
+        // = when the shares would overflow
+        // = * it raises an error
+        assert_eq!(
+            share_amount_for_lp(Uint128(u128::MAX), Uint128(u128::MAX), Uint128(1)).unwrap_err(),
+            StdError::generic_err("Amount is too large.")
+        );
+    }
+}
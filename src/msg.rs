@@ -1,8 +1,11 @@
 use crate::constants::BLOCK_SIZE;
-use crate::state::SecretContract;
+use crate::state::{
+    Callback, ConfigPublic, Deadline, DepositTarget, Dex, Invariant, Pool, PriceTrigger,
+    RegisteredToken, SecretContract, Vault,
+};
 use cosmwasm_std::{Binary, Decimal, HumanAddr, Uint128};
 use schemars::JsonSchema;
-use secret_toolkit::utils::HandleCallback;
+use secret_toolkit::utils::{HandleCallback, Query};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -13,15 +16,67 @@ pub struct InitMsg {
     pub butt_swbtc_trade_pair: SecretContract,
     pub butt_swbtc_lp: SecretContract,
     pub viewing_key: String,
+    pub max_price_impact: Decimal,
+    pub hide_amounts: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum HandleMsg {
-    IncreaseAllowanceForPairContract {},
+    AddPool {
+        trade_pair: SecretContract,
+        tokens: [SecretContract; 2],
+        lp_token: SecretContract,
+        deposit_target: Option<DepositTarget>,
+        dex: Option<Dex>,
+        invariant: Option<Invariant>,
+    },
+    AddSwapRouters {
+        routers: Vec<HumanAddr>,
+    },
+    CancelExitOrder {
+        exit_order_id: u64,
+    },
+    CancelOrder {
+        order_id: u64,
+    },
+    CancelSubscription {
+        subscription_id: u64,
+    },
+    CompoundHarvestedRewards {
+        harvester: HumanAddr,
+        butt_balance: Uint128,
+    },
+    ExecuteExitOrder {
+        exit_order_id: u64,
+    },
+    ExecuteExitOrders {
+        exit_order_ids: Vec<u64>,
+    },
+    ExecuteOrder {
+        order_id: u64,
+    },
+    ExecuteOrders {
+        order_ids: Vec<u64>,
+    },
+    FinishMigration {},
+    Harvest {},
+    IncreaseAllowanceForPairContract {
+        pool_id: u32,
+    },
+    MigrateState {},
+    ProcessSubscriptions {
+        subscription_ids: Vec<u64>,
+    },
+    RecordVaultRewards {
+        butt_balance: Uint128,
+    },
     RegisterTokens {
         tokens: Vec<SecretContract>,
     },
+    RemoveSwapRouters {
+        routers: Vec<HumanAddr>,
+    },
     Receive {
         sender: HumanAddr,
         from: HumanAddr,
@@ -33,24 +88,404 @@ pub enum HandleMsg {
         denom: Option<String>,
         token: Option<SecretContract>,
     },
+    RotateViewingKey {
+        entropy: String,
+    },
+    RouteMigratedTokens {},
     SendLpToUserThenDepositIntoFarmContract {},
+    SetDepositTarget {
+        pool_id: u32,
+        deposit_target: DepositTarget,
+    },
+    SetSuccessor {
+        contract: SecretContract,
+    },
+    SetVault {
+        share_token: SecretContract,
+        harvest_bounty: Decimal,
+        harvest_interval: u64,
+    },
+    SetViewingKey {
+        key: String,
+    },
+    UpdateConfig {
+        max_price_impact: Option<Decimal>,
+        hide_amounts: Option<bool>,
+    },
+    UpdateVault {
+        harvest_bounty: Option<Decimal>,
+        harvest_interval: Option<u64>,
+    },
+    ZapInstallment {
+        subscription_id: u64,
+        amount: Uint128,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HandleAnswer {
+    AddPool {
+        pool_id: u32,
+    },
+    AddSwapRouters {
+        status: ResponseStatus,
+    },
+    CancelExitOrder {
+        status: ResponseStatus,
+    },
+    CancelOrder {
+        status: ResponseStatus,
+    },
+    CancelSubscription {
+        status: ResponseStatus,
+    },
+    CompoundHarvestedRewards {
+        session_id: Option<u64>,
+        reward_amount: Uint128,
+        bounty_amount: Uint128,
+    },
+    ExecuteExitOrder {
+        butt_amount: Uint128,
+        swbtc_amount: Uint128,
+    },
+    ExecuteExitOrders {
+        executed_exit_order_ids: Vec<u64>,
+        expired_exit_order_ids: Vec<u64>,
+    },
+    ExecuteOrders {
+        executed_order_ids: Vec<u64>,
+        expired_order_ids: Vec<u64>,
+    },
+    FinishMigration {
+        swbtc_amount: Uint128,
+    },
+    Harvest {
+        status: ResponseStatus,
+    },
+    IncreaseAllowanceForPairContract {
+        status: ResponseStatus,
+    },
+    InitSwapAndProvide {
+        session_id: u64,
+    },
+    MigrateLiquidity {
+        session_id: u64,
+    },
+    MigrateState {
+        storage_version: u32,
+    },
+    PlaceExitOrder {
+        exit_order_id: u64,
+    },
+    PlaceOrder {
+        order_id: u64,
+    },
+    ProcessSubscriptions {
+        processed_subscription_ids: Vec<u64>,
+    },
+    ProvideLiquidityToTradePair {
+        butt_amount: Uint128,
+        swbtc_amount: Uint128,
+    },
+    Receive {
+        swbtc_amount: Uint128,
+    },
+    RecordVaultRewards {
+        reward_amount: Uint128,
+    },
+    RegisterTokens {
+        status: ResponseStatus,
+    },
+    RemoveSwapRouters {
+        status: ResponseStatus,
+    },
+    RescueTokens {
+        status: ResponseStatus,
+    },
+    RotateViewingKey {
+        status: ResponseStatus,
+    },
+    RouteMigratedTokens {
+        withdrawn_amounts: [Uint128; 2],
+    },
+    SendLpToUserThenDepositIntoFarmContract {
+        session_id: u64,
+        recipient: HumanAddr,
+        lp_amount: Uint128,
+        share_amount: Option<Uint128>,
+    },
+    SetDepositTarget {
+        status: ResponseStatus,
+    },
+    SetSuccessor {
+        status: ResponseStatus,
+    },
+    SetVault {
+        status: ResponseStatus,
+    },
+    SetViewingKey {
+        status: ResponseStatus,
+    },
+    Subscribe {
+        subscription_id: u64,
+    },
+    SwapHalfOfIntermediateToken {
+        amount_to_swap: Uint128,
+        amount_to_provide: Uint128,
+    },
+    TopUpSubscription {
+        balance: Uint128,
+    },
+    UpdateConfig {
+        status: ResponseStatus,
+    },
+    UpdateVault {
+        status: ResponseStatus,
+    },
+    WithdrawFromVault {
+        lp_amount: Uint128,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     Config {},
+    ContractInfo {},
+    ExitOrders {
+        address: HumanAddr,
+        key: String,
+        page: u32,
+        page_size: u32,
+    },
+    ExportState {},
+    Orders {
+        address: HumanAddr,
+        key: String,
+        page: u32,
+        page_size: u32,
+    },
+    PairAllowances {},
+    Pools {},
+    RegisteredTokens {
+        page: u32,
+        page_size: u32,
+    },
+    SimulateZap {
+        pool_id: u32,
+        intermediate_token: Option<HumanAddr>,
+        amount: Uint128,
+    },
+    Subscriptions {
+        address: HumanAddr,
+        key: String,
+        page: u32,
+        page_size: u32,
+    },
+    SwapRouters {},
+    Vault {},
+    ZapHistory {
+        address: HumanAddr,
+        key: String,
+        page: u32,
+        page_size: u32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AllowanceStatus {
+    Missing,
+    Insufficient,
+    Sufficient,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatus {
+    Operational,
+    Zapping,
+    Deprecated,
+    MigrationRequired,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractInfoResponse {
+    pub version: String,
+    pub storage_version: u32,
+    pub features: Vec<String>,
+    pub pools: Vec<Pool>,
+    pub status: ContractStatus,
+}
+
+// What a successor needs to carry on from this contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExportedState {
+    pub config: ConfigPublic,
+    pub zaps_count: u64,
+    pub pools: Vec<Pool>,
+    pub registered_tokens: Vec<RegisteredToken>,
+    pub swap_routers: Vec<HumanAddr>,
+}
+
+// The allowance this contract has given a pool's trade pair for one of its tokens
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PairAllowance {
+    pub pool_id: u32,
+    pub token: HumanAddr,
+    pub spender: HumanAddr,
+    pub allowance: Uint128,
+    pub status: AllowanceStatus,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseStatus {
+    Success,
+}
+
+// The expected result of zapping amount of the intermediate token into a pool at its current reserves
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateZapResponse {
+    pub amount_to_swap: Uint128,
+    pub amount_to_provide: Uint128,
+    pub return_amount: Uint128,
+    pub price_impact: Decimal,
+    pub lp_amount: Uint128,
+}
+
+#[allow(clippy::large_enum_variant)]
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ReceiveMsg {
-    InitSwapAndProvide {
-        first_token_contract_hash: String,
-        swap_to_swbtc_contract: Option<SecretContract>,
-        swap_to_swbtc_msg: Option<Binary>,
+    InitSwapAndProvide(ZapRequest),
+    MigrateLiquidity(MigrationRequest),
+    PlaceExitOrder(ExitOrderRequest),
+    PlaceOrder(OrderRequest),
+    Subscribe(SubscriptionRequest),
+    TopUpSubscription { subscription_id: u64 },
+    WithdrawFromVault {},
+}
+
+// Escrows the sent BUTT-SWBTC LP or vault shares until the BUTT-SWBTC price triggers the order,
+// then exits them to SWBTC. The swap of the withdrawn BUTT is bound by belief_price and max_spread.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExitOrderRequest {
+    pub target_price: Decimal,
+    pub trigger: PriceTrigger,
+    pub belief_price: Option<Decimal>,
+    pub max_spread: Option<Decimal>,
+    pub expiry: Deadline,
+}
+
+// Sent with the LP token of a registered pool to move its liquidity into the BUTT-SWBTC farm.
+// Each of the pool's tokens other than SWBTC is sent to the swap_to_swbtc_contract
+// with its swap_to_swbtc_msgs entry, in the order of the pool's tokens.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrationRequest {
+    pub swap_to_swbtc_contract: SecretContract,
+    pub swap_to_swbtc_msgs: [Option<Binary>; 2],
+    pub belief_price: Option<Decimal>,
+    pub max_spread: Option<Decimal>,
+    pub max_price_impact: Option<Decimal>,
+    pub deadline: Option<Deadline>,
+    pub recipient: Option<HumanAddr>,
+    pub hide_amounts: Option<bool>,
+}
+
+// Escrows the sent token until the BUTT-SWBTC price triggers the order, then zaps it with zap_request.
+// The zap's deadline applies to its execution, while the expiry is when the order is refunded.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrderRequest {
+    pub target_price: Decimal,
+    pub trigger: PriceTrigger,
+    pub expiry: Deadline,
+    pub zap_request: ZapRequest,
+}
+
+// Escrows the sent token as the budget of a subscription zapping amount_per_period with zap_request
+// every period seconds, starting right away. The budget must be amount_per_period times periods.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SubscriptionRequest {
+    pub amount_per_period: Uint128,
+    pub periods: u32,
+    pub period: u64,
+    pub zap_request: ZapRequest,
+}
+
+// The swap_to_swbtc_* fields route the first token to the intermediate token,
+// which is the second token of the pool unless the first is given as the intermediate_token.
+// The pool defaults to the BUTT-SWBTC pool 0, which lets claimed BUTT rewards be zapped into any pool.
+// The LP goes to the recipient, which defaults to the sender of the first token, and is deposited
+// into the pool's deposit target unless the zap sets its own.
+// With vault set, the contract keeps the farm position and mints vault shares to the recipient instead.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ZapRequest {
+    pub first_token_contract_hash: String,
+    pub swap_to_swbtc_contract: Option<SecretContract>,
+    pub swap_to_swbtc_msg: Option<Binary>,
+    pub belief_price: Option<Decimal>,
+    pub max_spread: Option<Decimal>,
+    pub intermediate_token: Option<HumanAddr>,
+    pub max_price_impact: Option<Decimal>,
+    pub deadline: Option<Deadline>,
+    pub recipient: Option<HumanAddr>,
+    pub callback: Option<Callback>,
+    pub hide_amounts: Option<bool>,
+    pub vault: Option<bool>,
+    pub pool_id: Option<u32>,
+    pub deposit_target: Option<DepositTarget>,
+}
+
+// The vault with the LP each share is currently redeemed for
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VaultResponse {
+    pub vault: Vault,
+    pub share_price: Decimal,
+}
+
+// === Callback Contract ===
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CallbackHandleMsg {
+    ZapCompleted {
+        user: HumanAddr,
+        lp_amount: Uint128,
+        butt_provided: Uint128,
+        swbtc_provided: Uint128,
+        session_id: u64,
+        msg: Binary,
     },
 }
+impl HandleCallback for CallbackHandleMsg {
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+}
+
+// === Farm Contract ===
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FarmReceiveMsg {
+    DepositIncentivizedToken {},
+}
+
+// The farm pays out pending rewards whenever a position changes,
+// so withdrawing nothing claims the rewards
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FarmHandleMsg {
+    Withdraw { incentivized_token_amount: Uint128 },
+}
+impl HandleCallback for FarmHandleMsg {
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+}
+
+// === Yield Optimizer Contract ===
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum YieldOptimizerReceiveMsg {
+    DepositIncentivizedToken {},
+}
 
 // === Secret Swap Pair Contract ===
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -67,6 +502,15 @@ pub enum AssetInfo {
         token_code_hash: String,
         viewing_key: String,
     },
+    NativeToken {
+        denom: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PoolResponse {
+    pub assets: [Asset; 2],
+    pub total_share: Uint128,
 }
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
@@ -79,3 +523,201 @@ pub enum SecretSwapHandleMsg {
 impl HandleCallback for SecretSwapHandleMsg {
     const BLOCK_SIZE: usize = BLOCK_SIZE;
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretSwapQueryMsg {
+    Pool {},
+}
+impl Query for SecretSwapQueryMsg {
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretSwapReceiveMsg {
+    Swap {
+        expected_return: Option<Uint128>,
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        to: Option<HumanAddr>,
+    },
+    // The trade pair transfers the withdrawn tokens back, which doesn't call Receive
+    WithdrawLiquidity {},
+}
+
+// === Sienna Swap Pair Contract ===
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SiennaSwapHandleMsg {
+    AddLiquidity {
+        deposit: TokenPairAmount,
+        slippage_tolerance: Option<Decimal>,
+    },
+}
+impl HandleCallback for SiennaSwapHandleMsg {
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SiennaSwapQueryMsg {
+    PairInfo,
+}
+impl Query for SiennaSwapQueryMsg {
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+}
+
+// Only the fields of the pair info this contract reads
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SiennaSwapQueryResponse {
+    PairInfo {
+        pair: TokenPair,
+        amount_0: Uint128,
+        amount_1: Uint128,
+        total_liquidity: Uint128,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SiennaSwapReceiveMsg {
+    Swap {
+        expected_return: Option<Uint128>,
+        to: Option<HumanAddr>,
+    },
+    RemoveLiquidity {
+        recipient: HumanAddr,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenPair(pub TokenType, pub TokenType);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenPairAmount {
+    pub pair: TokenPair,
+    pub amount_0: Uint128,
+    pub amount_1: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    CustomToken {
+        contract_addr: HumanAddr,
+        token_code_hash: String,
+    },
+    NativeToken {
+        denom: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::to_binary;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_callback_handle_msg_format() {
+        // * it serializes the result of the zap with the callback's msg
+        assert_eq!(
+            to_binary(&CallbackHandleMsg::ZapCompleted {
+                user: HumanAddr::from("gary"),
+                lp_amount: Uint128(3),
+                butt_provided: Uint128(2),
+                swbtc_provided: Uint128(1),
+                session_id: 4,
+                msg: Binary::from(r#"{"compound":{}}"#.as_bytes()),
+            })
+            .unwrap(),
+            Binary::from(
+                r#"{"zap_completed":{"user":"gary","lp_amount":"3","butt_provided":"2","swbtc_provided":"1","session_id":4,"msg":"eyJjb21wb3VuZCI6e319"}}"#
+                    .as_bytes()
+            )
+        );
+    }
+
+    #[test]
+    fn test_farm_handle_msg_format() {
+        // * it serializes the withdrawal expected by the farm contract
+        assert_eq!(
+            to_binary(&FarmHandleMsg::Withdraw {
+                incentivized_token_amount: Uint128(5)
+            })
+            .unwrap(),
+            Binary::from(r#"{"withdraw":{"incentivized_token_amount":"5"}}"#.as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_farm_receive_msg_format() {
+        // * it serializes the deposit hook expected by the farm contract
+        assert_eq!(
+            to_binary(&FarmReceiveMsg::DepositIncentivizedToken {}).unwrap(),
+            Binary::from(r#"{"deposit_incentivized_token":{}}"#.as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_secret_swap_query_msg_format() {
+        // * it serializes the pool query expected by the trade pair contract
+        assert_eq!(
+            to_binary(&SecretSwapQueryMsg::Pool {}).unwrap(),
+            Binary::from(r#"{"pool":{}}"#.as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_secret_swap_receive_msg_format() {
+        // = when no swap parameters are set
+        // = * it serializes the swap hook with null parameters
+        let mut msg = SecretSwapReceiveMsg::Swap {
+            expected_return: None,
+            belief_price: None,
+            max_spread: None,
+            to: None,
+        };
+        assert_eq!(
+            to_binary(&msg).unwrap(),
+            Binary::from(
+                r#"{"swap":{"expected_return":null,"belief_price":null,"max_spread":null,"to":null}}"#
+                    .as_bytes()
+            )
+        );
+
+        // = when all swap parameters are set
+        // = * it serializes amounts and decimals as strings
+        msg = SecretSwapReceiveMsg::Swap {
+            expected_return: Some(Uint128(5)),
+            belief_price: Some(Decimal::from_str("0.5").unwrap()),
+            max_spread: Some(Decimal::percent(1)),
+            to: Some(HumanAddr::from("gary")),
+        };
+        assert_eq!(
+            to_binary(&msg).unwrap(),
+            Binary::from(
+                r#"{"swap":{"expected_return":"5","belief_price":"0.5","max_spread":"0.01","to":"gary"}}"#
+                    .as_bytes()
+            )
+        );
+
+        // = when withdrawing liquidity
+        // = * it serializes the withdrawal hook
+        assert_eq!(
+            to_binary(&SecretSwapReceiveMsg::WithdrawLiquidity {}).unwrap(),
+            Binary::from(r#"{"withdraw_liquidity":{}}"#.as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_yield_optimizer_receive_msg_format() {
+        // * it serializes the deposit hook expected by the yield optimizer contract
+        assert_eq!(
+            to_binary(&YieldOptimizerReceiveMsg::DepositIncentivizedToken {}).unwrap(),
+            Binary::from(r#"{"deposit_incentivized_token":{}}"#.as_bytes())
+        );
+    }
+}
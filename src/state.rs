@@ -1,23 +1,27 @@
-use cosmwasm_std::{HumanAddr, StdResult, Uint128};
+use crate::msg::ZapRequest;
+use cosmwasm_std::{Binary, BlockInfo, Decimal, HumanAddr, StdResult, Uint128};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+// Settings that only change through admin handles
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
     pub admin: HumanAddr,
-    pub current_user: Option<HumanAddr>,
+    pub contract_address: HumanAddr,
     pub butt: SecretContract,
     pub swbtc: SecretContract,
     pub butt_swbtc_farm_pool: SecretContract,
     pub butt_swbtc_trade_pair: SecretContract,
     pub butt_swbtc_lp: SecretContract,
-    pub swap_to_swbtc_contract_address: Option<HumanAddr>,
-    pub butt_amount_to_provide: Option<Uint128>,
-    pub swbtc_amount_to_provide: Option<Uint128>,
+    pub max_price_impact: Decimal,
+    pub hide_amounts: bool,
     pub viewing_key: String,
 }
 impl Config {
-    pub fn with_public_attributes(self) -> StdResult<ConfigPublic> {
+    pub fn with_public_attributes(
+        self,
+        successor: Option<SecretContract>,
+    ) -> StdResult<ConfigPublic> {
         Ok(ConfigPublic {
             admin: self.admin,
             butt: self.butt,
@@ -25,10 +29,56 @@ impl Config {
             butt_swbtc_farm_pool: self.butt_swbtc_farm_pool,
             butt_swbtc_trade_pair: self.butt_swbtc_trade_pair,
             butt_swbtc_lp: self.butt_swbtc_lp,
+            max_price_impact: self.max_price_impact,
+            hide_amounts: self.hide_amounts,
+            successor,
         })
     }
 }
 
+// The single config blob of storage version 1, only read when migrating
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ConfigV1 {
+    pub admin: HumanAddr,
+    pub current_user: Option<HumanAddr>,
+    pub butt: SecretContract,
+    pub swbtc: SecretContract,
+    pub butt_swbtc_farm_pool: SecretContract,
+    pub butt_swbtc_trade_pair: SecretContract,
+    pub butt_swbtc_lp: SecretContract,
+    pub swap_to_swbtc_contract_address: Option<HumanAddr>,
+    pub butt_amount_to_provide: Option<Uint128>,
+    pub swbtc_amount_to_provide: Option<Uint128>,
+    pub viewing_key: String,
+}
+
+// A pool of storage version 2, only read when migrating
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PoolV2 {
+    pub id: u32,
+    pub trade_pair: SecretContract,
+    pub tokens: [SecretContract; 2],
+    pub lp_token: SecretContract,
+}
+
+// The session of storage version 2, only read when migrating
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SessionV2 {
+    pub id: u64,
+    pub current_user: Option<HumanAddr>,
+    pub recipient: Option<HumanAddr>,
+    pub swap_to_swbtc_contract_address: Option<HumanAddr>,
+    pub intermediate_token: Option<HumanAddr>,
+    pub butt_amount_to_provide: Option<Uint128>,
+    pub swbtc_amount_to_provide: Option<Uint128>,
+    pub belief_price: Option<Decimal>,
+    pub max_spread: Option<Decimal>,
+    pub max_price_impact: Option<Decimal>,
+    pub deadline: Option<Deadline>,
+    pub callback: Option<Callback>,
+    pub hide_amounts: bool,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ConfigPublic {
     pub admin: HumanAddr,
@@ -37,6 +87,214 @@ pub struct ConfigPublic {
     pub butt_swbtc_farm_pool: SecretContract,
     pub butt_swbtc_trade_pair: SecretContract,
     pub butt_swbtc_lp: SecretContract,
+    pub max_price_impact: Decimal,
+    pub hide_amounts: bool,
+    pub successor: Option<SecretContract>,
+}
+
+// The zap in progress, kept apart from the config so each step of a zap only rewrites this
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Session {
+    pub id: u64,
+    pub pool_id: u32,
+    pub current_user: Option<HumanAddr>,
+    pub recipient: Option<HumanAddr>,
+    pub swap_to_swbtc_contract_address: Option<HumanAddr>,
+    pub intermediate_token: Option<HumanAddr>,
+    // Amounts of the pool's tokens to provide, in the order of the pool's tokens
+    pub amounts_to_provide: [Option<Uint128>; 2],
+    // The contract's LP balance before liquidity is provided, as it also holds LP escrowed for exit orders
+    pub lp_balance: Option<Uint128>,
+    pub belief_price: Option<Decimal>,
+    pub max_spread: Option<Decimal>,
+    pub max_price_impact: Option<Decimal>,
+    pub deadline: Option<Deadline>,
+    pub callback: Option<Callback>,
+    pub hide_amounts: bool,
+    pub vault: bool,
+    pub compounding: bool,
+    pub migration: Option<Migration>,
+    pub deposit_target: Option<DepositTarget>,
+}
+impl Session {
+    // The pool token a zap is routed through before half of it is swapped
+    pub fn intermediate_token(&self, pool: &Pool) -> SecretContract {
+        if self.intermediate_token == Some(pool.tokens[0].address.clone()) {
+            pool.tokens[0].clone()
+        } else {
+            pool.tokens[1].clone()
+        }
+    }
+
+    // Position of the intermediate token among the pool's tokens
+    pub fn intermediate_token_index(&self, pool: &Pool) -> usize {
+        if self.intermediate_token(pool) == pool.tokens[0] {
+            0
+        } else {
+            1
+        }
+    }
+
+    // The pool token received from swapping half of the intermediate token
+    pub fn paired_token(&self, pool: &Pool) -> SecretContract {
+        if self.intermediate_token(pool) == pool.tokens[0] {
+            pool.tokens[1].clone()
+        } else {
+            pool.tokens[0].clone()
+        }
+    }
+
+    // The lower of the admin's maximum price impact and the one set for the zap
+    pub fn effective_max_price_impact(&self, config: &Config) -> Decimal {
+        match self.max_price_impact {
+            Some(max_price_impact) if max_price_impact < config.max_price_impact => {
+                max_price_impact
+            }
+            _ => config.max_price_impact,
+        }
+    }
+
+    // Amounts are kept out of the public logs when the admin or the zap asks for it
+    pub fn hides_amounts(&self, config: &Config) -> bool {
+        config.hide_amounts || self.hide_amounts
+    }
+
+    // Ends the zap, keeping the id of the last one
+    pub fn clear(&mut self) {
+        *self = Session {
+            id: self.id,
+            ..Session::default()
+        };
+    }
+}
+
+// A foreign pool's liquidity being routed to SWBTC before it's zapped into the BUTT-SWBTC farm.
+// Balances are the contract's before the liquidity was withdrawn, so only what the migration
+// withdraws and routes is zapped.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Migration {
+    pub pool_id: u32,
+    pub swap_to_swbtc_msgs: [Option<Binary>; 2],
+    pub token_balances: [Uint128; 2],
+    pub swbtc_balance: Uint128,
+}
+
+// Executed with the zap's result once the zap completes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Callback {
+    pub contract: HumanAddr,
+    pub code_hash: String,
+    pub msg: Binary,
+}
+
+// The DEX whose trade pair interface a pool's trade pair implements
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Dex {
+    SecretSwap,
+    SiennaSwap,
+}
+
+// The invariant a pool's trade pair prices its swaps by.
+// Stable swap pools hold correlated tokens, such as SWBTC and other wrapped BTC.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Invariant {
+    ConstantProduct,
+    StableSwap { amplification: u64 },
+}
+
+// Where the LP of a zap ends up. Contracts are deposited into on behalf of the recipient.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DepositTarget {
+    Farm(SecretContract),
+    YieldOptimizer(SecretContract),
+    Wallet,
+}
+
+// A zap reverts once the block passes its deadline
+#[derive(Serialize, Deserialize, Copy, Eq, PartialEq, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Deadline {
+    Height(u64),
+    Time(u64),
+}
+impl Deadline {
+    pub fn has_passed(&self, block: &BlockInfo) -> bool {
+        match self {
+            Deadline::Height(height) => block.height > *height,
+            Deadline::Time(time) => block.time > *time,
+        }
+    }
+}
+
+// A stop-loss or take-profit on BUTT-SWBTC liquidity held by the contract for the user,
+// either as BUTT-SWBTC LP or as vault shares escrowed by the order.
+// Once the BUTT-SWBTC price, in SWBTC per BUTT, reaches target_price anyone can execute it,
+// which withdraws the liquidity and swaps the BUTT to SWBTC within the user's slippage bounds.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExitOrder {
+    pub id: u64,
+    pub user: HumanAddr,
+    pub token: SecretContract,
+    pub amount: Uint128,
+    pub target_price: Decimal,
+    pub trigger: PriceTrigger,
+    pub belief_price: Option<Decimal>,
+    pub max_spread: Option<Decimal>,
+    pub expiry: Deadline,
+    pub status: OrderStatus,
+}
+impl ExitOrder {
+    pub fn is_triggered(&self, price: Decimal) -> bool {
+        self.trigger.is_met(price, self.target_price)
+    }
+}
+
+// A zap whose tokens are escrowed until the BUTT-SWBTC price, in SWBTC per BUTT, reaches target_price.
+// Anyone can execute it then, and it's refunded once cancelled by the user or past its expiry.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Order {
+    pub id: u64,
+    pub user: HumanAddr,
+    pub token: SecretContract,
+    pub amount: Uint128,
+    pub target_price: Decimal,
+    pub trigger: PriceTrigger,
+    pub expiry: Deadline,
+    pub zap_request: ZapRequest,
+    pub status: OrderStatus,
+}
+impl Order {
+    pub fn is_triggered(&self, price: Decimal) -> bool {
+        self.trigger.is_met(price, self.target_price)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    Pending,
+    Executed,
+    Cancelled,
+    Expired,
+}
+
+// Whether an order triggers at or above its target price, or at or below it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceTrigger {
+    Above,
+    Below,
+}
+impl PriceTrigger {
+    pub fn is_met(&self, price: Decimal, target_price: Decimal) -> bool {
+        match self {
+            PriceTrigger::Above => price >= target_price,
+            PriceTrigger::Below => price <= target_price,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, JsonSchema)]
@@ -44,3 +302,90 @@ pub struct SecretContract {
     pub address: HumanAddr,
     pub contract_hash: String,
 }
+
+// A budget of a token zapped with zap_request in installments of amount_per_period.
+// Anyone can process an installment once next_installment_time is reached, and the next one is due
+// period seconds after that. The user can top up the balance or cancel for a refund of it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Subscription {
+    pub id: u64,
+    pub user: HumanAddr,
+    pub token: SecretContract,
+    pub amount_per_period: Uint128,
+    pub period: u64,
+    pub balance: Uint128,
+    pub next_installment_time: u64,
+    pub zap_request: ZapRequest,
+    pub status: SubscriptionStatus,
+}
+impl Subscription {
+    pub fn is_due(&self, block: &BlockInfo) -> bool {
+        self.status == SubscriptionStatus::Active && block.time >= self.next_installment_time
+    }
+
+    // The last installment is whatever is left of the balance
+    pub fn installment_amount(&self) -> Uint128 {
+        if self.balance < self.amount_per_period {
+            self.balance
+        } else {
+            self.amount_per_period
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    Active,
+    Cancelled,
+    Completed,
+}
+
+// A trade pair whose tokens this contract provides as liquidity
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Pool {
+    pub id: u32,
+    pub trade_pair: SecretContract,
+    pub tokens: [SecretContract; 2],
+    pub lp_token: SecretContract,
+    pub deposit_target: DepositTarget,
+    pub dex: Dex,
+    pub invariant: Invariant,
+}
+
+// A token accepted as the input of a zap, with its SNIP-20 metadata
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RegisteredToken {
+    pub address: HumanAddr,
+    pub contract_hash: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+// The farm position held by the contract for its share holders.
+// Compounding adds LP without minting shares, which raises the LP each share is redeemed for.
+// Anyone can harvest once harvest_interval seconds have passed since the last harvest,
+// and is paid harvest_bounty of the rewards. The farm also pays out rewards whenever the vault
+// deposits or withdraws, which are kept in pending_rewards until the next harvest compounds them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Vault {
+    pub share_token: SecretContract,
+    pub total_shares: Uint128,
+    pub total_lp: Uint128,
+    pub harvest_bounty: Decimal,
+    pub harvest_interval: u64,
+    pub last_harvest_time: u64,
+    pub pending_rewards: Uint128,
+}
+
+// A completed zap, recorded in the history of both the user and the recipient
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Zap {
+    pub id: u64,
+    pub user: HumanAddr,
+    pub recipient: HumanAddr,
+    pub butt_amount: Uint128,
+    pub swbtc_amount: Uint128,
+    pub lp_amount: Uint128,
+    pub block_time: u64,
+}
@@ -1,4 +1,5 @@
-use cosmwasm_std::{HumanAddr, StdError, StdResult};
+use crate::state::{Deadline, DepositTarget, Invariant};
+use cosmwasm_std::{BlockInfo, Decimal, HumanAddr, StdError, StdResult};
 
 pub fn authorize(allowed: Vec<HumanAddr>, received: &HumanAddr) -> StdResult<()> {
     if !allowed.contains(received) {
@@ -7,3 +8,51 @@ pub fn authorize(allowed: Vec<HumanAddr>, received: &HumanAddr) -> StdResult<()>
 
     Ok(())
 }
+
+// Contracts are deposited into from the recipient's wallet with send_from,
+// which only the sender of a zap has allowed this contract to do
+pub fn validate_recipient(
+    recipient: Option<&HumanAddr>,
+    sender: &HumanAddr,
+    deposit_target: &DepositTarget,
+) -> StdResult<()> {
+    if let Some(recipient) = recipient {
+        if recipient != sender && *deposit_target != DepositTarget::Wallet {
+            return Err(StdError::generic_err(
+                "A recipient other than the sender can only receive the LP in their wallet.",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn validate_harvest_bounty(harvest_bounty: Decimal) -> StdResult<()> {
+    if harvest_bounty >= Decimal::one() {
+        return Err(StdError::generic_err(
+            "Harvest bounty must be less than 100%.",
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn validate_invariant(invariant: &Invariant) -> StdResult<()> {
+    if let Invariant::StableSwap { amplification: 0 } = invariant {
+        return Err(StdError::generic_err(
+            "Amplification must be greater than zero.",
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn validate_deadline(deadline: Option<Deadline>, block: &BlockInfo) -> StdResult<()> {
+    if let Some(deadline_unwrapped) = deadline {
+        if deadline_unwrapped.has_passed(block) {
+            return Err(StdError::generic_err("Zap deadline has passed."));
+        }
+    }
+
+    Ok(())
+}